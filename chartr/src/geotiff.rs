@@ -0,0 +1,746 @@
+//! GeoTIFF export of BSB/KAP charts, and import of the georeferencing of GeoTIFFs
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Seek, Write},
+    path::Path,
+};
+
+use anyhow::{anyhow, bail, Result};
+use libbsb::{
+    crs::Crs,
+    geodesy::Datum,
+    georef::{Affine, GeoTransform},
+    projection::{Projection, ProjectionMethod},
+    ColorPalette, KapImageFile,
+};
+use tiff::{
+    decoder::{ifd::Value, Decoder},
+    encoder::{
+        colortype::{self, ColorType},
+        DirectoryEncoder, TiffEncoder, TiffKind, TiffKindStandard, TiffValue,
+    },
+    tags::{PhotometricInterpretation, SampleFormat, Tag},
+    TiffResult,
+};
+use tracing::{debug, info};
+
+use crate::{
+    import::{
+        crs_from_epsg, datum_from_epsg, mercator_latitude_of_true_scale, ImageGeoreference,
+        PixelModel,
+    },
+    model_crs, ImageOptions,
+};
+
+/// Location of the values stored in the `GeoDoubleParamsTag`
+const DOUBLE_PARAMS: u16 = 34736;
+/// Location of the values stored in the `GeoAsciiParamsTag`
+const ASCII_PARAMS: u16 = 34737;
+/// Code of user-defined GeoTIFF values
+const USER_DEFINED: u16 = 32767;
+
+/// An 8-bit paletted image, the colors being stored in the `ColorMap` tag
+struct Palette8;
+
+impl ColorType for Palette8 {
+    type Inner = u8;
+    const TIFF_VALUE: PhotometricInterpretation = PhotometricInterpretation::RGBPalette;
+    const BITS_PER_SAMPLE: &'static [u16] = &[8];
+    const SAMPLE_FORMAT: &'static [SampleFormat] = &[SampleFormat::Uint];
+}
+
+/// The GeoKey directory and its associated parameters
+#[derive(Default)]
+struct GeoKeys {
+    keys: Vec<[u16; 4]>,
+    doubles: Vec<f64>,
+    ascii: String,
+}
+
+impl GeoKeys {
+    fn short(&mut self, key: u16, value: u16) {
+        self.keys.push([key, 0, 1, value]);
+    }
+
+    fn double(&mut self, key: u16, value: f64) {
+        self.keys
+            .push([key, DOUBLE_PARAMS, 1, self.doubles.len() as u16]);
+        self.doubles.push(value);
+    }
+
+    fn ascii(&mut self, key: u16, value: &str) {
+        // strings are terminated by a `|`, which is included in the count
+        let value = format!("{}|", value.replace('|', "/"));
+        self.keys.push([
+            key,
+            ASCII_PARAMS,
+            value.len() as u16,
+            self.ascii.len() as u16,
+        ]);
+        self.ascii.push_str(&value);
+    }
+
+    /// Returns the content of the `GeoKeyDirectoryTag`
+    fn directory(mut self) -> (Vec<u16>, Vec<f64>, String) {
+        self.keys.sort_by_key(|key| key[0]);
+        let mut directory = vec![1, 1, 0, self.keys.len() as u16];
+        directory.extend(self.keys.iter().flatten());
+        (directory, self.doubles, self.ascii)
+    }
+
+    /// Describes a coordinate reference system
+    fn crs(crs: &Crs) -> Result<Self> {
+        let mut keys = Self::default();
+        keys.short(1025, 1); // GTRasterTypeGeoKey: RasterPixelIsArea
+        keys.ascii(1026, &crs.name()); // GTCitationGeoKey
+        keys.short(2048, crs.datum.epsg()); // GeographicTypeGeoKey
+        keys.short(2054, 9102); // GeogAngularUnitsGeoKey: degree
+        let Some(projection) = &crs.projection else {
+            keys.short(1024, 2); // GTModelTypeGeoKey: ModelTypeGeographic
+            return Ok(keys);
+        };
+        keys.short(1024, 1); // GTModelTypeGeoKey: ModelTypeProjected
+        keys.short(3072, USER_DEFINED); // ProjectedCSTypeGeoKey
+        keys.ascii(3073, &crs.name()); // PCSCitationGeoKey
+        keys.short(3074, USER_DEFINED); // ProjectionGeoKey
+        keys.short(3076, 9001); // ProjLinearUnitsGeoKey: metre
+        keys.projection(projection)?;
+        Ok(keys)
+    }
+
+    /// Describes the parameters of a user-defined projection
+    fn projection(&mut self, projection: &Projection) -> Result<()> {
+        const COORD_TRANS: u16 = 3075;
+        const STD_PARALLEL_1: u16 = 3078;
+        const NAT_ORIGIN_LONG: u16 = 3080;
+        const NAT_ORIGIN_LAT: u16 = 3081;
+        const FALSE_EASTING: u16 = 3082;
+        const FALSE_NORTHING: u16 = 3083;
+
+        let (false_easting, false_northing) = match projection.method {
+            ProjectionMethod::Mercator {
+                central_meridian,
+                latitude_of_true_scale,
+            } => {
+                self.short(COORD_TRANS, 7); // CT_Mercator
+                self.double(STD_PARALLEL_1, latitude_of_true_scale);
+                self.double(NAT_ORIGIN_LONG, central_meridian);
+                (FALSE_EASTING, FALSE_NORTHING)
+            }
+            ProjectionMethod::TransverseMercator {
+                central_meridian,
+                latitude_of_origin,
+                scale_factor,
+            } => {
+                self.short(COORD_TRANS, 1); // CT_TransverseMercator
+                self.double(NAT_ORIGIN_LONG, central_meridian);
+                self.double(NAT_ORIGIN_LAT, latitude_of_origin);
+                self.double(3092, scale_factor); // ProjScaleAtNatOriginGeoKey
+                (FALSE_EASTING, FALSE_NORTHING)
+            }
+            ProjectionMethod::LambertConformalConic {
+                central_meridian,
+                latitude_of_origin,
+                standard_parallels: (first, second),
+            } => {
+                self.short(COORD_TRANS, 8); // CT_LambertConfConic_2SP
+                self.double(STD_PARALLEL_1, first);
+                self.double(3079, second); // ProjStdParallel2GeoKey
+                self.double(3084, central_meridian); // ProjFalseOriginLongGeoKey
+                self.double(3085, latitude_of_origin); // ProjFalseOriginLatGeoKey
+                (3086, 3087) // ProjFalseOriginEastingGeoKey, ProjFalseOriginNorthingGeoKey
+            }
+            ProjectionMethod::Polyconic {
+                central_meridian,
+                latitude_of_origin,
+            } => {
+                self.short(COORD_TRANS, 22); // CT_Polyconic
+                self.double(NAT_ORIGIN_LONG, central_meridian);
+                self.double(NAT_ORIGIN_LAT, latitude_of_origin);
+                (FALSE_EASTING, FALSE_NORTHING)
+            }
+            method => bail!("Unsupported projection {method:?}"),
+        };
+        self.double(false_easting, projection.false_easting);
+        self.double(false_northing, projection.false_northing);
+        Ok(())
+    }
+}
+
+impl GeoKeys {
+    /// Parses the content of the `GeoKeyDirectoryTag` and its associated parameters
+    fn parse(directory: &[u16], doubles: Vec<f64>, ascii: String) -> Result<Self> {
+        let Some(&[_, _, _, count]) = directory.get(..4) else {
+            bail!("Invalid GeoKey directory");
+        };
+        let keys = directory[4..]
+            .chunks_exact(4)
+            .take(usize::from(count))
+            .map(|key| [key[0], key[1], key[2], key[3]])
+            .collect();
+        Ok(Self {
+            keys,
+            doubles,
+            ascii,
+        })
+    }
+
+    fn get_short(&self, key: u16) -> Option<u16> {
+        self.keys
+            .iter()
+            .find(|k| k[0] == key && k[1] == 0)
+            .map(|k| k[3])
+    }
+
+    fn get_double(&self, key: u16) -> Option<f64> {
+        self.keys
+            .iter()
+            .find(|k| k[0] == key && k[1] == DOUBLE_PARAMS)
+            .and_then(|k| self.doubles.get(usize::from(k[3])).copied())
+    }
+
+    /// Returns the first of the double `keys` present
+    fn get_any_double(&self, keys: &[u16]) -> Option<f64> {
+        keys.iter().find_map(|&key| self.get_double(key))
+    }
+
+    /// Returns the geodetic datum of the geographic CRS
+    fn read_datum(&self) -> Result<Datum> {
+        let (key, code) = match self.get_short(2048) {
+            // GeogGeodeticDatumGeoKey
+            Some(USER_DEFINED) | None => (2050, self.get_short(2050)),
+            // GeographicTypeGeoKey
+            code => (2048, code),
+        };
+        code.and_then(datum_from_epsg)
+            .ok_or_else(|| anyhow!("Unsupported GeoTIFF datum (GeoKey {key}: {code:?})"))
+    }
+
+    /// Returns the coordinate reference system described by the keys
+    fn read_crs(&self) -> Result<Crs> {
+        match self.get_short(1024) {
+            Some(2) => return Ok(Crs::geographic(self.read_datum()?)),
+            Some(1) => {}
+            model => bail!("Unsupported GeoTIFF model type {model:?}"),
+        }
+        if let Some(units) = self.get_short(3076).filter(|&units| units != 9001) {
+            bail!("Unsupported GeoTIFF linear units {units}, only metres are supported");
+        }
+        match self.get_short(3072) {
+            Some(USER_DEFINED) | None => {}
+            Some(code) => {
+                return crs_from_epsg(code)
+                    .ok_or_else(|| anyhow!("Unsupported GeoTIFF projected CRS EPSG:{code}"))
+            }
+        }
+
+        let datum = self.read_datum()?;
+        let double = |keys: &[u16]| self.get_any_double(keys).unwrap_or_default();
+        let (method, false_origin) = match self.get_short(3075) {
+            // CT_TransverseMercator
+            Some(1) => (
+                ProjectionMethod::TransverseMercator {
+                    central_meridian: double(&[3080, 3088]),
+                    latitude_of_origin: double(&[3081, 3089]),
+                    scale_factor: self.get_any_double(&[3092, 3093]).unwrap_or(1.0),
+                },
+                [3082, 3083],
+            ),
+            // CT_Mercator
+            Some(7) => {
+                let latitude_of_true_scale =
+                    match (self.get_double(3078), self.get_any_double(&[3092, 3093])) {
+                        (Some(parallel), _) if parallel.abs() > 1e-9 => parallel,
+                        // ProjScaleAtNatOriginGeoKey
+                        (_, Some(scale_factor)) => {
+                            mercator_latitude_of_true_scale(&datum.ellipsoid(), scale_factor)?
+                        }
+                        _ => 0.0,
+                    };
+                (
+                    ProjectionMethod::Mercator {
+                        central_meridian: double(&[3080, 3088]),
+                        latitude_of_true_scale,
+                    },
+                    [3082, 3083],
+                )
+            }
+            // CT_LambertConfConic_2SP
+            Some(8) => (
+                ProjectionMethod::LambertConformalConic {
+                    central_meridian: double(&[3084, 3080]),
+                    latitude_of_origin: double(&[3085, 3081]),
+                    standard_parallels: (double(&[3078]), double(&[3079, 3078])),
+                },
+                [3086, 3087],
+            ),
+            // CT_LambertConfConic_1SP
+            Some(9) => {
+                let parallel = double(&[3081]);
+                (
+                    ProjectionMethod::LambertConformalConic {
+                        central_meridian: double(&[3080]),
+                        latitude_of_origin: parallel,
+                        standard_parallels: (parallel, parallel),
+                    },
+                    [3082, 3083],
+                )
+            }
+            // CT_Polyconic
+            Some(22) => (
+                ProjectionMethod::Polyconic {
+                    central_meridian: double(&[3080, 3088]),
+                    latitude_of_origin: double(&[3081, 3089]),
+                },
+                [3082, 3083],
+            ),
+            transformation => {
+                bail!("Unsupported GeoTIFF coordinate transformation {transformation:?}")
+            }
+        };
+        let mut projection = Projection::new(method, datum.ellipsoid());
+        projection.false_easting = double(&[false_origin[0], 3082]);
+        projection.false_northing = double(&[false_origin[1], 3083]);
+        Ok(Crs::projected(datum, projection))
+    }
+}
+
+/// Reads the georeferencing of a GeoTIFF, or returns [`None`] if the file has no GeoKeys
+///
+/// The CRS can be given by an EPSG code (see [`crs_from_epsg`]) or be user-defined with one of
+/// the supported datums and projections. The pixels are tied to model coordinates by a
+/// `ModelTransformationTag`, by a `ModelPixelScaleTag` with a `ModelTiepointTag`, or by
+/// `ModelTiepointTag` ground control points.
+pub(crate) fn read_georeference(image_file: &Path) -> Result<Option<ImageGeoreference>> {
+    let mut decoder = Decoder::new(BufReader::new(File::open(image_file)?))?;
+    let Some(directory) = decoder.find_tag(Tag::GeoKeyDirectoryTag)? else {
+        return Ok(None);
+    };
+    let doubles = decoder
+        .find_tag(Tag::GeoDoubleParamsTag)?
+        .map(Value::into_f64_vec)
+        .transpose()?
+        .unwrap_or_default();
+    let ascii = decoder
+        .find_tag(Tag::GeoAsciiParamsTag)?
+        .map(Value::into_string)
+        .transpose()?
+        .unwrap_or_default();
+    let keys = GeoKeys::parse(&directory.into_u16_vec()?, doubles, ascii)?;
+    let crs = keys.read_crs()?;
+
+    let mut f64_tag = |tag| -> Result<Option<Vec<f64>>> {
+        Ok(decoder
+            .find_tag(tag)?
+            .map(Value::into_f64_vec)
+            .transpose()?)
+    };
+    let matrix = f64_tag(Tag::ModelTransformationTag)?;
+    let scale = f64_tag(Tag::ModelPixelScaleTag)?;
+    let tiepoints = f64_tag(Tag::ModelTiepointTag)?.unwrap_or_default();
+    let mut model = match (matrix, scale) {
+        (Some(m), _) if m.len() >= 8 => {
+            PixelModel::Affine(Affine([m[3], m[0], m[1], m[7], m[4], m[5]]))
+        }
+        (_, Some(scale)) if scale.len() >= 2 && tiepoints.len() >= 6 => {
+            let (sx, sy) = (scale[0], scale[1]);
+            let [i, j, _, x, y, _] = tiepoints[..6] else {
+                unreachable!()
+            };
+            PixelModel::Affine(Affine([x - i * sx, sx, 0.0, y + j * sy, 0.0, -sy]))
+        }
+        _ if tiepoints.len() >= 18 => PixelModel::Tiepoints(
+            tiepoints
+                .chunks_exact(6)
+                .map(|t| ((t[0], t[1]), (t[3], t[4])))
+                .collect(),
+        ),
+        _ => bail!("The GeoTIFF has GeoKeys but no usable georeferencing tags"),
+    };
+    // GTRasterTypeGeoKey: RasterPixelIsPoint ties model coordinates to the centres of pixels
+    if keys.get_short(1025) == Some(2) {
+        match &mut model {
+            PixelModel::Affine(Affine([c0, c1, c2, c3, c4, c5])) => {
+                *c0 -= 0.5 * (*c1 + *c2);
+                *c3 -= 0.5 * (*c4 + *c5);
+            }
+            PixelModel::Tiepoints(tiepoints) => {
+                for ((x, y), _) in tiepoints {
+                    *x += 0.5;
+                    *y += 0.5;
+                }
+            }
+        }
+    }
+    debug!("GeoTIFF georeferencing in {}: {model:?}", crs.name());
+    Ok(Some(ImageGeoreference { crs, model }))
+}
+
+/// How pixels are tied to model coordinates
+enum Georeference {
+    /// A `ModelTransformationTag` (4x4 matrix, row-major)
+    Transformation(Vec<f64>),
+    /// A `ModelPixelScaleTag` and a single `ModelTiepointTag` for north-up images
+    Scaled { scale: Vec<f64>, tiepoint: Vec<f64> },
+    /// `ModelTiepointTag` ground control points, each `(x, y, 0, X, Y, 0)`
+    Tiepoints(Vec<f64>),
+}
+
+impl Georeference {
+    /// Ties the pixels of a chart to the model coordinates of its [`GeoTransform`]
+    ///
+    /// Affine transforms are written exactly. Other transforms are described by tie points:
+    /// the `REF` records of the chart, or a grid of points if the chart has none.
+    fn new(bsb: &KapImageFile, transform: &GeoTransform) -> Self {
+        if let Some(affine) = transform.affine() {
+            let [c0, c1, c2, c3, c4, c5] = affine.0;
+            if affine.is_north_up() {
+                return Self::Scaled {
+                    scale: vec![c1, -c5, 0.0],
+                    tiepoint: vec![0.0, 0.0, 0.0, c0, c3, 0.0],
+                };
+            }
+            #[rustfmt::skip]
+            let matrix = vec![
+                c1, c2, 0.0, c0,
+                c4, c5, 0.0, c3,
+                0.0, 0.0, 0.0, 0.0,
+                0.0, 0.0, 0.0, 1.0,
+            ];
+            return Self::Transformation(matrix);
+        }
+
+        let refs = bsb
+            .header()
+            .reference_point_record
+            .as_deref()
+            .unwrap_or_default();
+        let points: Vec<_> = if refs.is_empty() {
+            let (width, height) = (f64::from(bsb.width()), f64::from(bsb.height()));
+            (0..=4)
+                .flat_map(|j| (0..=4).map(move |i| (f64::from(i) / 4.0, f64::from(j) / 4.0)))
+                .map(|(i, j)| {
+                    let pixel = (i * width, j * height);
+                    (pixel, transform.pixel_to_model(pixel))
+                })
+                .collect()
+        } else {
+            refs.iter()
+                .map(|r| {
+                    let pixel = (r.pixels.0 as f64, r.pixels.1 as f64);
+                    // keep the longitude continuous with the rest of the chart
+                    let (lat, lon) = r.coords;
+                    let (model_lon, _) = transform.pixel_to_model(pixel);
+                    let lon = lon + 360.0 * ((model_lon - lon) / 360.0).round();
+                    (pixel, (lon, lat))
+                })
+                .collect()
+        };
+        Self::Tiepoints(
+            points
+                .into_iter()
+                .flat_map(|((x, y), (u, v))| [x, y, 0.0, u, v, 0.0])
+                .collect(),
+        )
+    }
+}
+
+/// The GeoTIFF tags of a chart
+struct GeoTags {
+    directory: Vec<u16>,
+    doubles: Vec<f64>,
+    ascii: String,
+    georeference: Georeference,
+}
+
+impl GeoTags {
+    fn write<W: Write + Seek, K: TiffKind>(
+        &self,
+        encoder: &mut DirectoryEncoder<'_, W, K>,
+    ) -> TiffResult<()> {
+        encoder.write_tag(Tag::GeoKeyDirectoryTag, &self.directory[..])?;
+        if !self.doubles.is_empty() {
+            encoder.write_tag(Tag::GeoDoubleParamsTag, &self.doubles[..])?;
+        }
+        encoder.write_tag(Tag::GeoAsciiParamsTag, self.ascii.as_str())?;
+        match &self.georeference {
+            Georeference::Transformation(matrix) => {
+                encoder.write_tag(Tag::ModelTransformationTag, &matrix[..])
+            }
+            Georeference::Scaled { scale, tiepoint } => {
+                encoder.write_tag(Tag::ModelPixelScaleTag, &scale[..])?;
+                encoder.write_tag(Tag::ModelTiepointTag, &tiepoint[..])
+            }
+            Georeference::Tiepoints(tiepoints) => {
+                encoder.write_tag(Tag::ModelTiepointTag, &tiepoints[..])
+            }
+        }
+    }
+}
+
+/// Writes a single image with its GeoTIFF tags, and the additional tags written by `tags`
+fn write_image<C: ColorType, W: Write + Seek>(
+    tiff: &mut TiffEncoder<W>,
+    (width, height): (u16, u16),
+    data: &[C::Inner],
+    geo_tags: &GeoTags,
+    tags: impl FnOnce(&mut DirectoryEncoder<W, TiffKindStandard>) -> TiffResult<()>,
+) -> Result<()>
+where
+    [C::Inner]: TiffValue,
+{
+    let mut image = tiff.new_image::<C>(u32::from(width), u32::from(height))?;
+    tags(image.encoder())?;
+    geo_tags.write(image.encoder())?;
+    image.write_data(data)?;
+    Ok(())
+}
+
+/// Writes a BSB/KAP chart as a GeoTIFF
+///
+/// The image is written with a color map unless `options.rgb` is set. The coordinate reference
+/// system of the chart is described with GeoKeys, in projected coordinates if the chart is
+/// georeferenced through its projection and in geographic coordinates otherwise.
+pub(crate) fn write_geotiff(
+    bsb: &KapImageFile,
+    output_name: &Path,
+    options: ImageOptions,
+) -> Result<()> {
+    let transform = bsb.geo_transform()?;
+    let crs = model_crs(bsb, &transform);
+    info!("Writing GeoTIFF in {}", crs.name());
+    let (directory, doubles, ascii) = GeoKeys::crs(&crs)?.directory();
+    let geo_tags = GeoTags {
+        directory,
+        doubles,
+        ascii,
+        georeference: Georeference::new(bsb, &transform),
+    };
+
+    let mut tiff = TiffEncoder::new(BufWriter::new(File::create(output_name)?))?;
+    let size = (bsb.width(), bsb.height());
+    match (options.rgb, options.clip) {
+        (false, clip) => {
+            let palette = bsb
+                .header()
+                .palette(ColorPalette::Rgb)
+                .ok_or(libbsb::Error::NonExistentPalette)?;
+            // one entry per index: the reds, then the greens, then the blues
+            let mut color_map = vec![0u16; 3 * 256];
+            for (index, &(r, g, b)) in palette.iter().enumerate().take(255) {
+                // BSB indexes start from 1
+                color_map[index + 1] = u16::from(r) * 257;
+                color_map[256 + index + 1] = u16::from(g) * 257;
+                color_map[512 + index + 1] = u16::from(b) * 257;
+            }
+            let mut data = bsb.pixel_indices().to_vec();
+            if clip {
+                for (index, inside) in data.iter_mut().zip(bsb.border_mask()?) {
+                    if !inside {
+                        *index = 0;
+                    }
+                }
+            }
+            debug!("Writing {} palette indices", data.len());
+            write_image::<Palette8, _>(&mut tiff, size, &data, &geo_tags, |encoder| {
+                encoder.write_tag(Tag::ColorMap, &color_map[..])?;
+                if clip {
+                    encoder.write_tag(Tag::GdalNodata, "0")?;
+                }
+                Ok(())
+            })?;
+        }
+        (true, false) => {
+            let data: Vec<_> = bsb.as_palette_iter(ColorPalette::Rgb)?.flatten().collect();
+            write_image::<colortype::RGB8, _>(&mut tiff, size, &data, &geo_tags, |_| Ok(()))?;
+        }
+        (true, true) => {
+            let data: Vec<_> = bsb
+                .as_rgba_palette_iter(ColorPalette::Rgb)?
+                .flatten()
+                .collect();
+            write_image::<colortype::RGBA8, _>(&mut tiff, size, &data, &geo_tags, |encoder| {
+                // unassociated alpha
+                encoder.write_tag(Tag::ExtraSamples, 2u16)
+            })?;
+        }
+    }
+    info!("Successfully wrote GeoTIFF to {}", output_name.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use libbsb::{
+        image::raw::header::{GeneralParameters, ImageHeader},
+        Depth,
+    };
+    use mktemp::Temp;
+
+    use super::*;
+
+    /// Writes the GeoKeys of a CRS and parses them back
+    fn round_trip(crs: &Crs) -> Crs {
+        let (directory, doubles, ascii) = GeoKeys::crs(crs).unwrap().directory();
+        GeoKeys::parse(&directory, doubles, ascii)
+            .unwrap()
+            .read_crs()
+            .unwrap()
+    }
+
+    #[test]
+    fn geokeys_round_trip() {
+        let projected = |datum: Datum, method, false_origin: (f64, f64)| {
+            let mut projection = Projection::new(method, datum.ellipsoid());
+            (projection.false_easting, projection.false_northing) = false_origin;
+            Crs::projected(datum, projection)
+        };
+        for crs in [
+            Crs::geographic(Datum::Wgs84),
+            Crs::geographic(Datum::Nad27),
+            crs_from_epsg(32631).unwrap(),
+            crs_from_epsg(32733).unwrap(),
+            crs_from_epsg(23030).unwrap(),
+            projected(
+                Datum::Wgs84,
+                ProjectionMethod::Mercator {
+                    central_meridian: -70.0,
+                    latitude_of_true_scale: 41.5,
+                },
+                (0.0, 0.0),
+            ),
+            projected(
+                Datum::Nad83,
+                ProjectionMethod::LambertConformalConic {
+                    central_meridian: -96.0,
+                    latitude_of_origin: 23.0,
+                    standard_parallels: (29.5, 45.5),
+                },
+                (1000.0, 2000.0),
+            ),
+            projected(
+                Datum::Nad27,
+                ProjectionMethod::Polyconic {
+                    central_meridian: -158.0,
+                    latitude_of_origin: 21.0,
+                },
+                (0.0, 0.0),
+            ),
+        ] {
+            assert_eq!(round_trip(&crs), crs, "{}", crs.name());
+        }
+    }
+
+    #[test]
+    fn geokeys_with_epsg_codes() {
+        let keys = |keys: &[[u16; 4]], doubles: Vec<f64>| {
+            let mut directory = vec![1, 1, 0, keys.len() as u16];
+            directory.extend(keys.iter().flatten());
+            GeoKeys::parse(&directory, doubles, String::new())
+                .unwrap()
+                .read_crs()
+        };
+        // ProjectedCSTypeGeoKey
+        assert_eq!(
+            keys(&[[1024, 0, 1, 1], [3072, 0, 1, 32733]], Vec::new()).unwrap(),
+            crs_from_epsg(32733).unwrap()
+        );
+        // GeographicTypeGeoKey
+        assert_eq!(
+            keys(&[[1024, 0, 1, 2], [2048, 0, 1, 4269]], Vec::new()).unwrap(),
+            Crs::geographic(Datum::Nad83)
+        );
+        // ProjLinearUnitsGeoKey: US survey foot
+        assert!(keys(
+            &[[1024, 0, 1, 1], [3072, 0, 1, 32733], [3076, 0, 1, 9003]],
+            vec![]
+        )
+        .is_err());
+        assert!(keys(&[[1024, 0, 1, 1], [3072, 0, 1, 3857]], Vec::new()).is_err());
+
+        // a Mercator projection given by its scale factor (ProjScaleAtNatOriginGeoKey)
+        let crs = keys(
+            &[
+                [1024, 0, 1, 1],
+                [2048, 0, 1, 4326],
+                [3072, 0, 1, USER_DEFINED],
+                [3075, 0, 1, 7],
+                [3080, DOUBLE_PARAMS, 1, 0],
+                [3092, DOUBLE_PARAMS, 1, 1],
+            ],
+            vec![10.0, 0.9],
+        )
+        .unwrap();
+        let Some(ProjectionMethod::Mercator {
+            central_meridian,
+            latitude_of_true_scale,
+        }) = crs.projection.map(|p| p.method)
+        else {
+            panic!("not a Mercator projection: {crs:?}");
+        };
+        assert!((central_meridian - 10.0).abs() < 1e-12);
+        let expected = mercator_latitude_of_true_scale(&Datum::Wgs84.ellipsoid(), 0.9).unwrap();
+        assert!((latitude_of_true_scale - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn write_and_read_geotiff() -> Result<()> {
+        let crs = crs_from_epsg(32631).unwrap();
+        let affine = Affine([500_000.0, 10.0, 0.0, 5_500_000.0, 0.0, -10.0]);
+        let (width, height) = (40, 30);
+        let mut header = ImageHeader::builder()
+            .ifm(Depth::Four)
+            .general_parameters(
+                GeneralParameters::builder()
+                    .chart_name("GeoTIFF test chart".to_owned())
+                    .image_width_height((width, height))
+                    .build(),
+            )
+            .rgb(vec![(0, 0, 0), (255, 255, 255)])
+            .build();
+        ImageGeoreference {
+            crs,
+            model: PixelModel::Affine(affine),
+        }
+        .georeference(&mut header, None)?;
+        // georeference the chart through its projection rather than its polynomials
+        (header.wpx, header.wpy, header.pwx, header.pwy, header.err) =
+            (None, None, None, None, None);
+        let raster = (0..usize::from(width) * usize::from(height))
+            .map(|i| 1 + (i % 2) as u8)
+            .collect();
+        let bsb = KapImageFile::new(header, raster)?;
+
+        let tiff = Temp::new_file()?;
+        write_geotiff(&bsb, &tiff, ImageOptions::default())?;
+        let georeference = read_georeference(&tiff)?.expect("the GeoTIFF has no GeoKeys");
+
+        assert_eq!(georeference.crs.datum, crs.datum);
+        let (projection, expected) = (
+            georeference.crs.projection.unwrap(),
+            crs.projection.unwrap(),
+        );
+        let ProjectionMethod::TransverseMercator {
+            central_meridian,
+            latitude_of_origin,
+            scale_factor,
+        } = projection.method
+        else {
+            panic!("not a Transverse Mercator projection: {projection:?}");
+        };
+        assert!((central_meridian - 3.0).abs() < 1e-6);
+        assert!(latitude_of_origin.abs() < 1e-6);
+        assert!((scale_factor - 0.9996).abs() < 1e-6);
+        assert!((projection.false_easting - expected.false_easting).abs() < 1e-3);
+        assert!((projection.false_northing - expected.false_northing).abs() < 1e-3);
+
+        let PixelModel::Affine(read) = georeference.model else {
+            panic!("the GeoTIFF is not affinely georeferenced");
+        };
+        for (a, b) in read.0.iter().zip(affine.0) {
+            assert!((a - b).abs() < 1e-3, "{read:?} != {affine:?}");
+        }
+        Ok(())
+    }
+}
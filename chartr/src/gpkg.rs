@@ -0,0 +1,631 @@
+//! OGC GeoPackage output for tile pyramids
+
+use std::{fs, path::Path};
+
+use anyhow::Result;
+use libbsb::crs::Crs;
+use rusqlite::{params, Connection};
+use tracing::debug;
+
+use crate::{
+    model_crs,
+    tiles::{encode_tile, TileOptions, TileRenderer, TILE_SIZE},
+};
+
+/// The name of the tile table
+const TILE_TABLE: &str = "chart";
+/// The name of the coverage feature table
+const COVERAGE_TABLE: &str = "coverage";
+/// Half the width of the Web Mercator world, in metres
+const HALF_WORLD: f64 = 20_037_508.342_789_244;
+/// The `srs_id` given to user-defined coordinate reference systems
+const USER_DEFINED_SRS_ID: i64 = 100_000;
+
+const WGS84_WKT: &str = r#"GEOGCS["WGS 84",DATUM["WGS_1984",SPHEROID["WGS 84",6378137,298.257223563,AUTHORITY["EPSG","7030"]],AUTHORITY["EPSG","6326"]],PRIMEM["Greenwich",0,AUTHORITY["EPSG","8901"]],UNIT["degree",0.0174532925199433,AUTHORITY["EPSG","9122"]],AXIS["Latitude",NORTH],AXIS["Longitude",EAST],AUTHORITY["EPSG","4326"]]"#;
+const WEB_MERCATOR_WKT: &str = r#"PROJCS["WGS 84 / Pseudo-Mercator",GEOGCS["WGS 84",DATUM["WGS_1984",SPHEROID["WGS 84",6378137,298.257223563,AUTHORITY["EPSG","7030"]],AUTHORITY["EPSG","6326"]],PRIMEM["Greenwich",0,AUTHORITY["EPSG","8901"]],UNIT["degree",0.0174532925199433,AUTHORITY["EPSG","9122"]],AUTHORITY["EPSG","4326"]],PROJECTION["Mercator_1SP"],PARAMETER["central_meridian",0],PARAMETER["scale_factor",1],PARAMETER["false_easting",0],PARAMETER["false_northing",0],UNIT["metre",1,AUTHORITY["EPSG","9001"]],AXIS["Easting",EAST],AXIS["Northing",NORTH],EXTENSION["PROJ4","+proj=merc +a=6378137 +b=6378137 +lat_ts=0 +lon_0=0 +x_0=0 +y_0=0 +k=1 +units=m +nadgrids=@null +wktext +no_defs"],AUTHORITY["EPSG","3857"]]"#;
+
+const SCHEMA: &str = "
+PRAGMA application_id = 1196444487;
+PRAGMA user_version = 10200;
+CREATE TABLE gpkg_spatial_ref_sys (
+    srs_name TEXT NOT NULL,
+    srs_id INTEGER PRIMARY KEY,
+    organization TEXT NOT NULL,
+    organization_coordsys_id INTEGER NOT NULL,
+    definition TEXT NOT NULL,
+    description TEXT
+);
+CREATE TABLE gpkg_contents (
+    table_name TEXT NOT NULL PRIMARY KEY,
+    data_type TEXT NOT NULL,
+    identifier TEXT UNIQUE,
+    description TEXT DEFAULT '',
+    last_change DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+    min_x DOUBLE,
+    min_y DOUBLE,
+    max_x DOUBLE,
+    max_y DOUBLE,
+    srs_id INTEGER REFERENCES gpkg_spatial_ref_sys(srs_id)
+);
+CREATE TABLE gpkg_geometry_columns (
+    table_name TEXT NOT NULL REFERENCES gpkg_contents(table_name),
+    column_name TEXT NOT NULL,
+    geometry_type_name TEXT NOT NULL,
+    srs_id INTEGER NOT NULL REFERENCES gpkg_spatial_ref_sys(srs_id),
+    z TINYINT NOT NULL,
+    m TINYINT NOT NULL,
+    PRIMARY KEY (table_name, column_name)
+);
+CREATE TABLE gpkg_tile_matrix_set (
+    table_name TEXT NOT NULL PRIMARY KEY REFERENCES gpkg_contents(table_name),
+    srs_id INTEGER NOT NULL REFERENCES gpkg_spatial_ref_sys(srs_id),
+    min_x DOUBLE NOT NULL,
+    min_y DOUBLE NOT NULL,
+    max_x DOUBLE NOT NULL,
+    max_y DOUBLE NOT NULL
+);
+CREATE TABLE gpkg_tile_matrix (
+    table_name TEXT NOT NULL REFERENCES gpkg_contents(table_name),
+    zoom_level INTEGER NOT NULL,
+    matrix_width INTEGER NOT NULL,
+    matrix_height INTEGER NOT NULL,
+    tile_width INTEGER NOT NULL,
+    tile_height INTEGER NOT NULL,
+    pixel_x_size DOUBLE NOT NULL,
+    pixel_y_size DOUBLE NOT NULL,
+    PRIMARY KEY (table_name, zoom_level)
+);
+CREATE TABLE chart (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    zoom_level INTEGER NOT NULL,
+    tile_column INTEGER NOT NULL,
+    tile_row INTEGER NOT NULL,
+    tile_data BLOB NOT NULL,
+    UNIQUE (zoom_level, tile_column, tile_row)
+);
+CREATE TABLE coverage (
+    fid INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    geom POLYGON,
+    name TEXT
+);
+";
+
+/// A coordinate reference system entry of `gpkg_spatial_ref_sys`
+struct SpatialRefSys {
+    name: String,
+    id: i64,
+    organization: &'static str,
+    definition: String,
+}
+
+impl SpatialRefSys {
+    fn wgs84() -> Self {
+        Self {
+            name: "WGS 84".to_owned(),
+            id: 4326,
+            organization: "EPSG",
+            definition: WGS84_WKT.to_owned(),
+        }
+    }
+
+    fn web_mercator() -> Self {
+        Self {
+            name: "WGS 84 / Pseudo-Mercator".to_owned(),
+            id: 3857,
+            organization: "EPSG",
+            definition: WEB_MERCATOR_WKT.to_owned(),
+        }
+    }
+
+    /// Describes the native CRS of a chart: its EPSG code for geographic CRS, a user-defined
+    /// entry for projected ones
+    fn native(crs: &Crs) -> Self {
+        let (id, organization) = if crs.projection.is_some() {
+            (USER_DEFINED_SRS_ID, "NONE")
+        } else {
+            (i64::from(crs.datum.epsg()), "EPSG")
+        };
+        Self {
+            name: crs.name(),
+            id,
+            organization,
+            definition: crs.to_wkt2(),
+        }
+    }
+
+    fn insert(&self, connection: &Connection) -> Result<()> {
+        connection.execute(
+            "INSERT OR IGNORE INTO gpkg_spatial_ref_sys VALUES (?1, ?2, ?3, ?4, ?5, NULL)",
+            params![
+                self.name,
+                self.id,
+                self.organization,
+                self.id,
+                self.definition
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+/// The `(zoom_level, matrix_width, matrix_height, pixel_x_size, pixel_y_size)` of a tile matrix
+type TileMatrix = (u8, u32, u32, f64, f64);
+
+/// A tile matrix set: its bounds `(min_x, min_y, max_x, max_y)` and its matrices
+struct TileMatrixSet {
+    bounds: (f64, f64, f64, f64),
+    matrices: Vec<TileMatrix>,
+}
+
+/// Writes the tiles of a chart into an OGC GeoPackage, replacing any existing file
+///
+/// The tiles are rendered in EPSG:3857 for the zoom levels of `options`, or in the native CRS
+/// of the chart if `options.native_crs` is set. In the latter case, the zoom levels of
+/// `options` are ignored: the finest zoom level matches the resolution of the chart, and each
+/// coarser level halves it until the chart fits in a single tile.
+///
+/// The coverage of the chart (its `PLY` border, or its image corners if it has none) is
+/// stored in WGS84 as a polygon feature layer.
+pub(crate) fn write_gpkg(
+    renderer: &TileRenderer,
+    output_name: &Path,
+    options: &TileOptions,
+) -> Result<usize> {
+    if output_name.exists() {
+        fs::remove_file(output_name)?;
+    }
+    let mut connection = Connection::open(output_name)?;
+    connection.execute_batch(SCHEMA)?;
+    let transaction = connection.transaction()?;
+    for srs in [
+        SpatialRefSys {
+            name: "Undefined cartesian SRS".to_owned(),
+            id: -1,
+            organization: "NONE",
+            definition: "undefined".to_owned(),
+        },
+        SpatialRefSys {
+            name: "Undefined geographic SRS".to_owned(),
+            id: 0,
+            organization: "NONE",
+            definition: "undefined".to_owned(),
+        },
+        SpatialRefSys::wgs84(),
+    ] {
+        srs.insert(&transaction)?;
+    }
+
+    let bsb = renderer.chart();
+    let name = bsb
+        .header()
+        .general_parameters
+        .chart_name
+        .clone()
+        .unwrap_or_else(|| TILE_TABLE.to_owned());
+
+    let mut count = 0;
+    let (srs, matrix_set) = {
+        let mut insert = transaction.prepare(
+            "INSERT INTO chart (zoom_level, tile_column, tile_row, tile_data) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        let mut write = |z: u8, x: u32, y: u32, data: Option<Vec<u8>>| -> Result<()> {
+            if let Some(data) = data {
+                insert.execute(params![z, x, y, encode_tile(&data)?])?;
+                count += 1;
+            }
+            Ok(())
+        };
+        if options.native_crs {
+            write_native_tiles(renderer, &mut write)?
+        } else {
+            for tile in renderer.tiles(options.zoom.clone()) {
+                // GeoPackage rows are numbered from the north, like XYZ tiles
+                write(tile.z, tile.x, tile.y, renderer.render(tile))?;
+            }
+            let matrices = options
+                .zoom
+                .clone()
+                .map(|z| {
+                    let size = 1u32 << z;
+                    let pixel = 2.0 * HALF_WORLD / f64::from(TILE_SIZE * size);
+                    (z, size, size, pixel, pixel)
+                })
+                .collect();
+            (
+                SpatialRefSys::web_mercator(),
+                TileMatrixSet {
+                    bounds: (-HALF_WORLD, -HALF_WORLD, HALF_WORLD, HALF_WORLD),
+                    matrices,
+                },
+            )
+        }
+    };
+    srs.insert(&transaction)?;
+
+    let coverage = renderer.coverage();
+    // the extents of GeoPackage contents cannot cross the antimeridian
+    let (west, east) = if coverage.crosses_antimeridian() {
+        (-180.0, 180.0)
+    } else {
+        (coverage.west, coverage.east)
+    };
+    let tiles_extent = if options.native_crs {
+        matrix_set.bounds
+    } else {
+        let (min_x, min_y) = web_mercator(coverage.south, west);
+        let (max_x, max_y) = web_mercator(coverage.north, east);
+        (min_x, min_y, max_x, max_y)
+    };
+    transaction.execute(
+        "INSERT INTO gpkg_contents (table_name, data_type, identifier, min_x, min_y, max_x, max_y, srs_id) VALUES (?1, 'tiles', ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![TILE_TABLE, name, tiles_extent.0, tiles_extent.1, tiles_extent.2, tiles_extent.3, srs.id],
+    )?;
+    let (min_x, min_y, max_x, max_y) = matrix_set.bounds;
+    transaction.execute(
+        "INSERT INTO gpkg_tile_matrix_set VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![TILE_TABLE, srs.id, min_x, min_y, max_x, max_y],
+    )?;
+    for (z, matrix_width, matrix_height, pixel_x, pixel_y) in matrix_set.matrices {
+        transaction.execute(
+            "INSERT INTO gpkg_tile_matrix VALUES (?1, ?2, ?3, ?4, ?5, ?5, ?6, ?7)",
+            params![
+                TILE_TABLE,
+                z,
+                matrix_width,
+                matrix_height,
+                TILE_SIZE,
+                pixel_x,
+                pixel_y
+            ],
+        )?;
+    }
+
+    // the coverage polygon, in WGS84
+    let polygon: Vec<_> = match bsb.header().ply.as_deref() {
+        Some(ply) if !ply.is_empty() => ply
+            .iter()
+            .map(|&c| renderer.transform().datum_shift().to_wgs84(c))
+            .collect(),
+        _ => {
+            let (width, height) = (f64::from(bsb.width()), f64::from(bsb.height()));
+            [(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)]
+                .map(|pixel| renderer.transform().pixel_to_wgs84(pixel))
+                .to_vec()
+        }
+    };
+    transaction.execute(
+        "INSERT INTO gpkg_contents (table_name, data_type, identifier, min_x, min_y, max_x, max_y, srs_id) VALUES (?1, 'features', ?2, ?3, ?4, ?5, ?6, 4326)",
+        params![
+            COVERAGE_TABLE,
+            format!("{name} coverage"),
+            west,
+            coverage.south,
+            east,
+            coverage.north
+        ],
+    )?;
+    transaction.execute(
+        "INSERT INTO gpkg_geometry_columns VALUES (?1, 'geom', 'POLYGON', 4326, 0, 0)",
+        params![COVERAGE_TABLE],
+    )?;
+    transaction.execute(
+        "INSERT INTO coverage (geom, name) VALUES (?1, ?2)",
+        params![polygon_geometry(&polygon, 4326), name],
+    )?;
+    transaction.commit()?;
+    debug!("Wrote coverage polygon of {} vertices", polygon.len());
+    Ok(count)
+}
+
+/// Renders the tiles of a chart in its native CRS, passing them to `write` as
+/// `(zoom_level, column, row, pixels)`
+fn write_native_tiles(
+    renderer: &TileRenderer,
+    write: &mut impl FnMut(u8, u32, u32, Option<Vec<u8>>) -> Result<()>,
+) -> Result<(SpatialRefSys, TileMatrixSet)> {
+    let bsb = renderer.chart();
+    let transform = renderer.transform();
+    let (width, height) = (f64::from(bsb.width()), f64::from(bsb.height()));
+    let corners = [(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)]
+        .map(|pixel| transform.pixel_to_model(pixel));
+    let min_x = corners.iter().map(|c| c.0).fold(f64::MAX, f64::min);
+    let max_x = corners.iter().map(|c| c.0).fold(f64::MIN, f64::max);
+    let min_y = corners.iter().map(|c| c.1).fold(f64::MAX, f64::min);
+    let max_y = corners.iter().map(|c| c.1).fold(f64::MIN, f64::max);
+    let (pixel_x, pixel_y) = ((max_x - min_x) / width, (max_y - min_y) / height);
+
+    // the number of halvings needed for the chart to fit in a single tile
+    let tile_size = f64::from(TILE_SIZE);
+    let levels = (width.max(height) / tile_size).log2().ceil().max(0.0) as u8;
+    let scale = f64::from(1u32 << levels);
+    let matrices: Vec<_> = (0..=levels)
+        .map(|z| {
+            let factor = f64::from(1u32 << (levels - z));
+            (z, 1u32 << z, 1u32 << z, pixel_x * factor, pixel_y * factor)
+        })
+        .collect();
+
+    for &(z, _, _, level_x, level_y) in &matrices {
+        let columns = (width * pixel_x / (tile_size * level_x)).ceil() as u32;
+        let rows = (height * pixel_y / (tile_size * level_y)).ceil() as u32;
+        for row in 0..rows {
+            for column in 0..columns {
+                let (origin_x, origin_y) = (
+                    min_x + f64::from(column) * tile_size * level_x,
+                    max_y - f64::from(row) * tile_size * level_y,
+                );
+                let data = renderer.render_with(|(c, r)| {
+                    transform.model_to_pixel((origin_x + c * level_x, origin_y - r * level_y))
+                });
+                write(z, column, row, data)?;
+            }
+        }
+    }
+
+    let crs = model_crs(bsb, transform);
+    Ok((
+        SpatialRefSys::native(&crs),
+        TileMatrixSet {
+            bounds: (
+                min_x,
+                max_y - tile_size * scale * pixel_y,
+                min_x + tile_size * scale * pixel_x,
+                max_y,
+            ),
+            matrices,
+        },
+    ))
+}
+
+/// Projects WGS84 `(latitude, longitude)` into EPSG:3857 `(x, y)`
+fn web_mercator(lat: f64, lon: f64) -> (f64, f64) {
+    let lat = lat.clamp(-85.051_128_78, 85.051_128_78).to_radians();
+    (
+        lon / 180.0 * HALF_WORLD,
+        lat.tan().asinh() / std::f64::consts::PI * HALF_WORLD,
+    )
+}
+
+/// Encodes a polygon of `(latitude, longitude)` vertices as a GeoPackage geometry blob
+fn polygon_geometry(vertices: &[(f64, f64)], srs_id: i32) -> Vec<u8> {
+    let (min_lon, max_lon, min_lat, max_lat) = vertices.iter().fold(
+        (f64::MAX, f64::MIN, f64::MAX, f64::MIN),
+        |(min_lon, max_lon, min_lat, max_lat), &(lat, lon)| {
+            (
+                min_lon.min(lon),
+                max_lon.max(lon),
+                min_lat.min(lat),
+                max_lat.max(lat),
+            )
+        },
+    );
+    // GeoPackage header: magic, version 0, little endian with an [minx, maxx, miny, maxy]
+    // envelope
+    let mut blob = vec![b'G', b'P', 0, 0b0000_0011];
+    blob.extend(srs_id.to_le_bytes());
+    for value in [min_lon, max_lon, min_lat, max_lat] {
+        blob.extend(value.to_le_bytes());
+    }
+    // WKB polygon with a single, closed ring
+    let open = vertices.first() != vertices.last();
+    blob.push(1);
+    blob.extend(3u32.to_le_bytes());
+    blob.extend(1u32.to_le_bytes());
+    blob.extend(((vertices.len() + usize::from(open)) as u32).to_le_bytes());
+    for &(lat, lon) in vertices.iter().chain(vertices.first().filter(|_| open)) {
+        blob.extend(lon.to_le_bytes());
+        blob.extend(lat.to_le_bytes());
+    }
+    blob
+}
+
+#[cfg(test)]
+mod tests {
+    use libbsb::ColorPalette;
+    use mktemp::Temp;
+
+    use super::*;
+    use crate::tests::{blank_chart, chesapeake_corner, chesapeake_header};
+
+    /// Returns the extent and `srs_id` of a table of `gpkg_contents`
+    fn contents(connection: &Connection, table: &str) -> Result<([f64; 4], i64)> {
+        Ok(connection.query_row(
+            "SELECT min_x, min_y, max_x, max_y, srs_id FROM gpkg_contents WHERE table_name = ?1",
+            [table],
+            |row| {
+                Ok((
+                    [row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?],
+                    row.get(4)?,
+                ))
+            },
+        )?)
+    }
+
+    /// Returns the bounds and `srs_id` of the tile matrix set
+    fn tile_matrix_set(connection: &Connection) -> Result<([f64; 4], i64)> {
+        Ok(connection.query_row(
+            "SELECT min_x, min_y, max_x, max_y, srs_id FROM gpkg_tile_matrix_set WHERE table_name = ?1",
+            [TILE_TABLE],
+            |row| Ok(([row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?], row.get(4)?)),
+        )?)
+    }
+
+    /// Returns the tile matrices, by zoom level
+    fn tile_matrices(connection: &Connection) -> Result<Vec<TileMatrix>> {
+        let mut statement = connection.prepare(
+            "SELECT zoom_level, matrix_width, matrix_height, pixel_x_size, pixel_y_size FROM gpkg_tile_matrix ORDER BY zoom_level",
+        )?;
+        let matrices = statement
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                ))
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(matrices)
+    }
+
+    fn assert_close(values: &[f64], expected: &[f64], tolerance: f64) {
+        assert_eq!(values.len(), expected.len());
+        for (value, expected) in values.iter().zip(expected) {
+            assert!(
+                (value - expected).abs() <= tolerance,
+                "{values:?} != {expected:?}"
+            );
+        }
+    }
+
+    /// Decodes the coverage polygon, checking the GeoPackage header of its blob, and returns its
+    /// `(longitude, latitude)` vertices
+    fn coverage_polygon(connection: &Connection) -> Result<Vec<(f64, f64)>> {
+        let blob: Vec<u8> =
+            connection.query_row("SELECT geom FROM coverage", [], |row| row.get(0))?;
+        let f64_at =
+            |offset: usize| f64::from_le_bytes(blob[offset..offset + 8].try_into().unwrap());
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(blob[offset..offset + 4].try_into().unwrap());
+        // magic, version 0, little endian with an envelope of 4 values, WGS84
+        assert_eq!(blob[..4], [b'G', b'P', 0, 0b0000_0011]);
+        assert_eq!(i32::from_le_bytes(blob[4..8].try_into()?), 4326);
+        let envelope = [f64_at(8), f64_at(16), f64_at(24), f64_at(32)];
+        // little endian WKB polygon with a single ring
+        assert_eq!((blob[40], u32_at(41), u32_at(45)), (1, 3, 1));
+        let count = u32_at(49) as usize;
+        assert_eq!(blob.len(), 53 + count * 16);
+        let vertices: Vec<_> = (0..count)
+            .map(|i| (f64_at(53 + i * 16), f64_at(61 + i * 16)))
+            .collect();
+        assert_eq!(vertices.first(), vertices.last());
+        let (lons, lats): (Vec<_>, Vec<_>) = vertices.iter().copied().unzip();
+        let min = |values: &[f64]| values.iter().copied().fold(f64::MAX, f64::min);
+        let max = |values: &[f64]| values.iter().copied().fold(f64::MIN, f64::max);
+        assert_eq!(envelope, [min(&lons), max(&lons), min(&lats), max(&lats)]);
+        Ok(vertices)
+    }
+
+    #[test]
+    fn web_mercator_geopackage() -> Result<()> {
+        let bsb = chesapeake_corner();
+        let renderer = TileRenderer::new(&bsb, ColorPalette::Rgb)?;
+        let options = TileOptions {
+            zoom: 13..=15,
+            ..TileOptions::default()
+        };
+        let output = Temp::new_file()?;
+        let count = write_gpkg(&renderer, &output, &options)?;
+        let connection = Connection::open(&output)?;
+
+        let coverage = renderer.coverage();
+        let (extent, srs_id) = contents(&connection, TILE_TABLE)?;
+        let (min_x, min_y) = web_mercator(coverage.south, coverage.west);
+        let (max_x, max_y) = web_mercator(coverage.north, coverage.east);
+        assert_eq!(srs_id, 3857);
+        assert_close(&extent, &[min_x, min_y, max_x, max_y], 1e-6);
+        // the chart lies about 37° north and 76° west
+        assert!((-8.6e6..-8.5e6).contains(&min_x) && (4.4e6..4.6e6).contains(&min_y));
+
+        let (bounds, srs_id) = tile_matrix_set(&connection)?;
+        assert_eq!(srs_id, 3857);
+        assert_eq!(bounds, [-HALF_WORLD, -HALF_WORLD, HALF_WORLD, HALF_WORLD]);
+        let matrices = tile_matrices(&connection)?;
+        assert_eq!(matrices.len(), 3);
+        for (&(z, width, height, pixel_x, pixel_y), expected_z) in matrices.iter().zip(13..) {
+            assert_eq!((z, width, height), (expected_z, 1 << z, 1 << z));
+            let expected = 156_543.033_928_041 / f64::from(1u32 << z);
+            assert_close(&[pixel_x, pixel_y], &[expected, expected], 1e-6);
+        }
+        let tiles: usize =
+            connection.query_row("SELECT COUNT(*) FROM chart", [], |row| row.get(0))?;
+        assert_eq!(tiles, count);
+
+        let (extent, srs_id) = contents(&connection, COVERAGE_TABLE)?;
+        assert_eq!(srs_id, 4326);
+        assert_close(
+            &extent,
+            &[coverage.west, coverage.south, coverage.east, coverage.north],
+            1e-12,
+        );
+        // the `PLY` border, closed and in WGS84
+        let polygon = coverage_polygon(&connection)?;
+        let shift = renderer.transform().datum_shift();
+        let ply = bsb.header().ply.as_ref().unwrap();
+        assert_eq!(polygon.len(), ply.len() + 1);
+        for (&(lon, lat), &coords) in polygon.iter().zip(ply) {
+            let (expected_lat, expected_lon) = shift.to_wgs84(coords);
+            assert_close(&[lon, lat], &[expected_lon, expected_lat], 1e-12);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn native_crs_geopackage() -> Result<()> {
+        let mut header = chesapeake_header();
+        // georeference the chart through its Mercator projection, with tiles in Mercator metres
+        (header.wpx, header.wpy, header.pwx, header.pwy) = (None, None, None, None);
+        header.general_parameters.image_width_height = (600, 400);
+        header.ply = None;
+        let bsb = blank_chart(header);
+        let renderer = TileRenderer::new(&bsb, ColorPalette::Rgb)?;
+        let options = TileOptions {
+            native_crs: true,
+            ..TileOptions::default()
+        };
+        let output = Temp::new_file()?;
+        let count = write_gpkg(&renderer, &output, &options)?;
+        let connection = Connection::open(&output)?;
+
+        let (name, organization, definition): (String, String, String) = connection.query_row(
+            "SELECT srs_name, organization, definition FROM gpkg_spatial_ref_sys WHERE srs_id = ?1",
+            [USER_DEFINED_SRS_ID],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+        assert_eq!(name, "NAD83 / Mercator");
+        assert_eq!(organization, "NONE");
+        assert_eq!(definition, bsb.header().crs()?.to_wkt2());
+
+        // the 8 m pixels of the chart at the finest level, which has 3x2 tiles
+        let [x0, pixel_x, _, y0, _, pixel_y] = renderer.transform().affine().unwrap().0;
+        let (pixel_x, pixel_y) = (pixel_x, -pixel_y);
+        assert!((pixel_x - 8.0).abs() < 1e-3 && (pixel_y - 8.0).abs() < 1e-3);
+        let matrices = tile_matrices(&connection)?;
+        assert_eq!(matrices.len(), 3);
+        for (z, &(level, width, height, level_x, level_y)) in matrices.iter().enumerate() {
+            let factor = f64::from(1u32 << (2 - z));
+            assert_eq!((usize::from(level), width, height), (z, 1 << z, 1 << z));
+            assert_close(
+                &[level_x, level_y],
+                &[pixel_x * factor, pixel_y * factor],
+                1e-3,
+            );
+        }
+        // 1x1, 2x1 and 3x2 tiles
+        assert_eq!(count, 1 + 2 + 6);
+
+        // the tile matrix set spans 4 tiles of the finest level from the top left corner
+        let (bounds, srs_id) = tile_matrix_set(&connection)?;
+        assert_eq!(srs_id, USER_DEFINED_SRS_ID);
+        let span = 4.0 * f64::from(TILE_SIZE);
+        assert_close(
+            &bounds,
+            &[x0, y0 - span * pixel_y, x0 + span * pixel_x, y0],
+            0.1,
+        );
+        let (extent, srs_id) = contents(&connection, TILE_TABLE)?;
+        assert_eq!(srs_id, USER_DEFINED_SRS_ID);
+        assert_eq!(extent, bounds);
+
+        // without a `PLY` border, the coverage is the image
+        let polygon = coverage_polygon(&connection)?;
+        assert_eq!(polygon.len(), 5);
+        let (lat, lon) = renderer.transform().pixel_to_wgs84((600.0, 400.0));
+        assert_close(&[polygon[2].0, polygon[2].1], &[lon, lat], 1e-12);
+        let (extent, srs_id) = contents(&connection, COVERAGE_TABLE)?;
+        assert_eq!(srs_id, 4326);
+        assert_close(&[extent[2], extent[1]], &[lon, lat], 1e-6);
+        Ok(())
+    }
+}
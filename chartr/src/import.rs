@@ -0,0 +1,764 @@
+//! Georeferencing of imported images, read from GeoTIFF tags or world files
+
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Result};
+use libbsb::{
+    crs::Crs,
+    geodesy::{Datum, Ellipsoid},
+    georef::{Affine, PolynomialOrder},
+    image::raw::header::{DetailedParameters, ImageHeader, Ref},
+    projection::{Projection, ProjectionMethod},
+};
+use tracing::{debug, info};
+
+use crate::{geotiff, world_file};
+
+/// The drawing units (pixels per inch) assumed to compute the scale of imported images
+const DRAWING_UNITS: usize = 254;
+/// The number of reference points along each axis of affinely georeferenced images
+const REF_GRID: usize = 3;
+
+/// How the pixels of an imported image are tied to model coordinates
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum PixelModel {
+    /// An affine transformation from pixels (`(0, 0)` being the top left corner of the top left
+    /// pixel) into model coordinates
+    Affine(Affine),
+    /// Ground control points, each `((x, y), (X, Y))`
+    Tiepoints(Vec<((f64, f64), (f64, f64))>),
+}
+
+/// The georeferencing of an imported image
+///
+/// Model coordinates are projected `(easting, northing)` in metres if the CRS is projected, and
+/// `(longitude, latitude)` in degrees otherwise.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ImageGeoreference {
+    pub(crate) crs: Crs,
+    pub(crate) model: PixelModel,
+}
+
+impl ImageGeoreference {
+    /// Reads the georeferencing of an image: its GeoTIFF tags for `.tif` and `.tiff` files, or
+    /// its world file and `.prj` sidecars otherwise
+    ///
+    /// Returns [`None`] if the image is not georeferenced.
+    pub(crate) fn read(image_file: &Path) -> Result<Option<Self>> {
+        let extension = image_file
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase);
+        if matches!(extension.as_deref(), Some("tif" | "tiff")) {
+            if let Some(georeference) = geotiff::read_georeference(image_file)? {
+                return Ok(Some(georeference));
+            }
+        }
+        world_file::read_world_file(image_file)
+    }
+
+    /// Converts model coordinates into `(latitude, longitude)`
+    fn to_coords(&self, (u, v): (f64, f64)) -> (f64, f64) {
+        self.crs
+            .projection
+            .map_or((v, u), |projection| projection.inverse((u, v)))
+    }
+
+    /// Returns the reference points of an image of `width * height` pixels
+    fn refs(&self, width: u16, height: u16) -> Vec<Ref> {
+        let ref_at = |pixels: (usize, usize), model| {
+            Ref::builder()
+                .pixels(pixels)
+                .coords(self.to_coords(model))
+                .build()
+        };
+        match &self.model {
+            PixelModel::Affine(affine) => {
+                let steps = |size: u16| {
+                    let last = usize::from(size.max(1) - 1);
+                    (0..REF_GRID).map(move |i| i * last / (REF_GRID - 1))
+                };
+                steps(height)
+                    .flat_map(|y| steps(width).map(move |x| (x, y)))
+                    .map(|(x, y)| ref_at((x, y), affine.apply((x as f64, y as f64))))
+                    .collect()
+            }
+            PixelModel::Tiepoints(tiepoints) => tiepoints
+                .iter()
+                .map(|&((x, y), model)| {
+                    let pixels = (x.round().max(0.0) as usize, y.round().max(0.0) as usize);
+                    if (pixels.0 as f64 - x).abs() > 1e-6 || (pixels.1 as f64 - y).abs() > 1e-6 {
+                        debug!("Rounding tie point ({x}, {y}) to {pixels:?}");
+                    }
+                    ref_at(pixels, model)
+                })
+                .collect(),
+        }
+    }
+
+    /// Georeferences a header: writes its `REF` records, fits its polynomials and derives its
+    /// `PLY` border and its `KNP` and `KNQ` records
+    ///
+    /// The scale is computed from the size of the pixels at the centre of the image, assuming
+    /// 254 pixels per inch unless the header has drawing units.
+    pub(crate) fn georeference(
+        &self,
+        header: &mut ImageHeader,
+        order: Option<PolynomialOrder>,
+    ) -> Result<()> {
+        let (width, height) = header.general_parameters.image_width_height;
+        let refs = self.refs(width, height);
+        let order = order.unwrap_or_else(|| PolynomialOrder::for_point_count(refs.len()));
+        info!(
+            "Georeferencing the image in {} with {} reference points and {order:?} polynomials",
+            self.crs.name(),
+            refs.len()
+        );
+        header.reference_point_record = Some(refs);
+        header.fit_polynomials(order)?;
+        header.update_err()?;
+
+        let transform = header.geo_transform()?;
+        let (width, height) = (f64::from(width), f64::from(height));
+        header.ply = Some(
+            [(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)]
+                .map(|pixel| transform.pixel_to_coords(pixel))
+                .to_vec(),
+        );
+
+        // the size and orientation of the pixels at the centre of the image
+        let ellipsoid = self.crs.datum.ellipsoid();
+        let centre = (width / 2.0, height / 2.0);
+        let origin = transform.pixel_to_coords(centre);
+        let right = metres(
+            &ellipsoid,
+            origin,
+            transform.pixel_to_coords((centre.0 + 1.0, centre.1)),
+        );
+        let down = metres(
+            &ellipsoid,
+            origin,
+            transform.pixel_to_coords((centre.0, centre.1 + 1.0)),
+        );
+        let (dx, dy) = (right.0.hypot(right.1), down.0.hypot(down.1));
+        // the bearing of the top of the image
+        let skew = (-down.0).atan2(-down.1).to_degrees().rem_euclid(360.0);
+        let skew = if (0.001..359.999).contains(&skew) {
+            skew
+        } else {
+            0.0
+        };
+
+        let drawing_units = *header
+            .general_parameters
+            .drawing_units
+            .get_or_insert(DRAWING_UNITS);
+        let scale = (f64::midpoint(dx, dy) * drawing_units as f64 / 0.0254).round() as usize;
+        debug!("Pixel size: {dx:.3} m x {dy:.3} m, scale 1:{scale}, skew {skew:.3}°");
+
+        header.detailed_parameters = Some(
+            DetailedParameters::builder()
+                .chart_scale(scale)
+                .geodetic_datum_name(self.crs.datum.to_string())
+                .skew_angle(skew as f32)
+                .text_angle(90.0)
+                .depth_units("METERS".to_owned())
+                .x_resolution(dx as f32)
+                .y_resolution(dy as f32)
+                .build(),
+        );
+        header.additional_parameters = None;
+        header.set_projection(&header_projection(&self.crs, origin));
+        Ok(())
+    }
+}
+
+/// Returns the `(east, north)` offset in metres from `from` to `to`, both `(latitude,
+/// longitude)` on `ellipsoid`
+fn metres(ellipsoid: &Ellipsoid, from: (f64, f64), to: (f64, f64)) -> (f64, f64) {
+    let lat = f64::midpoint(from.0, to.0).to_radians();
+    let east = (to.1 - from.1).to_radians() * ellipsoid.prime_vertical_radius(lat) * lat.cos();
+    let north =
+        ellipsoid.meridian_arc(to.0.to_radians()) - ellipsoid.meridian_arc(from.0.to_radians());
+    (east, north)
+}
+
+/// Returns the projection written into the header of an image in `crs`
+///
+/// Plotters require a projection, so geographic images are described as Mercator charts with
+/// a latitude of true scale at `centre`: their georeferencing is carried by the polynomials.
+pub(crate) fn header_projection(crs: &Crs, centre: (f64, f64)) -> Projection {
+    crs.projection.unwrap_or_else(|| {
+        Projection::new(
+            ProjectionMethod::Mercator {
+                central_meridian: 0.0,
+                latitude_of_true_scale: (centre.0 * 1e3).round() / 1e3,
+            },
+            crs.datum.ellipsoid(),
+        )
+    })
+}
+
+/// Returns the latitude of true scale of a Mercator projection whose scale factor at the
+/// equator is `scale_factor`
+pub(crate) fn mercator_latitude_of_true_scale(
+    ellipsoid: &Ellipsoid,
+    scale_factor: f64,
+) -> Result<f64> {
+    if !(scale_factor > 0.0 && scale_factor <= 1.0 + 1e-9) {
+        bail!("Unsupported Mercator scale factor {scale_factor}, it must be between 0 and 1");
+    }
+    // the scale factor at the latitude of true scale `φ` is `cos φ / sqrt(1 - e² sin² φ)`
+    let k2 = scale_factor * scale_factor;
+    let sin2 = (1.0 - k2) / (1.0 - k2 * ellipsoid.eccentricity_squared());
+    Ok(sin2.max(0.0).sqrt().asin().to_degrees())
+}
+
+/// Returns the datum of a geographic CRS or geodetic datum EPSG code
+pub(crate) const fn datum_from_epsg(code: u16) -> Option<Datum> {
+    match code {
+        4326 | 6326 => Some(Datum::Wgs84),
+        4269 | 6269 => Some(Datum::Nad83),
+        4267 | 6267 => Some(Datum::Nad27),
+        4230 | 6230 => Some(Datum::Ed50),
+        _ => None,
+    }
+}
+
+/// Returns the CRS of an EPSG code: the geographic CRS of the supported datums, their UTM
+/// zones and the WGS 84 World Mercator
+pub(crate) fn crs_from_epsg(code: u16) -> Option<Crs> {
+    if let Some(datum) = datum_from_epsg(code) {
+        return Some(Crs::geographic(datum));
+    }
+    let utm = |datum: Datum, zone: u16, south: bool| {
+        (1..=60).contains(&zone).then(|| {
+            let mut projection = Projection::new(
+                ProjectionMethod::TransverseMercator {
+                    central_meridian: f64::from(zone) * 6.0 - 183.0,
+                    latitude_of_origin: 0.0,
+                    scale_factor: 0.9996,
+                },
+                datum.ellipsoid(),
+            );
+            projection.false_easting = 500_000.0;
+            projection.false_northing = if south { 10_000_000.0 } else { 0.0 };
+            Crs::projected(datum, projection)
+        })
+    };
+    match code {
+        3395 => Some(Crs::projected(
+            Datum::Wgs84,
+            Projection::new(
+                ProjectionMethod::Mercator {
+                    central_meridian: 0.0,
+                    latitude_of_true_scale: 0.0,
+                },
+                Ellipsoid::WGS84,
+            ),
+        )),
+        32601..=32660 => utm(Datum::Wgs84, code - 32600, false),
+        32701..=32760 => utm(Datum::Wgs84, code - 32700, true),
+        26901..=26923 => utm(Datum::Nad83, code - 26900, false),
+        26701..=26722 => utm(Datum::Nad27, code - 26700, false),
+        23028..=23038 => utm(Datum::Ed50, code - 23000, false),
+        _ => None,
+    }
+}
+
+/// A node of a WKT string, e.g. `PARAMETER["central_meridian",-123]`
+#[derive(Debug, Clone, PartialEq)]
+struct WktNode {
+    keyword: String,
+    args: Vec<WktArg>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum WktArg {
+    Node(WktNode),
+    Text(String),
+    Value(String),
+}
+
+impl WktNode {
+    /// Parses a WKT string
+    fn parse(wkt: &str) -> Result<Self> {
+        let mut chars = wkt.trim().chars().peekable();
+        let node = Self::parse_node(&mut chars)?;
+        Ok(node)
+    }
+
+    fn parse_node(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<Self> {
+        let keyword: String =
+            std::iter::from_fn(|| chars.next_if(|c| c.is_ascii_alphanumeric())).collect();
+        if keyword.is_empty() || !matches!(chars.next(), Some('[' | '(')) {
+            bail!("Invalid WKT: expected a keyword followed by `[`");
+        }
+        let mut args = Vec::new();
+        loop {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            match chars.peek() {
+                Some('"') => {
+                    chars.next();
+                    let mut text = String::new();
+                    loop {
+                        match chars.next() {
+                            // quotes are escaped by doubling them
+                            Some('"') if chars.next_if_eq(&'"').is_some() => text.push('"'),
+                            Some('"') => break,
+                            Some(c) => text.push(c),
+                            None => bail!("Invalid WKT: unterminated string"),
+                        }
+                    }
+                    args.push(WktArg::Text(text));
+                }
+                Some(c) if c.is_ascii_alphabetic() => {
+                    let mut lookahead = chars.clone();
+                    let word: String =
+                        std::iter::from_fn(|| lookahead.next_if(|c| c.is_ascii_alphanumeric()))
+                            .collect();
+                    if matches!(lookahead.peek(), Some('[' | '(')) {
+                        args.push(WktArg::Node(Self::parse_node(chars)?));
+                    } else {
+                        *chars = lookahead;
+                        args.push(WktArg::Value(word));
+                    }
+                }
+                Some(_) => {
+                    let value: String =
+                        std::iter::from_fn(|| chars.next_if(|c| !matches!(c, ',' | ']' | ')')))
+                            .collect();
+                    args.push(WktArg::Value(value.trim().to_owned()));
+                }
+                None => bail!("Invalid WKT: unterminated `{keyword}`"),
+            }
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            match chars.next() {
+                Some(',') => {}
+                Some(']' | ')') => break,
+                _ => bail!("Invalid WKT: expected `,` or `]` in `{keyword}`"),
+            }
+        }
+        Ok(Self { keyword, args })
+    }
+
+    /// Returns the child nodes
+    fn children(&self) -> impl Iterator<Item = &Self> {
+        self.args.iter().filter_map(|arg| match arg {
+            WktArg::Node(node) => Some(node),
+            _ => None,
+        })
+    }
+
+    /// Returns the first descendant with one of the `keywords`, searching breadth first
+    fn find(&self, keywords: &[&str]) -> Option<&Self> {
+        self.children()
+            .find(|node| {
+                keywords
+                    .iter()
+                    .any(|k| node.keyword.eq_ignore_ascii_case(k))
+            })
+            .or_else(|| self.children().find_map(|node| node.find(keywords)))
+    }
+
+    /// Returns all the descendants with the `keyword`
+    fn find_all<'a>(&'a self, keyword: &'a str, found: &mut Vec<&'a Self>) {
+        for node in self.children() {
+            if node.keyword.eq_ignore_ascii_case(keyword) {
+                found.push(node);
+            }
+            node.find_all(keyword, found);
+        }
+    }
+
+    /// Returns the first quoted argument (the name of most nodes)
+    fn name(&self) -> Option<&str> {
+        self.args.iter().find_map(|arg| match arg {
+            WktArg::Text(text) => Some(text.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Returns the first unquoted numerical argument
+    fn value(&self) -> Option<f64> {
+        self.args.iter().find_map(|arg| match arg {
+            WktArg::Value(value) => value.parse().ok(),
+            _ => None,
+        })
+    }
+
+    /// Returns the EPSG code of the node, from its `ID` (WKT2) or `AUTHORITY` (WKT1) child
+    fn epsg(&self) -> Option<u16> {
+        self.children()
+            .filter(|node| {
+                node.keyword.eq_ignore_ascii_case("ID")
+                    || node.keyword.eq_ignore_ascii_case("AUTHORITY")
+            })
+            .find(|node| {
+                node.name()
+                    .is_some_and(|name| name.eq_ignore_ascii_case("EPSG"))
+            })
+            .and_then(|node| node.args.get(1))
+            .and_then(|arg| match arg {
+                WktArg::Value(value) | WktArg::Text(value) => value.parse().ok(),
+                WktArg::Node(_) => None,
+            })
+    }
+}
+
+/// Returns a name in lowercase, without any non alphanumeric character
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Parses the CRS of a WKT (WKT1, ESRI WKT or WKT2) string, e.g. the content of a `.prj` file
+///
+/// CRSs with a supported EPSG code (see [`crs_from_epsg`]) are recognized from their code.
+/// Other CRSs must use one of the supported datums and projections, with their coordinates in
+/// metres: other linear units are rejected. The scale factor of Mercator projections is
+/// converted into their latitude of true scale.
+pub(crate) fn crs_from_wkt(wkt: &str) -> Result<Crs> {
+    let root = WktNode::parse(wkt)?;
+    if let Some(crs) = root.epsg().and_then(crs_from_epsg) {
+        return Ok(crs);
+    }
+    let datum_name = root
+        .find(&["DATUM", "GEODETICDATUM", "TRF"])
+        .and_then(WktNode::name)
+        .ok_or_else(|| anyhow!("The WKT has no datum"))?;
+    let datum: Datum = datum_name
+        .strip_prefix("D_")
+        .unwrap_or(datum_name)
+        .parse()?;
+    let keyword = root.keyword.to_ascii_uppercase();
+    if !keyword.starts_with("PROJ") {
+        return Ok(Crs::geographic(datum));
+    }
+
+    // the unit of the coordinates, given by the CRS (WKT1) or by its axes (WKT2)
+    let is_unit = |node: &&WktNode| {
+        node.keyword.eq_ignore_ascii_case("UNIT") || node.keyword.eq_ignore_ascii_case("LENGTHUNIT")
+    };
+    let unit = root.children().find(is_unit).or_else(|| {
+        root.children()
+            .filter(|node| node.keyword.eq_ignore_ascii_case("AXIS"))
+            .find_map(|axis| axis.children().find(is_unit))
+    });
+    if let Some(unit) = unit {
+        if unit
+            .value()
+            .is_none_or(|metres| (metres - 1.0).abs() > 1e-9)
+        {
+            bail!(
+                "Unsupported WKT linear unit `{}`, only metres are supported",
+                unit.name().unwrap_or_default()
+            );
+        }
+    }
+
+    let method = root
+        .find(&["PROJECTION", "METHOD"])
+        .and_then(WktNode::name)
+        .map(normalize)
+        .ok_or_else(|| anyhow!("The projected WKT has no projection method"))?;
+    let mut parameters = Vec::new();
+    root.find_all("PARAMETER", &mut parameters);
+    let parameter = |names: &[&str]| {
+        parameters
+            .iter()
+            .find(|p| {
+                p.name()
+                    .is_some_and(|name| names.contains(&normalize(name).as_str()))
+            })
+            .and_then(|p| p.value())
+    };
+    let central_meridian = parameter(&[
+        "centralmeridian",
+        "longitudeofnaturalorigin",
+        "longitudeoffalseorigin",
+        "longitudeofcenter",
+        "longitudeoforigin",
+    ])
+    .unwrap_or_default();
+    let latitude_of_origin = parameter(&[
+        "latitudeoforigin",
+        "latitudeofnaturalorigin",
+        "latitudeoffalseorigin",
+        "latitudeofcenter",
+    ])
+    .unwrap_or_default();
+    let first_parallel = parameter(&["standardparallel1", "latitudeof1ststandardparallel"]);
+    let scale_factor = parameter(&["scalefactor", "scalefactoratnaturalorigin"]);
+
+    let method = if method.contains("transverse") {
+        ProjectionMethod::TransverseMercator {
+            central_meridian,
+            latitude_of_origin,
+            scale_factor: scale_factor.unwrap_or(1.0),
+        }
+    } else if method.contains("mercator") {
+        let latitude_of_true_scale = match (first_parallel, scale_factor) {
+            (Some(parallel), _) if parallel.abs() > 1e-9 => parallel,
+            (_, Some(scale_factor)) => {
+                mercator_latitude_of_true_scale(&datum.ellipsoid(), scale_factor)?
+            }
+            _ => 0.0,
+        };
+        ProjectionMethod::Mercator {
+            central_meridian,
+            latitude_of_true_scale,
+        }
+    } else if method.contains("lambert") {
+        let first = first_parallel.unwrap_or(latitude_of_origin);
+        ProjectionMethod::LambertConformalConic {
+            central_meridian,
+            latitude_of_origin,
+            standard_parallels: (
+                first,
+                parameter(&["standardparallel2", "latitudeof2ndstandardparallel"]).unwrap_or(first),
+            ),
+        }
+    } else if method.contains("polyconic") {
+        ProjectionMethod::Polyconic {
+            central_meridian,
+            latitude_of_origin,
+        }
+    } else {
+        bail!("Unsupported projection method `{method}`");
+    };
+    let mut projection = Projection::new(method, datum.ellipsoid());
+    projection.false_easting =
+        parameter(&["falseeasting", "eastingatfalseorigin"]).unwrap_or_default();
+    projection.false_northing =
+        parameter(&["falsenorthing", "northingatfalseorigin"]).unwrap_or_default();
+    Ok(Crs::projected(datum, projection))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Asserts that two CRSs are equal, up to rounding errors in their parameters
+    fn assert_crs_eq(crs: &Crs, expected: &Crs) {
+        assert_eq!(crs.datum, expected.datum);
+        let (Some(projection), Some(expected)) = (crs.projection, expected.projection) else {
+            assert_eq!(crs.projection, expected.projection);
+            return;
+        };
+        assert_eq!(projection.ellipsoid, expected.ellipsoid);
+        assert!((projection.false_easting - expected.false_easting).abs() < 1e-6);
+        assert!((projection.false_northing - expected.false_northing).abs() < 1e-6);
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        match (projection.method, expected.method) {
+            (
+                ProjectionMethod::TransverseMercator {
+                    central_meridian: a0,
+                    latitude_of_origin: a1,
+                    scale_factor: a2,
+                },
+                ProjectionMethod::TransverseMercator {
+                    central_meridian: b0,
+                    latitude_of_origin: b1,
+                    scale_factor: b2,
+                },
+            ) => assert!(close(a0, b0) && close(a1, b1) && close(a2, b2)),
+            (
+                ProjectionMethod::Mercator {
+                    central_meridian: a0,
+                    latitude_of_true_scale: a1,
+                },
+                ProjectionMethod::Mercator {
+                    central_meridian: b0,
+                    latitude_of_true_scale: b1,
+                },
+            ) => assert!(close(a0, b0) && (a1 - b1).abs() < 1e-6),
+            (method, expected) => assert_eq!(method, expected),
+        }
+    }
+
+    #[test]
+    fn utm_zones_from_epsg() {
+        let zone = |code| {
+            let crs = crs_from_epsg(code).unwrap();
+            let projection = crs.projection.unwrap();
+            let ProjectionMethod::TransverseMercator {
+                central_meridian,
+                scale_factor,
+                ..
+            } = projection.method
+            else {
+                panic!("EPSG:{code} is not a Transverse Mercator projection");
+            };
+            assert!((scale_factor - 0.9996).abs() < 1e-12);
+            assert!((projection.false_easting - 500_000.0).abs() < 1e-9);
+            (crs.datum, central_meridian, projection.false_northing)
+        };
+        assert_eq!(zone(32631), (Datum::Wgs84, 3.0, 0.0));
+        assert_eq!(zone(32601), (Datum::Wgs84, -177.0, 0.0));
+        assert_eq!(zone(32660), (Datum::Wgs84, 177.0, 0.0));
+        assert_eq!(zone(32733), (Datum::Wgs84, 15.0, 10_000_000.0));
+        assert_eq!(zone(26918), (Datum::Nad83, -75.0, 0.0));
+        assert_eq!(zone(26710), (Datum::Nad27, -123.0, 0.0));
+        assert_eq!(zone(23031), (Datum::Ed50, 3.0, 0.0));
+
+        assert_eq!(crs_from_epsg(4326), Some(Crs::geographic(Datum::Wgs84)));
+        assert_eq!(crs_from_epsg(6269), Some(Crs::geographic(Datum::Nad83)));
+        assert!(matches!(
+            crs_from_epsg(3395)
+                .and_then(|crs| crs.projection)
+                .map(|p| p.method),
+            Some(ProjectionMethod::Mercator { .. })
+        ));
+        for code in [32600, 32661, 32700, 32761, 3857, 2154] {
+            assert_eq!(crs_from_epsg(code), None, "EPSG:{code}");
+        }
+    }
+
+    #[test]
+    fn wkt_nodes() {
+        let node =
+            WktNode::parse(r#"UNIT["US survey foot",0.304800609601219,AUTHORITY["EPSG","9003"]]"#)
+                .unwrap();
+        assert_eq!(node.keyword, "UNIT");
+        assert_eq!(node.name(), Some("US survey foot"));
+        assert_eq!(node.value(), Some(0.304_800_609_601_219));
+        assert_eq!(node.epsg(), Some(9003));
+
+        // parentheses, escaped quotes and unquoted values
+        let node = WktNode::parse(r#"AXIS("Easting ""E""", EAST)"#).unwrap();
+        assert_eq!(node.name(), Some(r#"Easting "E""#));
+        assert_eq!(node.args[1], WktArg::Value("EAST".to_owned()));
+
+        for invalid in ["", "UNIT", r#"UNIT["metre",1"#, r#"UNIT["metre]"#] {
+            assert!(WktNode::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn wkt1_crs() {
+        // recognized from its EPSG code
+        let wkt = r#"PROJCS["WGS 84 / UTM zone 31N",GEOGCS["WGS 84",DATUM["WGS_1984",SPHEROID["WGS 84",6378137,298.257223563,AUTHORITY["EPSG","7030"]],AUTHORITY["EPSG","6326"]],PRIMEM["Greenwich",0],UNIT["degree",0.0174532925199433],AUTHORITY["EPSG","4326"]],PROJECTION["Transverse_Mercator"],PARAMETER["latitude_of_origin",0],PARAMETER["central_meridian",3],PARAMETER["scale_factor",0.9996],PARAMETER["false_easting",500000],PARAMETER["false_northing",0],UNIT["metre",1,AUTHORITY["EPSG","9001"]],AXIS["Easting",EAST],AXIS["Northing",NORTH],AUTHORITY["EPSG","32631"]]"#;
+        let utm = crs_from_epsg(32631).unwrap();
+        assert_eq!(crs_from_wkt(wkt).unwrap(), utm);
+        // or from its parameters
+        let wkt = wkt.replace(r#",AUTHORITY["EPSG","32631"]"#, "");
+        assert_crs_eq(&crs_from_wkt(&wkt).unwrap(), &utm);
+
+        let wkt = r#"GEOGCS["NAD83",DATUM["North_American_Datum_1983",SPHEROID["GRS 1980",6378137,298.257222101]],PRIMEM["Greenwich",0],UNIT["degree",0.0174532925199433]]"#;
+        assert_eq!(crs_from_wkt(wkt).unwrap(), Crs::geographic(Datum::Nad83));
+
+        let wkt = r#"PROJCS["NAD27 / Lambert",GEOGCS["NAD27",DATUM["North_American_Datum_1927",SPHEROID["Clarke 1866",6378206.4,294.978698213898]],PRIMEM["Greenwich",0],UNIT["degree",0.0174532925199433]],PROJECTION["Lambert_Conformal_Conic_2SP"],PARAMETER["standard_parallel_1",33],PARAMETER["standard_parallel_2",45],PARAMETER["latitude_of_origin",23],PARAMETER["central_meridian",-96],PARAMETER["false_easting",1000],PARAMETER["false_northing",2000],UNIT["metre",1]]"#;
+        let mut projection = Projection::new(
+            ProjectionMethod::LambertConformalConic {
+                central_meridian: -96.0,
+                latitude_of_origin: 23.0,
+                standard_parallels: (33.0, 45.0),
+            },
+            Datum::Nad27.ellipsoid(),
+        );
+        (projection.false_easting, projection.false_northing) = (1000.0, 2000.0);
+        assert_crs_eq(
+            &crs_from_wkt(wkt).unwrap(),
+            &Crs::projected(Datum::Nad27, projection),
+        );
+    }
+
+    #[test]
+    fn esri_wkt_crs() {
+        let wkt = r#"PROJCS["WGS_1984_UTM_Zone_33S",GEOGCS["GCS_WGS_1984",DATUM["D_WGS_1984",SPHEROID["WGS_1984",6378137.0,298.257223563]],PRIMEM["Greenwich",0.0],UNIT["Degree",0.0174532925199433]],PROJECTION["Transverse_Mercator"],PARAMETER["False_Easting",500000.0],PARAMETER["False_Northing",10000000.0],PARAMETER["Central_Meridian",15.0],PARAMETER["Scale_Factor",0.9996],PARAMETER["Latitude_Of_Origin",0.0],UNIT["Meter",1.0]]"#;
+        assert_crs_eq(&crs_from_wkt(wkt).unwrap(), &crs_from_epsg(32733).unwrap());
+
+        let wkt = r#"PROJCS["Mercator",GEOGCS["GCS_European_1950",DATUM["D_European_1950",SPHEROID["International_1924",6378388.0,297.0]],PRIMEM["Greenwich",0.0],UNIT["Degree",0.0174532925199433]],PROJECTION["Mercator"],PARAMETER["False_Easting",0.0],PARAMETER["False_Northing",0.0],PARAMETER["Central_Meridian",-5.0],PARAMETER["Standard_Parallel_1",48.5],UNIT["Meter",1.0]]"#;
+        let projection = Projection::new(
+            ProjectionMethod::Mercator {
+                central_meridian: -5.0,
+                latitude_of_true_scale: 48.5,
+            },
+            Datum::Ed50.ellipsoid(),
+        );
+        assert_crs_eq(
+            &crs_from_wkt(wkt).unwrap(),
+            &Crs::projected(Datum::Ed50, projection),
+        );
+    }
+
+    #[test]
+    fn wkt2_crs() {
+        let wkt = r#"PROJCRS["WGS 84 / UTM zone 33S",
+            BASEGEOGCRS["WGS 84",
+                DATUM["World Geodetic System 1984",
+                    ELLIPSOID["WGS 84",6378137,298.257223563,LENGTHUNIT["metre",1]]],
+                PRIMEM["Greenwich",0,ANGLEUNIT["degree",0.0174532925199433]]],
+            CONVERSION["UTM zone 33S",
+                METHOD["Transverse Mercator",ID["EPSG",9807]],
+                PARAMETER["Latitude of natural origin",0,ANGLEUNIT["degree",0.0174532925199433]],
+                PARAMETER["Longitude of natural origin",15,ANGLEUNIT["degree",0.0174532925199433]],
+                PARAMETER["Scale factor at natural origin",0.9996,SCALEUNIT["unity",1]],
+                PARAMETER["False easting",500000,LENGTHUNIT["metre",1]],
+                PARAMETER["False northing",10000000,LENGTHUNIT["metre",1]]],
+            CS[Cartesian,2],
+                AXIS["(E)",east,ORDER[1],LENGTHUNIT["metre",1]],
+                AXIS["(N)",north,ORDER[2],LENGTHUNIT["metre",1]]]"#;
+        let utm = crs_from_epsg(32733).unwrap();
+        assert_crs_eq(&crs_from_wkt(wkt).unwrap(), &utm);
+        let with_id = format!(r#"{},ID["EPSG",32733]]"#, wkt.strip_suffix(']').unwrap());
+        assert_eq!(crs_from_wkt(&with_id).unwrap(), utm);
+
+        // the WKT2 written for charts is read back
+        for crs in [utm, Crs::geographic(Datum::Nad27)] {
+            assert_crs_eq(&crs_from_wkt(&crs.to_wkt2()).unwrap(), &crs);
+        }
+
+        let feet = wkt.replace(
+            r#"LENGTHUNIT["metre",1]],"#,
+            r#"LENGTHUNIT["foot",0.3048]],"#,
+        );
+        let error = crs_from_wkt(&feet).unwrap_err().to_string();
+        assert!(error.contains("foot"), "{error}");
+    }
+
+    #[test]
+    fn wkt_linear_units_must_be_metres() {
+        let wkt = r#"PROJCS["NAD83 / UTM zone 18N (ftUS)",GEOGCS["NAD83",DATUM["North_American_Datum_1983",SPHEROID["GRS 1980",6378137,298.257222101]],PRIMEM["Greenwich",0],UNIT["degree",0.0174532925199433]],PROJECTION["Transverse_Mercator"],PARAMETER["latitude_of_origin",0],PARAMETER["central_meridian",-75],PARAMETER["scale_factor",0.9996],PARAMETER["false_easting",1640416.667],PARAMETER["false_northing",0],UNIT["US survey foot",0.304800609601219]]"#;
+        let error = crs_from_wkt(wkt).unwrap_err().to_string();
+        assert!(error.contains("US survey foot"), "{error}");
+        let wkt = wkt.replace(r#"UNIT["US survey foot",0.304800609601219]"#, "");
+        let wkt = wkt.replace(",]", "]");
+        assert!(crs_from_wkt(&wkt).is_ok());
+    }
+
+    #[test]
+    fn mercator_scale_factor() {
+        let ellipsoid = Ellipsoid::WGS84;
+        // the scale factor at the equator of a Mercator projection true at 30°
+        let lat = 30f64.to_radians();
+        let k0 = lat.cos() / (1.0 - ellipsoid.eccentricity_squared() * lat.sin().powi(2)).sqrt();
+        let latitude = mercator_latitude_of_true_scale(&ellipsoid, k0).unwrap();
+        assert!((latitude - 30.0).abs() < 1e-9);
+        assert!(
+            mercator_latitude_of_true_scale(&ellipsoid, 1.0)
+                .unwrap()
+                .abs()
+                < 1e-9
+        );
+        assert!(mercator_latitude_of_true_scale(&ellipsoid, 1.1).is_err());
+
+        let wkt = format!(
+            r#"PROJCS["WGS 84 / Mercator 1SP",GEOGCS["WGS 84",DATUM["WGS_1984",SPHEROID["WGS 84",6378137,298.257223563]],PRIMEM["Greenwich",0],UNIT["degree",0.0174532925199433]],PROJECTION["Mercator_1SP"],PARAMETER["central_meridian",10],PARAMETER["scale_factor",{k0}],PARAMETER["false_easting",0],PARAMETER["false_northing",0],UNIT["metre",1]]"#
+        );
+        let projection = Projection::new(
+            ProjectionMethod::Mercator {
+                central_meridian: 10.0,
+                latitude_of_true_scale: 30.0,
+            },
+            ellipsoid,
+        );
+        assert_crs_eq(
+            &crs_from_wkt(&wkt).unwrap(),
+            &Crs::projected(Datum::Wgs84, projection),
+        );
+    }
+}
@@ -0,0 +1,184 @@
+//! KMZ export of BSB/KAP charts as KML ground overlays
+
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Result;
+use libbsb::KapImageFile;
+use tracing::info;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{encode_png, vrt::escape, ImageOptions};
+
+/// The path of the chart image inside the KMZ archive
+const IMAGE_PATH: &str = "files/chart.png";
+
+/// The difference, in degrees, below which the corners of a chart are considered aligned with
+/// meridians and parallels
+const ALIGNMENT_TOLERANCE: f64 = 1e-6;
+
+/// Writes a BSB/KAP chart as a KMZ archive holding the chart image and a KML `GroundOverlay`
+///
+/// The overlay is placed with a `LatLonBox` if the corners of the chart are aligned with
+/// meridians and parallels, and with a `gx:LatLonQuad` otherwise. Coordinates are converted
+/// into WGS84.
+pub(crate) fn write_kmz(
+    bsb: &KapImageFile,
+    output_name: &Path,
+    options: ImageOptions,
+) -> Result<()> {
+    let transform = bsb.geo_transform()?;
+    let (width, height) = (f64::from(bsb.width()), f64::from(bsb.height()));
+    // counter-clockwise from the lower left corner, as expected by `gx:LatLonQuad`
+    let corners = [(0.0, height), (width, height), (width, 0.0), (0.0, 0.0)]
+        .map(|pixel| transform.pixel_to_wgs84(pixel));
+    let [lower_left, lower_right, upper_right, upper_left] = corners;
+
+    let name = bsb
+        .header()
+        .general_parameters
+        .chart_name
+        .as_deref()
+        .unwrap_or("BSB/KAP chart");
+    let mut kml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<kml xmlns=\"http://www.opengis.net/kml/2.2\" xmlns:gx=\"http://www.google.com/kml/ext/2.2\">\n  <GroundOverlay>\n    <name>{}</name>\n    <Icon>\n      <href>{IMAGE_PATH}</href>\n    </Icon>\n",
+        escape(name)
+    );
+    let aligned = |a: f64, b: f64| (a - b).abs() < ALIGNMENT_TOLERANCE;
+    if aligned(lower_left.1, upper_left.1)
+        && aligned(lower_right.1, upper_right.1)
+        && aligned(lower_left.0, lower_right.0)
+        && aligned(upper_left.0, upper_right.0)
+    {
+        let _ = write!(
+            kml,
+            "    <LatLonBox>\n      <north>{}</north>\n      <south>{}</south>\n      <east>{}</east>\n      <west>{}</west>\n    </LatLonBox>\n",
+            upper_left.0, lower_left.0, upper_right.1, upper_left.1
+        );
+    } else {
+        let coordinates = corners
+            .iter()
+            .map(|(lat, lon)| format!("{lon},{lat}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let _ = write!(
+            kml,
+            "    <gx:LatLonQuad>\n      <coordinates>{coordinates}</coordinates>\n    </gx:LatLonQuad>\n"
+        );
+    }
+    kml.push_str("  </GroundOverlay>\n</kml>\n");
+
+    let mut zip = ZipWriter::new(BufWriter::new(File::create(output_name)?));
+    zip.start_file("doc.kml", SimpleFileOptions::default())?;
+    zip.write_all(kml.as_bytes())?;
+    // PNG data is already compressed
+    zip.start_file(
+        IMAGE_PATH,
+        SimpleFileOptions::default().compression_method(CompressionMethod::Stored),
+    )?;
+    encode_png(bsb, &mut zip, options)?;
+    zip.finish()?.flush()?;
+    info!("Successfully wrote KMZ to {}", output_name.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use mktemp::Temp;
+    use zip::ZipArchive;
+
+    use super::*;
+    use crate::tests::{blank_chart, chesapeake_header};
+
+    /// Writes the KMZ of `bsb` and returns its KML document and chart image
+    fn write_and_read(bsb: &KapImageFile) -> Result<(String, Vec<u8>)> {
+        let kmz = Temp::new_file()?;
+        write_kmz(bsb, &kmz, ImageOptions::default())?;
+        let mut archive = ZipArchive::new(File::open(&kmz)?)?;
+        let mut kml = String::new();
+        archive.by_name("doc.kml")?.read_to_string(&mut kml)?;
+        let mut image = Vec::new();
+        archive.by_name(IMAGE_PATH)?.read_to_end(&mut image)?;
+        assert_eq!(archive.len(), 2);
+        Ok((kml, image))
+    }
+
+    /// Returns the text of the first `element` of an XML document
+    fn text<'a>(xml: &'a str, element: &str) -> &'a str {
+        let start = xml.find(&format!("<{element}>")).unwrap() + element.len() + 2;
+        let end = xml.find(&format!("</{element}>")).unwrap();
+        &xml[start..end]
+    }
+
+    #[test]
+    fn kmz_of_rotated_chart() -> Result<()> {
+        let mut header = chesapeake_header();
+        // keep the raster small: the polynomials still describe the whole chart
+        header.general_parameters.image_width_height = (116, 98);
+        // and rotate it slightly
+        let pwx = header.pwx.as_mut().unwrap();
+        pwx.poly[2] = -0.1 * pwx.poly[1];
+        let bsb = blank_chart(header);
+
+        let (kml, image) = write_and_read(&bsb)?;
+        assert_eq!(text(&kml, "name"), "CHESAPEAKE BAY ENTRANCE");
+        assert_eq!(text(&kml, "href"), IMAGE_PATH);
+        // a PNG image of the size of the chart
+        assert_eq!(image[..8], *b"\x89PNG\r\n\x1a\n");
+        assert_eq!(image[16..24], [0, 0, 0, 116, 0, 0, 0, 98]);
+
+        // the rotated chart is not aligned with meridians and parallels
+        assert!(!kml.contains("<LatLonBox>"));
+        let corners = text(&kml, "coordinates")
+            .split(' ')
+            .map(|corner| {
+                let (lon, lat) = corner.split_once(',').unwrap();
+                Ok((lat.parse::<f64>()?, lon.parse::<f64>()?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let transform = bsb.geo_transform()?;
+        let expected = [(0.0, 98.0), (116.0, 98.0), (116.0, 0.0), (0.0, 0.0)]
+            .map(|pixel| transform.pixel_to_wgs84(pixel));
+        assert_eq!(corners.len(), 4);
+        for (corner, expected) in corners.iter().zip(expected) {
+            assert!((corner.0 - expected.0).abs() < 1e-9 && (corner.1 - expected.1).abs() < 1e-9);
+        }
+        // counter-clockwise from the lower left corner
+        let [lower_left, lower_right, upper_right, upper_left] = expected;
+        assert!(lower_left.1 < lower_right.1 && lower_right.0 < upper_right.0);
+        assert!(upper_right.1 > upper_left.1 && upper_left.0 > lower_left.0);
+        Ok(())
+    }
+
+    #[test]
+    fn kmz_of_aligned_chart() -> Result<()> {
+        let mut header = chesapeake_header();
+        // georeferenced through its Mercator projection, the chart is aligned with meridians
+        // and parallels
+        (header.wpx, header.wpy, header.pwx, header.pwy) = (None, None, None, None);
+        header.general_parameters.image_width_height = (116, 98);
+        let bsb = blank_chart(header);
+
+        let (kml, _) = write_and_read(&bsb)?;
+        assert!(!kml.contains("<gx:LatLonQuad>"));
+        let transform = bsb.geo_transform()?;
+        let (south, west) = transform.pixel_to_wgs84((0.0, 98.0));
+        let (north, east) = transform.pixel_to_wgs84((116.0, 0.0));
+        for (element, expected) in [
+            ("north", north),
+            ("south", south),
+            ("east", east),
+            ("west", west),
+        ] {
+            let value: f64 = text(&kml, element).parse()?;
+            assert!((value - expected).abs() < 1e-6, "{element}: {value}");
+        }
+        Ok(())
+    }
+}
@@ -1,21 +1,108 @@
-use std::{collections::HashSet, fs::File, path::Path};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use image::{codecs::png::PngEncoder, GenericImageView, ImageEncoder};
 use libbsb::{
-    image::raw::header::{GeneralParameters, ImageHeader},
+    crs::Crs,
+    georef::{GeoTransform, PolynomialOrder, ResidualReport, SplitLayout},
+    image::raw::header::{GeneralParameters, ImageHeader, Ref},
     ColorPalette, KapImageFile,
 };
-use tracing::{debug, info, instrument};
+use tracing::{debug, info, instrument, warn};
+
+use crate::import::{crs_from_epsg, header_projection, ImageGeoreference};
+
+mod geotiff;
+mod gpkg;
+mod import;
+mod kmz;
+mod mbtiles;
+mod pmtiles;
+pub mod tiles;
+mod vrt;
+mod world_file;
+
+pub use tiles::{kap_to_tiles, TileOptions};
 
+/// Options of [`kap_to_image`]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ImageOptions {
+    /// Make the pixels outside of the chart's `PLY` border transparent
+    pub clip: bool,
+    /// Write GeoTIFFs as RGB rather than with a color map
+    pub rgb: bool,
+    /// Write a world file (e.g. `.pgw`) and a `.prj` file next to the image
+    pub world_file: bool,
+    /// Write a GDAL VRT file with the `REF` records as ground control points next to the image
+    pub vrt: bool,
+}
+
+/// Returns the coordinate reference system of the model coordinates of `transform` (see
+/// [`GeoTransform::pixel_to_model`])
+///
+/// Charts with an unknown datum are assumed to be in WGS84.
+pub(crate) fn model_crs(bsb: &KapImageFile, transform: &GeoTransform) -> Crs {
+    let datum = bsb.header().datum_or_wgs84();
+    transform.projection().map_or_else(
+        || Crs::geographic(datum),
+        |projection| Crs::projected(datum, *projection),
+    )
+}
+
+/// Converts a BSB/KAP file into an image
+///
+/// The format is chosen from the extension of `output_name`: `.tif` and `.tiff` files are
+/// written as GeoTIFFs, `.kmz` files as KMZ ground overlays, everything else as PNG.
+///
+/// If `options.clip` is `true`, the pixels outside of the chart's `PLY` border are made
+/// transparent (or set to the GeoTIFF nodata index for paletted GeoTIFFs).
+///
+/// If `options.world_file` is `true`, a world file and a `.prj` file describing the
+/// georeferencing of the image are written next to it. If `options.vrt` is `true`, a GDAL VRT
+/// file listing the `REF` records as ground control points is written next to it, which lets
+/// GIS tools warp charts whose georeferencing is not affine.
 #[instrument]
-pub fn kap_to_image(bsb_file: &Path, output_name: &Path) -> Result<()> {
+pub fn kap_to_image(bsb_file: &Path, output_name: &Path, options: ImageOptions) -> Result<()> {
     let bsb = KapImageFile::from_path(bsb_file)?;
     debug!("Read bsb from file");
 
-    let as_rgb: Vec<_> = bsb.as_palette_iter(ColorPalette::Rgb)?.flatten().collect();
-    debug!("Length of RGB bsb data: {}", as_rgb.len());
+    let extension = output_name
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_default();
+    let paletted = match extension.as_str() {
+        "tif" | "tiff" => {
+            geotiff::write_geotiff(&bsb, output_name, options)?;
+            !options.rgb
+        }
+        "kmz" => {
+            if options.world_file || options.vrt {
+                warn!("World files and VRT files are not written for KMZ exports");
+            }
+            return kmz::write_kmz(&bsb, output_name, options);
+        }
+        _ => {
+            write_png(&bsb, output_name, options)?;
+            false
+        }
+    };
+    if options.world_file {
+        world_file::write_world_file(&bsb, output_name)?;
+    }
+    if options.vrt {
+        vrt::write_vrt(&bsb, output_name, options, paletted)?;
+    }
+    Ok(())
+}
 
+/// Writes a BSB/KAP chart as a PNG image
+fn write_png(bsb: &KapImageFile, output_name: &Path, options: ImageOptions) -> Result<()> {
     let output = File::options()
         .create(true)
         .write(true)
@@ -23,13 +110,7 @@ pub fn kap_to_image(bsb_file: &Path, output_name: &Path) -> Result<()> {
         .open(output_name)?;
 
     info!("Writing applied palatte image to {}", output_name.display());
-    let encoder = PngEncoder::new(output);
-    encoder.write_image(
-        &as_rgb,
-        bsb.width() as u32,
-        bsb.height() as u32,
-        image::ExtendedColorType::Rgb8,
-    )?;
+    encode_png(bsb, output, options)?;
     info!(
         "Successfully wrote palatte image to {}",
         output_name.display()
@@ -37,33 +118,76 @@ pub fn kap_to_image(bsb_file: &Path, output_name: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Encodes a BSB/KAP chart as a PNG image into `output`
+pub(crate) fn encode_png(
+    bsb: &KapImageFile,
+    output: impl Write,
+    options: ImageOptions,
+) -> Result<()> {
+    let (data, color_type): (Vec<_>, _) = if options.clip {
+        (
+            bsb.as_rgba_palette_iter(ColorPalette::Rgb)?
+                .flatten()
+                .collect(),
+            image::ExtendedColorType::Rgba8,
+        )
+    } else {
+        (
+            bsb.as_palette_iter(ColorPalette::Rgb)?.flatten().collect(),
+            image::ExtendedColorType::Rgb8,
+        )
+    };
+    debug!("Length of bsb color data: {}", data.len());
+
+    let encoder = PngEncoder::new(output);
+    encoder.write_image(&data, bsb.width() as u32, bsb.height() as u32, color_type)?;
+    Ok(())
+}
+
+/// Converts an image into a BSB/KAP file
+///
+/// If `refs` is not empty, the reference points are written to the header and the
+/// georeferencing polynomials are fitted from them. When `order` is [`None`], the highest
+/// order supported by the number of reference points is used.
+///
+/// Otherwise, the georeferencing is read from the GeoTIFF tags of `.tif` and `.tiff` images, or
+/// from a world file and `.prj` file next to the image. The reference points, the polynomials,
+/// the `PLY` border and the scale, projection and datum of the `KNP` record are then derived
+/// from it.
 #[instrument]
-pub fn image_to_kap(image_file: &Path, output_name: &Path) -> Result<()> {
-    let img = image::open(image_file).expect("Failed to open image");
-    let unique_colors = img
+pub fn image_to_kap(
+    image_file: &Path,
+    output_name: &Path,
+    refs: &[Ref],
+    order: Option<PolynomialOrder>,
+) -> Result<()> {
+    let img = image::open(image_file)?;
+    let palette: Vec<_> = img
         .pixels()
         .map(|(_, _, rgba)| (rgba[0], rgba[1], rgba[2]))
-        .collect::<HashSet<_>>();
-    debug!("Read {} unique colors from image", unique_colors.len());
-
-    debug_assert!(unique_colors.len() <= 127);
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    debug!("Read {} unique colors from image", palette.len());
 
     // The 3 depth types a BSB file can hold
-    let depth = match unique_colors.len() {
-        1 => 1,
+    let depth = match palette.len() {
+        0 | 1 => 1,
         n if n <= 15 => 4,
         n if n <= 127 => 7,
-        _ => unreachable!(),
+        n => bail!("The image has {n} colors, more than the 127 a BSB/KAP file can hold"),
     }
-    .try_into()
-    .unwrap();
+    .try_into()?;
 
-    let (width, height) = (
-        img.width().try_into().expect("width is too big"),
-        img.height().try_into().expect("height is too big"),
-    );
+    let (Ok(width), Ok(height)) = (u16::try_from(img.width()), u16::try_from(img.height())) else {
+        bail!(
+            "The image is {}x{} pixels, larger than the maximum BSB/KAP image size of 65535x65535",
+            img.width(),
+            img.height()
+        );
+    };
 
-    let header = ImageHeader::builder()
+    let mut header = ImageHeader::builder()
         .ifm(depth)
         .general_parameters(
             GeneralParameters::builder()
@@ -71,22 +195,410 @@ pub fn image_to_kap(image_file: &Path, output_name: &Path) -> Result<()> {
                 .image_width_height((width, height))
                 .build(),
         )
-        .rgb(unique_colors.into_iter().collect())
+        .rgb(palette.clone())
         .build();
 
-    let rgbs = header.rgb.as_ref().unwrap();
-    // let mut bitmap = BitMap::empty(width, height);
-    let mut raster_data = Vec::with_capacity((width * height) as usize);
-    for (x, y, p) in img.pixels() {
-        if let Some(index) = rgbs.iter().position(|rgb| rgb.eq(&(p[0], p[1], p[2]))) {
-            // BSB indexes start from 1
-            // bitmap.set_pixel_index(x as u16, y as u16, (index + 1) as u8);
-            raster_data.insert((x * y) as usize, (index + 1) as u8);
-        } else {
-            eprintln!("Unable to find pos for pixel");
+    if refs.is_empty() {
+        if let Some(georeference) = ImageGeoreference::read(image_file)? {
+            georeference.georeference(&mut header, order)?;
         }
+    } else {
+        let order = order.unwrap_or_else(|| PolynomialOrder::for_point_count(refs.len()));
+        info!(
+            "Fitting {order:?} polynomials to {} reference points",
+            refs.len()
+        );
+        header.reference_point_record = Some(refs.to_vec());
+        header.fit_polynomials(order)?;
+        header.update_err()?;
     }
+
+    // BSB indexes start from 1
+    let indices: HashMap<_, _> = palette.into_iter().zip(1u8..).collect();
+    let raster_data = img
+        .pixels()
+        .map(|(_, _, p)| {
+            indices
+                .get(&(p[0], p[1], p[2]))
+                .copied()
+                .unwrap_or_default()
+        })
+        .collect();
     let bsb = KapImageFile::new(header, raster_data)?;
-    bsb.into_file("test_assets/bsb_from_png.kap")?;
+    bsb.into_file(output_name)?;
+    Ok(())
+}
+
+/// The window extracted by [`crop_kap`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CropWindow {
+    /// The top left pixel and the size of the window, in pixels
+    Pixels {
+        /// The top left pixel `(x, y)`
+        origin: (u16, u16),
+        /// The width and height of the window
+        size: (u16, u16),
+    },
+    /// A bounding box in the chart datum, covered by the smallest enclosing pixel window
+    Coords {
+        /// The `(latitude, longitude)` of the south west corner
+        south_west: (f64, f64),
+        /// The `(latitude, longitude)` of the north east corner
+        north_east: (f64, f64),
+    },
+}
+
+/// Crops a BSB/KAP file to a window, keeping its georeferencing
+///
+/// The `REF` records and the polynomials follow the window, and the `PLY` border is clipped to
+/// it (see [`KapImageFile::crop`]).
+#[instrument]
+pub fn crop_kap(bsb_file: &Path, output_name: &Path, window: CropWindow) -> Result<()> {
+    let bsb = KapImageFile::from_path(bsb_file)?;
+    let cropped = match window {
+        CropWindow::Pixels { origin, size } => bsb.crop(origin, size)?,
+        CropWindow::Coords {
+            south_west,
+            north_east,
+        } => bsb.crop_to_coords(south_west, north_east)?,
+    };
+    info!(
+        "Cropped {}x{} pixels into {}x{} pixels",
+        bsb.width(),
+        bsb.height(),
+        cropped.width(),
+        cropped.height()
+    );
+    cropped.into_file(output_name)?;
+    Ok(())
+}
+
+/// Splits a BSB/KAP file into sub-charts (see [`KapImageFile::split`]), written into
+/// `output_dir` as `<name>_<i>.kap`, and returns their number
+#[instrument]
+pub fn split_kap(bsb_file: &Path, output_dir: &Path, layout: SplitLayout) -> Result<usize> {
+    let bsb = KapImageFile::from_path(bsb_file)?;
+    let Some(stem) = bsb_file.file_stem().and_then(|s| s.to_str()) else {
+        bail!("Invalid bsb file");
+    };
+    let charts = bsb.split(layout)?;
+    std::fs::create_dir_all(output_dir)?;
+    let count = charts.len();
+    for (i, chart) in charts.into_iter().enumerate() {
+        let output = output_dir.join(format!("{stem}_{}.kap", i + 1));
+        info!(
+            "Writing {}x{} pixels into {}",
+            chart.width(),
+            chart.height(),
+            output.display()
+        );
+        chart.into_file(output)?;
+    }
+    Ok(count)
+}
+
+/// Mosaics several BSB/KAP files into a single chart (see [`KapImageFile::mosaic`])
+///
+/// The mosaic is in the CRS of the EPSG code `epsg`, or in the CRS of the first chart if
+/// [`None`]. Geographic CRSs are replaced by a Mercator projection with a latitude of true scale
+/// at the centre of the first chart.
+#[instrument]
+pub fn mosaic_kaps(bsb_files: &[PathBuf], output_name: &Path, epsg: Option<u16>) -> Result<()> {
+    let charts = bsb_files
+        .iter()
+        .map(KapImageFile::from_path)
+        .collect::<Result<Vec<_>, _>>()?;
+    let Some(first) = charts.first() else {
+        bail!("No chart to mosaic");
+    };
+    let crs = match epsg {
+        Some(code) => crs_from_epsg(code).ok_or_else(|| anyhow!("Unsupported EPSG code {code}"))?,
+        None => first.header().crs()?,
+    };
+    let centre = first.geo_transform()?.pixel_to_coords((
+        f64::from(first.width()) / 2.0,
+        f64::from(first.height()) / 2.0,
+    ));
+    let crs = Crs::projected(crs.datum, header_projection(&crs, centre));
+    info!("Mosaicking {} charts in {}", charts.len(), crs.name());
+
+    let mosaic = KapImageFile::mosaic(&charts, &crs)?;
+    info!(
+        "Mosaic of {}x{} pixels with {} colors",
+        mosaic.width(),
+        mosaic.height(),
+        mosaic.header().rgb.as_ref().map_or(0, Vec::len)
+    );
+    mosaic.into_file(output_name)?;
     Ok(())
 }
+
+/// The position queried by [`locate`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Position {
+    /// A WGS84 `(latitude, longitude)`, as given by GPS receivers
+    Coords(f64, f64),
+    /// A position `(x, y)` in pixel coordinates, from the top left corner of the image
+    Pixel(f64, f64),
+}
+
+/// Where a position lies on a BSB/KAP file, as returned by [`locate`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    /// The position `(x, y)` in pixel coordinates
+    pub pixel: (f64, f64),
+    /// The WGS84 `(latitude, longitude)`
+    pub wgs84: (f64, f64),
+    /// The `(latitude, longitude)` in the chart datum
+    pub coords: (f64, f64),
+    /// The pixel of the image at the position, or [`None`] if it is outside of the image
+    pub sample: Option<PixelSample>,
+}
+
+/// An RGB color
+pub type Rgb = (u8, u8, u8);
+
+/// The pixel of a BSB/KAP file at a [`Location`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PixelSample {
+    /// Whether the pixel is inside the `PLY` border
+    pub inside_border: bool,
+    /// The palette index of the pixel
+    pub index: u8,
+    /// Each palette of the chart and the color of the pixel in it, if the palette has one
+    pub colors: Vec<(ColorPalette, Option<Rgb>)>,
+    /// The width and height of the pixel in metres, if they can be computed
+    pub size: Option<(f64, f64)>,
+}
+
+/// Locates `position` on a BSB/KAP file: its pixel and coordinates, whether it is inside the
+/// `PLY` border, the pixel index and its color in each palette, and the size of the pixels in
+/// metres
+///
+/// Coordinates are converted between WGS84 and the chart datum with the datum shift of the
+/// chart (see [`GeoTransform::wgs84_to_pixel`]).
+#[instrument]
+pub fn locate(bsb_file: &Path, position: Position) -> Result<Location> {
+    locate_on(&KapImageFile::from_path(bsb_file)?, position)
+}
+
+/// Locates `position` on a BSB/KAP chart (see [`locate`])
+fn locate_on(bsb: &KapImageFile, position: Position) -> Result<Location> {
+    let header = bsb.header();
+    let transform = bsb.geo_transform()?;
+    let pixel = match position {
+        Position::Coords(lat, lon) => transform.wgs84_to_pixel((lat, lon)),
+        Position::Pixel(x, y) => (x, y),
+    };
+    let coords = transform.pixel_to_coords(pixel);
+    let wgs84 = match position {
+        Position::Coords(lat, lon) => (lat, lon),
+        Position::Pixel(..) => transform.datum_shift().to_wgs84(coords),
+    };
+
+    let (width, height) = (f64::from(bsb.width()), f64::from(bsb.height()));
+    let sample = if (0.0..width).contains(&pixel.0) && (0.0..height).contains(&pixel.1) {
+        let index =
+            bsb.pixel_indices()[pixel.1 as usize * usize::from(bsb.width()) + pixel.0 as usize];
+        let colors = [
+            ColorPalette::Rgb,
+            ColorPalette::Day,
+            ColorPalette::Dsk,
+            ColorPalette::Ngt,
+            ColorPalette::Ngr,
+            ColorPalette::Gry,
+            ColorPalette::Prc,
+            ColorPalette::Prg,
+        ]
+        .into_iter()
+        .filter_map(|palette| {
+            let colors = header.palette(palette)?;
+            Some((
+                palette,
+                colors.get(usize::from(index).wrapping_sub(1)).copied(),
+            ))
+        })
+        .collect();
+        Some(PixelSample {
+            inside_border: header.border()?.is_none_or(|border| border.contains(pixel)),
+            index,
+            colors,
+            size: bsb
+                .pixel_size_at(pixel)
+                .inspect_err(|e| warn!("Cannot compute the pixel size: {e}"))
+                .ok(),
+        })
+    } else {
+        None
+    };
+    Ok(Location {
+        pixel,
+        wgs84,
+        coords,
+        sample,
+    })
+}
+
+/// Computes the residuals of the reference points of a BSB/KAP file and prints a report,
+/// flagging the points whose residuals exceed `tolerance` pixels
+#[instrument]
+pub fn check_residuals(bsb_file: &Path, tolerance: f64) -> Result<ResidualReport> {
+    let bsb = KapImageFile::from_path(bsb_file)?;
+    let report = bsb.header().residual_report(tolerance)?;
+    let refs = bsb
+        .header()
+        .reference_point_record
+        .as_deref()
+        .unwrap_or_default();
+
+    println!(
+        "{:>5} {:>7} {:>7} {:>10} {:>10} {:>12} {:>12}",
+        "REF", "x", "y", "dx (px)", "dy (px)", "dlat (deg)", "dlon (deg)"
+    );
+    for (i, (r, residual)) in refs.iter().zip(&report.residuals).enumerate() {
+        let flag = if report.flagged.contains(&i) {
+            " !"
+        } else {
+            ""
+        };
+        println!(
+            "{:>5} {:>7} {:>7} {:>10.4} {:>10.4} {:>12.8} {:>12.8}{flag}",
+            i + 1,
+            r.pixels.0,
+            r.pixels.1,
+            residual.x,
+            residual.y,
+            residual.lat,
+            residual.lon,
+        );
+    }
+    println!(
+        "RMS: {:.4} px, max: {:.4} px, {} of {} reference points exceed {tolerance} px",
+        report.rms_pixels(),
+        report.max_pixels(),
+        report.flagged.len(),
+        report.residuals.len()
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use libbsb::geodesy::Datum;
+
+    use super::*;
+
+    /// The header of the Chesapeake Bay test chart
+    const CHESAPEAKE_HEADER: &str = "../test_assets/12221_1_MapTech_testing_file_header.kap";
+
+    /// Returns the header of the Chesapeake Bay test chart
+    pub(crate) fn chesapeake_header() -> ImageHeader {
+        std::fs::read_to_string(CHESAPEAKE_HEADER)
+            .unwrap()
+            .parse()
+            .unwrap()
+    }
+
+    /// Returns the top left corner of the Chesapeake Bay test chart as a 116x98 pixels
+    /// checkerboard, inside a `PLY` border 10 pixels within the image
+    pub(crate) fn chesapeake_corner() -> KapImageFile {
+        let mut header = chesapeake_header();
+        header.general_parameters.image_width_height = (116, 98);
+        let transform = header.geo_transform().unwrap();
+        header.ply = Some(
+            [(10.0, 10.0), (106.0, 10.0), (106.0, 88.0), (10.0, 88.0)]
+                .map(|pixel| transform.pixel_to_coords(pixel))
+                .to_vec(),
+        );
+        let raster = (0..116 * 98)
+            .map(|i| 1 + u8::from((i % 116 / 4 + i / 116 / 4) % 2 == 0))
+            .collect();
+        KapImageFile::new(header, raster).unwrap()
+    }
+
+    /// Returns a chart of `header` whose pixels all have the index 1
+    pub(crate) fn blank_chart(header: ImageHeader) -> KapImageFile {
+        let (width, height) = header.general_parameters.image_width_height;
+        let size = usize::from(width) * usize::from(height);
+        KapImageFile::new(header, vec![1; size]).unwrap()
+    }
+
+    fn assert_near((a, b): (f64, f64), (c, d): (f64, f64), tolerance: f64) {
+        assert!(
+            (a - c).abs() < tolerance && (b - d).abs() < tolerance,
+            "{:?} != {:?}",
+            (a, b),
+            (c, d)
+        );
+    }
+
+    #[test]
+    fn locate_reference_points() {
+        let bsb = chesapeake_corner();
+        for r in bsb.header().reference_point_record.as_ref().unwrap() {
+            let pixel = (r.pixels.0 as f64, r.pixels.1 as f64);
+            let location = locate_on(&bsb, Position::Coords(r.coords.0, r.coords.1)).unwrap();
+            assert_near(location.pixel, pixel, 1.0);
+            assert_eq!(location.wgs84, r.coords);
+
+            let location = locate_on(&bsb, Position::Pixel(pixel.0, pixel.1)).unwrap();
+            assert_eq!(location.pixel, pixel);
+            assert_near(location.coords, r.coords, 1e-4);
+            assert_near(location.wgs84, r.coords, 1e-4);
+        }
+    }
+
+    #[test]
+    fn locate_wgs84_positions_on_nad27_charts() {
+        let mut header = chesapeake_header();
+        header.general_parameters.image_width_height = (16, 16);
+        header.dtm = None;
+        header
+            .detailed_parameters
+            .as_mut()
+            .unwrap()
+            .geodetic_datum_name = Some("NAD27".to_owned());
+        let bsb = blank_chart(header);
+        for r in bsb.header().reference_point_record.as_ref().unwrap() {
+            let pixel = (r.pixels.0 as f64, r.pixels.1 as f64);
+            let wgs84 = Datum::Nad27.transform_to_wgs84(r.coords);
+            let location = locate_on(&bsb, Position::Coords(wgs84.0, wgs84.1)).unwrap();
+            assert_near(location.pixel, pixel, 1.0);
+            assert_near(location.coords, r.coords, 1e-4);
+
+            let location = locate_on(&bsb, Position::Pixel(pixel.0, pixel.1)).unwrap();
+            assert_near(location.wgs84, wgs84, 1e-4);
+        }
+    }
+
+    #[test]
+    fn locate_pixels_of_the_image() {
+        let bsb = chesapeake_corner();
+        let sample = |x, y| locate_on(&bsb, Position::Pixel(x, y)).unwrap().sample;
+
+        let inside = sample(50.5, 50.5).unwrap();
+        assert!(inside.inside_border);
+        assert_eq!(inside.index, 2);
+        assert_eq!(inside.colors[0], (ColorPalette::Rgb, Some((255, 255, 255))));
+        let (width, height) = inside.size.unwrap();
+        assert!((width - 8.0).abs() < 0.5 && (height - 8.0).abs() < 0.5);
+
+        let outside_border = sample(0.5, 0.5).unwrap();
+        assert!(!outside_border.inside_border);
+        assert_eq!(outside_border.index, 2);
+        assert_eq!(
+            outside_border.colors[0],
+            (ColorPalette::Rgb, Some((255, 255, 255)))
+        );
+
+        let outside_border = sample(4.5, 0.5).unwrap();
+        assert_eq!(outside_border.index, 1);
+        assert_eq!(
+            outside_border.colors[0],
+            (ColorPalette::Rgb, Some((0, 0, 0)))
+        );
+
+        assert_eq!(sample(116.5, 50.5), None);
+        assert_eq!(sample(-0.5, 50.5), None);
+    }
+}
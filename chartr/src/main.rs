@@ -1,5 +1,13 @@
-use chartr::{image_to_kap, kap_to_image};
-use std::path::PathBuf;
+use chartr::{
+    check_residuals, crop_kap, image_to_kap, kap_to_image, kap_to_tiles, locate, mosaic_kaps,
+    split_kap, CropWindow, ImageOptions, Location, Position, TileOptions,
+};
+use libbsb::{
+    georef::{PolynomialOrder, SplitLayout},
+    image::raw::header::Ref,
+    ColorPalette,
+};
+use std::{ops::RangeInclusive, path::PathBuf};
 use tracing::{info, Level};
 
 use anyhow::{bail, Result};
@@ -30,12 +38,24 @@ enum Commands {
         // #[arg(short, long)]
         bsb_file: PathBuf,
 
-        /// The output file name
+        /// The output file name (`.png`, `.tif` for a GeoTIFF or `.kmz` for a KMZ ground overlay)
         #[arg(short, long)]
         output: Option<PathBuf>,
+        /// Make the pixels outside of the chart's PLY border transparent
+        #[arg(long)]
+        clip: bool,
+        /// Write GeoTIFFs as RGB rather than with a color map
+        #[arg(long)]
+        rgb: bool,
+        /// Write a world file and a .prj file next to the image
+        #[arg(long)]
+        world_file: bool,
+        /// Write a GDAL VRT file with the REF records as ground control points next to the image
+        #[arg(long)]
+        vrt: bool,
     },
 
-    /// converts a PNG image to a BSB/KAP file
+    /// converts an image to a BSB/KAP file, georeferenced from its GeoTIFF tags or world file
     #[command(name = "imgkap")]
     ImageToBsb {
         /// The image
@@ -43,8 +63,244 @@ enum Commands {
         /// The output file name
         #[arg(short, long)]
         output: Option<PathBuf>,
+        /// A reference point in the form `x,y,lat,lon` (can be repeated)
+        #[arg(short, long = "ref", value_parser = parse_ref)]
+        refs: Vec<Ref>,
+        /// The order of the fitted georeferencing polynomials (1 or 2)
+        #[arg(long, value_parser = parse_order)]
+        order: Option<PolynomialOrder>,
+    },
+
+    /// renders a BSB/KAP image into a Web Mercator tile pyramid
+    #[command(name = "tiles")]
+    Tiles {
+        /// The kap image
+        bsb_file: PathBuf,
+        /// The output directory, or a `.mbtiles`, `.gpkg` or `.pmtiles` file
+        output: PathBuf,
+        /// The zoom levels to render, e.g. `8..16` or `12`
+        #[arg(short, long, default_value = "8..16", value_parser = parse_zoom)]
+        zoom: RangeInclusive<u8>,
+        /// The palette to render (rgb, day, dsk, ngt, ngr, gry, prc or prg)
+        #[arg(short, long, default_value = "rgb", value_parser = parse_palette)]
+        palette: ColorPalette,
+        /// Number the tile rows from the south (TMS) rather than from the north (XYZ)
+        #[arg(long)]
+        tms: bool,
+        /// Render GeoPackage tiles in the native CRS of the chart rather than in EPSG:3857
+        #[arg(long)]
+        native_crs: bool,
+    },
+
+    /// crops a BSB/KAP image to a pixel window or a bounding box, keeping its georeferencing
+    #[command(name = "crop")]
+    Crop {
+        /// The kap image
+        bsb_file: PathBuf,
+        /// The output file name
+        output: PathBuf,
+        /// The pixel window in the form `x,y,width,height`
+        #[arg(long, value_parser = parse_pixel_window, conflicts_with = "bbox")]
+        pixels: Option<CropWindow>,
+        /// The bounding box in the form `south,west,north,east`, in the chart datum
+        #[arg(long, value_parser = parse_bbox)]
+        bbox: Option<CropWindow>,
+    },
+
+    /// splits a BSB/KAP image into georeferenced sub-charts
+    #[command(name = "split")]
+    Split {
+        /// The kap image
+        bsb_file: PathBuf,
+        /// The output directory
+        output: PathBuf,
+        /// The grid of sub-charts in the form `columnsxrows`, e.g. `2x2`
+        #[arg(long, value_parser = parse_grid, conflicts_with = "max_size")]
+        grid: Option<SplitLayout>,
+        /// The largest width and height of the sub-charts, in pixels
+        #[arg(long)]
+        max_size: Option<u16>,
+    },
+
+    /// locates a position on a BSB/KAP image, given either as coordinates or as a pixel
+    #[command(name = "locate")]
+    Locate {
+        /// The kap image
+        bsb_file: PathBuf,
+        /// The WGS84 latitude, as given by GPS receivers
+        #[arg(long, allow_negative_numbers = true, requires = "lon")]
+        lat: Option<f64>,
+        /// The WGS84 longitude, as given by GPS receivers
+        #[arg(long, allow_negative_numbers = true, requires = "lat")]
+        lon: Option<f64>,
+        /// The pixel in the form `x,y`
+        #[arg(long, value_parser = parse_pixel, conflicts_with_all = ["lat", "lon"])]
+        pixel: Option<Position>,
     },
+
+    /// mosaics several BSB/KAP images into a single chart
+    #[command(name = "mosaic")]
+    Mosaic {
+        /// The kap images, in order of precedence where they overlap
+        #[arg(required = true, num_args = 2..)]
+        bsb_files: Vec<PathBuf>,
+        /// The output file name
+        #[arg(short, long)]
+        output: PathBuf,
+        /// The EPSG code of the CRS of the mosaic (defaults to the CRS of the first image)
+        #[arg(long)]
+        epsg: Option<u16>,
+    },
+
+    /// checks the residuals of the reference points of a BSB/KAP image
+    #[command(name = "check")]
+    CheckResiduals {
+        /// The kap image
+        bsb_file: PathBuf,
+        /// The largest accepted residual, in pixels
+        #[arg(short, long, default_value_t = 1.0)]
+        tolerance: f64,
+    },
+}
+
+fn parse_ref(s: &str) -> Result<Ref, String> {
+    let values: Vec<_> = s.split(',').map(str::trim).collect();
+    let [x, y, lat, lon] = values.as_slice() else {
+        return Err("expected `x,y,lat,lon`".to_owned());
+    };
+    let pixel = |v: &str| {
+        v.parse::<usize>()
+            .map_err(|e| format!("invalid pixel `{v}`: {e}"))
+    };
+    let coord = |v: &str| {
+        v.parse::<f64>()
+            .map_err(|e| format!("invalid coordinate `{v}`: {e}"))
+    };
+    Ok(Ref::builder()
+        .pixels((pixel(x)?, pixel(y)?))
+        .coords((coord(lat)?, coord(lon)?))
+        .build())
+}
+
+/// Parses a comma separated list of `N` values
+fn parse_values<T: std::str::FromStr, const N: usize>(s: &str, form: &str) -> Result<[T; N], String>
+where
+    T::Err: std::fmt::Display,
+{
+    let values = s
+        .split(',')
+        .map(|v| {
+            v.trim()
+                .parse::<T>()
+                .map_err(|e| format!("invalid value `{v}`: {e}"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    values.try_into().map_err(|_| format!("expected `{form}`"))
+}
+
+fn parse_pixel_window(s: &str) -> Result<CropWindow, String> {
+    let [x, y, width, height] = parse_values(s, "x,y,width,height")?;
+    Ok(CropWindow::Pixels {
+        origin: (x, y),
+        size: (width, height),
+    })
 }
+
+fn parse_bbox(s: &str) -> Result<CropWindow, String> {
+    let [south, west, north, east] = parse_values(s, "south,west,north,east")?;
+    Ok(CropWindow::Coords {
+        south_west: (south, west),
+        north_east: (north, east),
+    })
+}
+
+fn parse_pixel(s: &str) -> Result<Position, String> {
+    let [x, y] = parse_values(s, "x,y")?;
+    Ok(Position::Pixel(x, y))
+}
+
+fn parse_grid(s: &str) -> Result<SplitLayout, String> {
+    let Some((columns, rows)) = s.split_once('x') else {
+        return Err("expected `columnsxrows`".to_owned());
+    };
+    let count = |v: &str| {
+        v.trim()
+            .parse::<u16>()
+            .map_err(|e| format!("invalid count `{v}`: {e}"))
+    };
+    Ok(SplitLayout::Grid {
+        columns: count(columns)?,
+        rows: count(rows)?,
+    })
+}
+
+fn parse_order(s: &str) -> Result<PolynomialOrder, String> {
+    s.parse::<usize>()
+        .map_err(|e| e.to_string())?
+        .try_into()
+        .map_err(|e: libbsb::Error| e.to_string())
+}
+
+fn parse_zoom(s: &str) -> Result<RangeInclusive<u8>, String> {
+    let level = |v: &str| {
+        v.trim()
+            .parse::<u8>()
+            .map_err(|e| format!("invalid zoom level `{v}`: {e}"))
+    };
+    match s.split_once("..") {
+        Some((start, end)) => Ok(level(start)?..=level(end.trim_start_matches('='))?),
+        None => {
+            let level = level(s)?;
+            Ok(level..=level)
+        }
+    }
+}
+
+fn parse_palette(s: &str) -> Result<ColorPalette, String> {
+    match s.to_ascii_lowercase().as_str() {
+        "rgb" => Ok(ColorPalette::Rgb),
+        "day" => Ok(ColorPalette::Day),
+        "dsk" => Ok(ColorPalette::Dsk),
+        "ngt" => Ok(ColorPalette::Ngt),
+        "ngr" => Ok(ColorPalette::Ngr),
+        "gry" => Ok(ColorPalette::Gry),
+        "prc" => Ok(ColorPalette::Prc),
+        "prg" => Ok(ColorPalette::Prg),
+        _ => Err(format!(
+            "unknown palette `{s}`, expected one of rgb, day, dsk, ngt, ngr, gry, prc or prg"
+        )),
+    }
+}
+
+fn print_location(location: &Location) {
+    let Location {
+        pixel,
+        wgs84,
+        coords,
+        sample,
+    } = location;
+    println!("Pixel: {:.3}, {:.3}", pixel.0, pixel.1);
+    println!("WGS84: {:.8}, {:.8}", wgs84.0, wgs84.1);
+    println!("Chart datum: {:.8}, {:.8}", coords.0, coords.1);
+    let Some(sample) = sample else {
+        println!("Outside of the image");
+        return;
+    };
+    let inside = if sample.inside_border { "yes" } else { "no" };
+    println!("Inside the border: {inside}");
+    println!("Index: {}", sample.index);
+    for (palette, color) in &sample.colors {
+        let name = format!("{palette:?}").to_uppercase();
+        match color {
+            Some((r, g, b)) => println!("{name}: #{r:02x}{g:02x}{b:02x} ({r}, {g}, {b})"),
+            None => println!("{name}: no color"),
+        }
+    }
+    if let Some((x, y)) = sample.size {
+        println!("Pixel size: {x:.3} m x {y:.3} m");
+    }
+}
+
 fn main() -> Result<()> {
     let cli = Cli::parse();
     let level = match cli.verbosity {
@@ -61,7 +317,14 @@ fn main() -> Result<()> {
         .init();
 
     match cli.command {
-        Commands::BsbToImage { bsb_file, output } => {
+        Commands::BsbToImage {
+            bsb_file,
+            output,
+            clip,
+            rgb,
+            world_file,
+            vrt,
+        } => {
             let output = match output {
                 Some(o) => o,
                 None => {
@@ -79,9 +342,23 @@ fn main() -> Result<()> {
                     output
                 }
             };
-            kap_to_image(&bsb_file, &output)?;
+            kap_to_image(
+                &bsb_file,
+                &output,
+                ImageOptions {
+                    clip,
+                    rgb,
+                    world_file,
+                    vrt,
+                },
+            )?;
         }
-        Commands::ImageToBsb { img_file, output } => {
+        Commands::ImageToBsb {
+            img_file,
+            output,
+            refs,
+            order,
+        } => {
             let output = match output {
                 Some(o) => o,
                 None => {
@@ -99,7 +376,79 @@ fn main() -> Result<()> {
                     output
                 }
             };
-            image_to_kap(&img_file, &output)?;
+            image_to_kap(&img_file, &output, &refs, order)?;
+        }
+        Commands::Tiles {
+            bsb_file,
+            output,
+            zoom,
+            palette,
+            tms,
+            native_crs,
+        } => {
+            let options = TileOptions {
+                zoom,
+                palette,
+                tms,
+                native_crs,
+            };
+            let count = kap_to_tiles(&bsb_file, &output, &options)?;
+            info!("Wrote {count} tiles");
+        }
+        Commands::Crop {
+            bsb_file,
+            output,
+            pixels,
+            bbox,
+        } => {
+            let Some(window) = pixels.or(bbox) else {
+                bail!("Either --pixels or --bbox is required");
+            };
+            crop_kap(&bsb_file, &output, window)?;
+        }
+        Commands::Split {
+            bsb_file,
+            output,
+            grid,
+            max_size,
+        } => {
+            let Some(layout) = grid.or(max_size.map(SplitLayout::MaxSize)) else {
+                bail!("Either --grid or --max-size is required");
+            };
+            let count = split_kap(&bsb_file, &output, layout)?;
+            info!("Wrote {count} charts");
+        }
+        Commands::Locate {
+            bsb_file,
+            lat,
+            lon,
+            pixel,
+        } => {
+            let position = match (lat, lon, pixel) {
+                (Some(lat), Some(lon), _) => Position::Coords(lat, lon),
+                (_, _, Some(pixel)) => pixel,
+                _ => bail!("Either --lat and --lon or --pixel is required"),
+            };
+            print_location(&locate(&bsb_file, position)?);
+        }
+        Commands::Mosaic {
+            bsb_files,
+            output,
+            epsg,
+        } => {
+            mosaic_kaps(&bsb_files, &output, epsg)?;
+        }
+        Commands::CheckResiduals {
+            bsb_file,
+            tolerance,
+        } => {
+            let report = check_residuals(&bsb_file, tolerance)?;
+            if !report.is_ok() {
+                bail!(
+                    "{} reference points exceed the tolerance",
+                    report.flagged.len()
+                );
+            }
         }
     }
     Ok(())
@@ -0,0 +1,169 @@
+//! MBTiles (SQLite) output for tile pyramids
+
+use std::{fs, path::Path};
+
+use anyhow::Result;
+use rusqlite::{params, Connection};
+use tracing::debug;
+
+use crate::tiles::{TileOptions, TileRenderer};
+
+/// Writes the tiles of a chart into an MBTiles 1.3 file, replacing any existing file
+///
+/// The metadata table is filled from the chart header: the name from the chart name, the
+/// bounds from the `PLY` border and the attribution from the copyright record.
+pub(crate) fn write_mbtiles(
+    renderer: &TileRenderer,
+    output_name: &Path,
+    options: &TileOptions,
+) -> Result<usize> {
+    if output_name.exists() {
+        fs::remove_file(output_name)?;
+    }
+    let mut connection = Connection::open(output_name)?;
+    connection.execute_batch(
+        "CREATE TABLE metadata (name TEXT, value TEXT);
+         CREATE TABLE tiles (zoom_level INTEGER, tile_column INTEGER, tile_row INTEGER, tile_data BLOB);
+         CREATE UNIQUE INDEX tile_index ON tiles (zoom_level, tile_column, tile_row);",
+    )?;
+
+    let header = renderer.chart().header();
+    let coverage = renderer.coverage();
+    let (lat, lon) = coverage.centre();
+    let name = header
+        .general_parameters
+        .chart_name
+        .clone()
+        .or_else(|| {
+            output_name
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+        })
+        .unwrap_or_default();
+    let mut metadata = vec![
+        ("name", name),
+        ("format", "png".to_owned()),
+        ("type", "overlay".to_owned()),
+        ("version", "1".to_owned()),
+        (
+            "bounds",
+            format!(
+                "{},{},{},{}",
+                coverage.west, coverage.south, coverage.east, coverage.north
+            ),
+        ),
+        ("center", format!("{lon},{lat},{}", options.zoom.start())),
+        ("minzoom", options.zoom.start().to_string()),
+        ("maxzoom", options.zoom.end().to_string()),
+    ];
+    if let Some(copyright) = &header.copyright_record {
+        metadata.push(("attribution", copyright.clone()));
+    }
+
+    let transaction = connection.transaction()?;
+    for (name, value) in &metadata {
+        transaction.execute(
+            "INSERT INTO metadata (name, value) VALUES (?1, ?2)",
+            params![name, value],
+        )?;
+    }
+    let mut count = 0;
+    {
+        let mut insert = transaction.prepare(
+            "INSERT INTO tiles (zoom_level, tile_column, tile_row, tile_data) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for tile in renderer.tiles(options.zoom.clone()) {
+            let Some(png) = renderer.render_png(tile)? else {
+                continue;
+            };
+            // MBTiles rows are numbered from the south
+            insert.execute(params![tile.z, tile.x, tile.tms_y(), png])?;
+            count += 1;
+        }
+    }
+    transaction.commit()?;
+    debug!("Wrote {} metadata entries", metadata.len());
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeSet, HashMap};
+
+    use libbsb::ColorPalette;
+    use mktemp::Temp;
+
+    use super::*;
+    use crate::tests::chesapeake_corner;
+
+    #[test]
+    fn mbtiles_metadata_and_tile_rows() -> Result<()> {
+        let bsb = chesapeake_corner();
+        let renderer = TileRenderer::new(&bsb, ColorPalette::Rgb)?;
+        let options = TileOptions {
+            zoom: 13..=15,
+            ..TileOptions::default()
+        };
+        let output = Temp::new_file()?;
+        let count = write_mbtiles(&renderer, &output, &options)?;
+
+        let connection = Connection::open(&output)?;
+        let metadata: HashMap<String, String> = connection
+            .prepare("SELECT name, value FROM metadata")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+        assert_eq!(metadata["name"], "CHESAPEAKE BAY ENTRANCE");
+        assert_eq!(metadata["format"], "png");
+        assert_eq!(metadata["minzoom"], "13");
+        assert_eq!(metadata["maxzoom"], "15");
+        assert_eq!(
+            Some(&metadata["attribution"]),
+            bsb.header().copyright_record.as_ref()
+        );
+        assert!(metadata["attribution"].contains("NOAA"));
+
+        // the bounds and centre of the `PLY` border
+        let parse = |value: &str| {
+            value
+                .split(',')
+                .map(str::parse)
+                .collect::<Result<Vec<f64>, _>>()
+        };
+        let ply = bsb.header().ply.as_ref().unwrap();
+        let (lats, lons): (Vec<_>, Vec<_>) = ply.iter().copied().unzip();
+        let min = |values: &[f64]| values.iter().copied().fold(f64::MAX, f64::min);
+        let max = |values: &[f64]| values.iter().copied().fold(f64::MIN, f64::max);
+        let bounds = parse(&metadata["bounds"])?;
+        let expected = [min(&lons), min(&lats), max(&lons), max(&lats)];
+        for (value, expected) in bounds.iter().zip(expected) {
+            // NAD83 and WGS84 differ by less than a metre here
+            assert!(
+                (value - expected).abs() < 1e-5,
+                "{bounds:?} != {expected:?}"
+            );
+        }
+        let center = parse(&metadata["center"])?;
+        assert!((center[0] - f64::midpoint(bounds[0], bounds[2])).abs() < 1e-9);
+        assert!((center[1] - f64::midpoint(bounds[1], bounds[3])).abs() < 1e-9);
+        assert_eq!(center[2], 13.0);
+
+        // the rows are numbered from the south
+        let rows: BTreeSet<(u8, u32, u32)> = connection
+            .prepare("SELECT zoom_level, tile_column, tile_row FROM tiles")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<Result<_, _>>()?;
+        let expected: BTreeSet<_> = renderer
+            .tiles(options.zoom.clone())
+            .filter(|&tile| renderer.render(tile).is_some())
+            .map(|tile| (tile.z, tile.x, (1 << tile.z) - 1 - tile.y))
+            .collect();
+        assert_eq!(rows.len(), count);
+        assert_eq!(rows, expected);
+        // at zoom level 13, the chart lies in a single tile
+        assert_eq!(renderer.tile_count(13..=13), 1);
+        let tile = renderer.tiles(13..=13).next().unwrap();
+        assert!(rows.contains(&(13, tile.x, 8191 - tile.y)));
+        assert_ne!(tile.y, 8191 - tile.y);
+        Ok(())
+    }
+}
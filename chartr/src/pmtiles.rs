@@ -0,0 +1,468 @@
+//! PMTiles v3 single-file archives for tile pyramids
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    fs::File,
+    hash::{Hash, Hasher},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::{bail, Result};
+use flate2::{write::GzEncoder, Compression};
+use serde_json::{json, Map, Value};
+use tracing::debug;
+
+use crate::tiles::{TileId, TileOptions, TileRenderer};
+
+/// The length of the fixed-size header
+const HEADER_LEN: usize = 127;
+/// The header and the root directory must fit in the first 16 KiB of the archive
+const MAX_ROOT_LEN: usize = 16_384 - HEADER_LEN;
+/// The maximum number of tiles covering a chart, since the ids of all of them are sorted before
+/// rendering
+const MAX_TILES: u64 = 1 << 24;
+/// The initial number of entries per leaf directory
+const LEAF_SIZE: usize = 4096;
+
+/// `Compression` values of the header
+const COMPRESSION_NONE: u8 = 1;
+const COMPRESSION_GZIP: u8 = 2;
+/// `TileType` value of PNG tiles
+const TILE_TYPE_PNG: u8 = 2;
+
+/// An entry of a PMTiles directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Entry {
+    tile_id: u64,
+    offset: u64,
+    length: u64,
+    /// The number of consecutive tile ids sharing the same data, or 0 for leaf directories
+    run_length: u64,
+}
+
+/// Returns the PMTiles id of a tile: its position along the Hilbert curves of the successive
+/// zoom levels
+fn tile_id(tile: TileId) -> u64 {
+    // the number of tiles in the lower zoom levels
+    let base = ((1u64 << (2 * u32::from(tile.z))) - 1) / 3;
+    let n = 1u64 << tile.z;
+    let (mut x, mut y) = (u64::from(tile.x), u64::from(tile.y));
+    let mut d = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = u64::from(x & s > 0);
+        let ry = u64::from(y & s > 0);
+        d += s * s * ((3 * rx) ^ ry);
+        if ry == 0 {
+            if rx == 1 {
+                x = n - 1 - x;
+                y = n - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    base + d
+}
+
+/// Appends an unsigned LEB128 varint
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// Compresses internal data (directories and metadata) with gzip
+fn gzip(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+/// Serializes and compresses a directory
+///
+/// The fields are stored column by column: delta-encoded tile ids, run lengths, lengths, and
+/// offsets, an offset of 0 meaning the data directly follows the data of the previous entry.
+fn serialize_directory(entries: &[Entry]) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    write_varint(&mut buf, entries.len() as u64);
+    let mut last_id = 0;
+    for entry in entries {
+        write_varint(&mut buf, entry.tile_id - last_id);
+        last_id = entry.tile_id;
+    }
+    for entry in entries {
+        write_varint(&mut buf, entry.run_length);
+    }
+    for entry in entries {
+        write_varint(&mut buf, entry.length);
+    }
+    for (i, entry) in entries.iter().enumerate() {
+        let contiguous = i > 0 && {
+            let previous = entries[i - 1];
+            entry.offset == previous.offset + previous.length
+        };
+        write_varint(&mut buf, if contiguous { 0 } else { entry.offset + 1 });
+    }
+    gzip(&buf)
+}
+
+/// Serializes the root directory, splitting the entries into leaf directories if it does not
+/// fit in the first 16 KiB of the archive
+///
+/// Returns the root directory and the concatenated leaf directories.
+fn build_directories(entries: &[Entry]) -> Result<(Vec<u8>, Vec<u8>)> {
+    let root = serialize_directory(entries)?;
+    if root.len() <= MAX_ROOT_LEN {
+        return Ok((root, Vec::new()));
+    }
+    let mut leaf_size = LEAF_SIZE;
+    loop {
+        let mut leaves = Vec::new();
+        let mut root_entries = Vec::new();
+        for chunk in entries.chunks(leaf_size) {
+            let leaf = serialize_directory(chunk)?;
+            root_entries.push(Entry {
+                tile_id: chunk[0].tile_id,
+                offset: leaves.len() as u64,
+                length: leaf.len() as u64,
+                run_length: 0,
+            });
+            leaves.extend(leaf);
+        }
+        let root = serialize_directory(&root_entries)?;
+        if root.len() <= MAX_ROOT_LEN {
+            return Ok((root, leaves));
+        }
+        leaf_size *= 2;
+    }
+}
+
+/// Builds the JSON metadata of the archive from the chart header
+fn metadata(renderer: &TileRenderer, output_name: &Path) -> Value {
+    let header = renderer.chart().header();
+    let name = header
+        .general_parameters
+        .chart_name
+        .clone()
+        .or_else(|| {
+            output_name
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+        })
+        .unwrap_or_default();
+    let mut metadata = Map::new();
+    metadata.insert("name".to_owned(), json!(name));
+    metadata.insert("format".to_owned(), json!("png"));
+    metadata.insert("type".to_owned(), json!("overlay"));
+    if let Some(number) = &header.general_parameters.chart_number {
+        metadata.insert("number".to_owned(), json!(number));
+    }
+    if let Some(scale) = header
+        .detailed_parameters
+        .as_ref()
+        .and_then(|knp| knp.chart_scale)
+    {
+        metadata.insert("scale".to_owned(), json!(scale));
+    }
+    if let Some(ced) = &header.ced {
+        let mut edition = Map::new();
+        if let Some(source_edition) = ced.source_edition {
+            edition.insert("source_edition".to_owned(), json!(source_edition));
+        }
+        if let Some(raster_edition) = ced.raster_edition {
+            edition.insert("raster_edition".to_owned(), json!(raster_edition));
+        }
+        if let Some(date) = ced.edition_date {
+            edition.insert("edition_date".to_owned(), json!(date.to_string()));
+        }
+        metadata.insert("edition".to_owned(), Value::Object(edition));
+    }
+    if let Some(copyright) = &header.copyright_record {
+        metadata.insert("attribution".to_owned(), json!(copyright));
+    }
+    Value::Object(metadata)
+}
+
+/// Converts degrees into the fixed-point (1e-7 degrees) representation of the header
+fn e7(degrees: f64) -> i32 {
+    (degrees * 1e7).round() as i32
+}
+
+/// Writes the tiles of a chart into a PMTiles v3 archive, replacing any existing file
+///
+/// Tiles with identical contents are stored once. The JSON metadata holds the name, number,
+/// scale and edition of the chart, and the bounds in the header are those of the `PLY` border.
+pub(crate) fn write_pmtiles(
+    renderer: &TileRenderer,
+    output_name: &Path,
+    options: &TileOptions,
+) -> Result<usize> {
+    let tile_count = renderer.tile_count(options.zoom.clone());
+    if tile_count > MAX_TILES {
+        bail!(
+            "The chart covers {tile_count} tiles at zoom levels {}..{}, more than the {MAX_TILES} this writer supports: lower the maximum zoom level",
+            options.zoom.start(),
+            options.zoom.end()
+        );
+    }
+    let mut tiles: Vec<_> = renderer
+        .tiles(options.zoom.clone())
+        .map(|tile| (tile_id(tile), tile))
+        .collect();
+    tiles.sort_unstable_by_key(|&(id, _)| id);
+
+    let mut data = Vec::new();
+    let mut entries: Vec<Entry> = Vec::new();
+    // the offsets and lengths of the tile contents already written, by hash
+    let mut contents: HashMap<u64, Vec<(u64, u64)>> = HashMap::new();
+    let mut count = 0;
+    for (id, tile) in tiles {
+        let Some(png) = renderer.render_png(tile)? else {
+            continue;
+        };
+        count += 1;
+        let mut hasher = DefaultHasher::new();
+        png.hash(&mut hasher);
+        let candidates = contents.entry(hasher.finish()).or_default();
+        let existing = candidates
+            .iter()
+            .copied()
+            .find(|&(offset, length)| data[offset as usize..(offset + length) as usize] == png[..]);
+        let (offset, length) = existing.unwrap_or_else(|| {
+            let position = (data.len() as u64, png.len() as u64);
+            data.extend_from_slice(&png);
+            candidates.push(position);
+            position
+        });
+        match entries.last_mut() {
+            Some(last) if last.offset == offset && last.tile_id + last.run_length == id => {
+                last.run_length += 1;
+            }
+            _ => entries.push(Entry {
+                tile_id: id,
+                offset,
+                length,
+                run_length: 1,
+            }),
+        }
+    }
+    let unique_contents: usize = contents.values().map(Vec::len).sum();
+    debug!(
+        "{count} tiles, {} entries, {unique_contents} unique tiles",
+        entries.len()
+    );
+
+    let (root, leaves) = build_directories(&entries)?;
+    let metadata = gzip(serde_json::to_string(&metadata(renderer, output_name))?.as_bytes())?;
+    let root_offset = HEADER_LEN as u64;
+    let metadata_offset = root_offset + root.len() as u64;
+    let leaves_offset = metadata_offset + metadata.len() as u64;
+    let data_offset = leaves_offset + leaves.len() as u64;
+
+    let coverage = renderer.coverage();
+    let (centre_lat, centre_lon) = coverage.centre();
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(b"PMTiles");
+    header.push(3);
+    for value in [
+        root_offset,
+        root.len() as u64,
+        metadata_offset,
+        metadata.len() as u64,
+        leaves_offset,
+        leaves.len() as u64,
+        data_offset,
+        data.len() as u64,
+        count as u64,
+        entries.len() as u64,
+        unique_contents as u64,
+    ] {
+        header.extend_from_slice(&value.to_le_bytes());
+    }
+    header.extend_from_slice(&[
+        // clustered: the tile data is ordered by tile id
+        1,
+        COMPRESSION_GZIP,
+        COMPRESSION_NONE,
+        TILE_TYPE_PNG,
+        *options.zoom.start(),
+        *options.zoom.end(),
+    ]);
+    for degrees in [coverage.west, coverage.south, coverage.east, coverage.north] {
+        header.extend_from_slice(&e7(degrees).to_le_bytes());
+    }
+    header.push(*options.zoom.start());
+    header.extend_from_slice(&e7(centre_lon).to_le_bytes());
+    header.extend_from_slice(&e7(centre_lat).to_le_bytes());
+    debug_assert_eq!(header.len(), HEADER_LEN);
+
+    let mut file = BufWriter::new(File::create(output_name)?);
+    for section in [&header, &root, &metadata, &leaves, &data] {
+        file.write_all(section)?;
+    }
+    file.flush()?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+
+    use super::*;
+
+    /// Decompresses and deserializes a directory
+    fn deserialize_directory(data: &[u8]) -> Vec<Entry> {
+        let mut buf = Vec::new();
+        GzDecoder::new(data).read_to_end(&mut buf).unwrap();
+        let mut bytes = buf.into_iter();
+        let mut read_varint = || {
+            let (mut value, mut shift) = (0, 0);
+            loop {
+                let byte = bytes.next().unwrap();
+                value |= u64::from(byte & 0x7f) << shift;
+                if byte & 0x80 == 0 {
+                    return value;
+                }
+                shift += 7;
+            }
+        };
+        let len = read_varint() as usize;
+        let mut entries = vec![
+            Entry {
+                tile_id: 0,
+                offset: 0,
+                length: 0,
+                run_length: 0,
+            };
+            len
+        ];
+        let mut last_id = 0;
+        for entry in &mut entries {
+            entry.tile_id = last_id + read_varint();
+            last_id = entry.tile_id;
+        }
+        for entry in &mut entries {
+            entry.run_length = read_varint();
+        }
+        for entry in &mut entries {
+            entry.length = read_varint();
+        }
+        for i in 0..len {
+            entries[i].offset = match read_varint() {
+                0 => entries[i - 1].offset + entries[i - 1].length,
+                offset => offset - 1,
+            };
+        }
+        entries
+    }
+
+    #[test]
+    fn tile_ids_follow_hilbert_curves() {
+        let id = |z, x, y| tile_id(TileId { z, x, y });
+        assert_eq!(id(0, 0, 0), 0);
+        assert_eq!(id(1, 0, 0), 1);
+        assert_eq!(id(1, 0, 1), 2);
+        assert_eq!(id(1, 1, 1), 3);
+        assert_eq!(id(1, 1, 0), 4);
+        assert_eq!(id(2, 0, 0), 5);
+        assert_eq!(id(2, 3, 0), 20);
+        assert_eq!(id(12, 3423, 1763), 19_078_479);
+    }
+
+    #[test]
+    fn varints() {
+        let mut buf = Vec::new();
+        for value in [0, 1, 127, 128, 300, u64::from(u32::MAX)] {
+            write_varint(&mut buf, value);
+        }
+        assert_eq!(
+            buf,
+            [0, 1, 0x7f, 0x80, 0x01, 0xac, 0x02, 0xff, 0xff, 0xff, 0xff, 0x0f]
+        );
+    }
+
+    #[test]
+    fn directory_encoding() {
+        let entries = [
+            Entry {
+                tile_id: 1,
+                offset: 0,
+                length: 10,
+                run_length: 1,
+            },
+            Entry {
+                tile_id: 2,
+                offset: 10,
+                length: 5,
+                run_length: 2,
+            },
+            Entry {
+                tile_id: 5,
+                offset: 0,
+                length: 10,
+                run_length: 1,
+            },
+        ];
+        let directory = serialize_directory(&entries).unwrap();
+        let mut buf = Vec::new();
+        GzDecoder::new(&directory[..])
+            .read_to_end(&mut buf)
+            .unwrap();
+        // the count, the tile id deltas, the run lengths, the lengths, and the offsets plus 1
+        // or 0 for the data following the previous entry
+        assert_eq!(buf, [3, 1, 1, 3, 1, 2, 1, 10, 5, 10, 1, 0, 1]);
+        assert_eq!(deserialize_directory(&directory), entries);
+    }
+
+    #[test]
+    fn large_directories_are_split_into_leaves() {
+        // pseudo-random gaps and lengths, so that the directory does not compress well
+        let mut state = 1u64;
+        let mut random = || {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            state >> 44
+        };
+        let (mut tile_id, mut offset) = (0, 0);
+        let entries: Vec<_> = (0..20_000)
+            .map(|_| {
+                tile_id += 1 + random() % 1000;
+                let length = 1 + random() % 100_000;
+                let entry = Entry {
+                    tile_id,
+                    offset,
+                    length,
+                    run_length: 1,
+                };
+                offset += length;
+                entry
+            })
+            .collect();
+        assert!(serialize_directory(&entries).unwrap().len() > MAX_ROOT_LEN);
+
+        let (root, leaves) = build_directories(&entries).unwrap();
+        assert!(root.len() <= MAX_ROOT_LEN);
+        let root = deserialize_directory(&root);
+        assert!(root.len() > 1);
+        let mut leaf_entries = Vec::new();
+        let mut next_offset = 0;
+        for leaf in root {
+            assert_eq!(leaf.run_length, 0);
+            assert_eq!(leaf.offset, next_offset);
+            next_offset += leaf.length;
+            let leaf_data = &leaves[leaf.offset as usize..next_offset as usize];
+            let chunk = deserialize_directory(leaf_data);
+            assert_eq!(chunk[0].tile_id, leaf.tile_id);
+            leaf_entries.extend(chunk);
+        }
+        assert_eq!(next_offset, leaves.len() as u64);
+        assert_eq!(leaf_entries, entries);
+    }
+}
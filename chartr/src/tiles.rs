@@ -0,0 +1,465 @@
+//! Web Mercator tile pyramids (XYZ/TMS) rendered from BSB/KAP charts
+
+use std::{
+    f64::consts::PI,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    ops::RangeInclusive,
+    path::Path,
+};
+
+use anyhow::{bail, Result};
+use image::{codecs::png::PngEncoder, ImageEncoder};
+use libbsb::{
+    georef::{normalize_longitude, sample_grid, GeoTransform},
+    ColorPalette, KapImageFile,
+};
+use tracing::{debug, info, instrument};
+
+use crate::{gpkg, mbtiles, pmtiles};
+
+/// The width and height of a tile, in pixels
+pub const TILE_SIZE: u32 = 256;
+/// The highest supported zoom level
+pub const MAX_ZOOM: u8 = 24;
+/// The number of samples along each edge of a chart used to compute its bounds
+const EDGE_SAMPLES: u16 = 64;
+
+/// Options of [`kap_to_tiles`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TileOptions {
+    /// The zoom levels to render
+    pub zoom: RangeInclusive<u8>,
+    /// The palette used to color the tiles
+    pub palette: ColorPalette,
+    /// Number the rows from the south (TMS) rather than from the north (XYZ)
+    pub tms: bool,
+    /// Render GeoPackage tiles in the native CRS of the chart rather than in EPSG:3857
+    pub native_crs: bool,
+}
+
+impl Default for TileOptions {
+    fn default() -> Self {
+        Self {
+            zoom: 8..=16,
+            palette: ColorPalette::Rgb,
+            tms: false,
+            native_crs: false,
+        }
+    }
+}
+
+/// The position of a tile in a pyramid
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TileId {
+    /// The zoom level
+    pub z: u8,
+    /// The column, from the west
+    pub x: u32,
+    /// The row, from the north
+    pub y: u32,
+}
+
+impl TileId {
+    /// Returns the row numbered from the south, as used by TMS and MBTiles
+    #[must_use]
+    pub const fn tms_y(&self) -> u32 {
+        (1 << self.z) - 1 - self.y
+    }
+}
+
+/// The WGS84 bounds of a chart
+///
+/// Bounds crossing the antimeridian have a western longitude greater than their eastern
+/// longitude.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    /// The westernmost longitude
+    pub west: f64,
+    /// The southernmost latitude
+    pub south: f64,
+    /// The easternmost longitude
+    pub east: f64,
+    /// The northernmost latitude
+    pub north: f64,
+}
+
+impl Bounds {
+    /// Computes the WGS84 bounds of a chart by sampling the edges of its image
+    pub fn of_chart(bsb: &KapImageFile, transform: &GeoTransform) -> Self {
+        let (width, height) = (f64::from(bsb.width()), f64::from(bsb.height()));
+        let samples = f64::from(EDGE_SAMPLES);
+        let points = (0..=EDGE_SAMPLES)
+            .map(|i| f64::from(i) / samples)
+            .flat_map(|t| {
+                [
+                    (t * width, 0.0),
+                    (t * width, height),
+                    (0.0, t * height),
+                    (width, t * height),
+                ]
+            })
+            .map(|pixel| transform.pixel_to_wgs84(pixel));
+        Self::of_points(points)
+    }
+
+    /// Computes the bounds of `(latitude, longitude)` points
+    ///
+    /// The points cross the antimeridian if their longitudes span a narrower range when counted
+    /// from 0° to 360° than from -180° to 180°.
+    pub fn of_points(points: impl IntoIterator<Item = (f64, f64)>) -> Self {
+        let points: Vec<_> = points.into_iter().collect();
+        let bounds = points.iter().fold(
+            Self {
+                west: f64::MAX,
+                south: f64::MAX,
+                east: f64::MIN,
+                north: f64::MIN,
+            },
+            |bounds, &(lat, lon)| Self {
+                west: bounds.west.min(lon),
+                south: bounds.south.min(lat),
+                east: bounds.east.max(lon),
+                north: bounds.north.max(lat),
+            },
+        );
+        let (west, east) = points
+            .iter()
+            .fold((f64::MAX, f64::MIN), |(west, east), &(_, lon)| {
+                let lon = lon.rem_euclid(360.0);
+                (west.min(lon), east.max(lon))
+            });
+        if east - west < bounds.east - bounds.west {
+            Self {
+                west: normalize_longitude(west),
+                east: normalize_longitude(east),
+                ..bounds
+            }
+        } else {
+            bounds
+        }
+    }
+
+    /// Returns whether the bounds cross the antimeridian
+    pub fn crosses_antimeridian(&self) -> bool {
+        self.west > self.east
+    }
+
+    /// Returns the centre of the bounds as `(latitude, longitude)`
+    pub fn centre(&self) -> (f64, f64) {
+        let east = if self.crosses_antimeridian() {
+            self.east + 360.0
+        } else {
+            self.east
+        };
+        (
+            f64::midpoint(self.south, self.north),
+            normalize_longitude(f64::midpoint(self.west, east)),
+        )
+    }
+
+    /// Returns the tiles of zoom level `z` covering the bounds, as `(columns, rows)`
+    ///
+    /// The columns of bounds crossing the antimeridian run to the last column of the zoom level,
+    /// then from the first one.
+    pub fn tiles(&self, z: u8) -> (impl Iterator<Item = u32> + Clone, RangeInclusive<u32>) {
+        let (columns, rows) = self.tile_ranges(z);
+        let size = 1u32 << z;
+        (columns.map(move |x| x % size), rows)
+    }
+
+    /// Returns the number of tiles of zoom level `z` covering the bounds
+    pub fn tile_count(&self, z: u8) -> u64 {
+        let (columns, rows) = self.tile_ranges(z);
+        let count = |range: RangeInclusive<u32>| u64::from(range.end() - range.start()) + 1;
+        count(columns) * count(rows)
+    }
+
+    /// Returns the ranges of columns and rows of zoom level `z` covering the bounds, the
+    /// columns past the antimeridian being numbered from the number of columns of the zoom level
+    fn tile_ranges(&self, z: u8) -> (RangeInclusive<u32>, RangeInclusive<u32>) {
+        let (min_x, min_y) = tile_containing(self.north, self.west, z);
+        let (mut max_x, max_y) = tile_containing(self.south, self.east, z);
+        if self.crosses_antimeridian() {
+            max_x += 1 << z;
+        }
+        (min_x..=max_x, min_y..=max_y)
+    }
+}
+
+/// Returns the `(x, y)` tile of zoom level `z` containing a WGS84 position
+fn tile_containing(lat: f64, lon: f64, z: u8) -> (u32, u32) {
+    let n = f64::from(1u32 << z);
+    let lat = lat.clamp(-85.051_128_78, 85.051_128_78).to_radians();
+    let x = (lon + 180.0) / 360.0 * n;
+    let y = (1.0 - lat.tan().asinh() / PI) / 2.0 * n;
+    let max = (1u32 << z) - 1;
+    (
+        (x.floor().max(0.0) as u32).min(max),
+        (y.floor().max(0.0) as u32).min(max),
+    )
+}
+
+/// Renders the tiles of a chart, resampling it with the nearest neighbour
+pub(crate) struct TileRenderer<'a> {
+    bsb: &'a KapImageFile,
+    transform: GeoTransform,
+    mask: Vec<bool>,
+    /// The RGBA colors of the palette indices
+    colors: [[u8; 4]; 256],
+    bounds: Bounds,
+}
+
+impl<'a> TileRenderer<'a> {
+    pub(crate) fn new(bsb: &'a KapImageFile, palette: ColorPalette) -> Result<Self> {
+        let transform = bsb.geo_transform()?;
+        let Some(rgbs) = bsb.header().palette(palette) else {
+            bail!("The chart has no {palette:?} palette");
+        };
+        let mut colors = [[0; 4]; 256];
+        // BSB indexes start from 1
+        for (color, &(r, g, b)) in colors.iter_mut().skip(1).zip(rgbs) {
+            *color = [r, g, b, 255];
+        }
+        Ok(Self {
+            bsb,
+            mask: bsb.border_mask()?,
+            bounds: Bounds::of_chart(bsb, &transform),
+            transform,
+            colors,
+        })
+    }
+
+    /// Returns the WGS84 bounds of the chart image
+    pub(crate) const fn bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    /// Returns the WGS84 bounds of the valid area of the chart: the bounds of its `PLY` border,
+    /// or of its image if it has none
+    pub(crate) fn coverage(&self) -> Bounds {
+        match self.bsb.header().ply.as_deref() {
+            Some(ply) if !ply.is_empty() => Bounds::of_points(
+                ply.iter()
+                    .map(|&c| self.transform.datum_shift().to_wgs84(c)),
+            ),
+            _ => self.bounds,
+        }
+    }
+
+    /// Returns the chart being rendered
+    pub(crate) const fn chart(&self) -> &KapImageFile {
+        self.bsb
+    }
+
+    /// Returns the tiles of the zoom levels `zoom` covering the chart
+    pub(crate) fn tiles(&self, zoom: RangeInclusive<u8>) -> impl Iterator<Item = TileId> + '_ {
+        zoom.flat_map(move |z| {
+            let (columns, rows) = self.bounds.tiles(z);
+            rows.flat_map(move |y| columns.clone().map(move |x| TileId { z, x, y }))
+        })
+    }
+
+    /// Returns the number of tiles of the zoom levels `zoom` covering the chart
+    pub(crate) fn tile_count(&self, zoom: RangeInclusive<u8>) -> u64 {
+        zoom.map(|z| self.bounds.tile_count(z)).sum()
+    }
+
+    /// Returns the [`GeoTransform`] of the chart being rendered
+    pub(crate) const fn transform(&self) -> &GeoTransform {
+        &self.transform
+    }
+
+    /// Renders a Web Mercator tile as RGBA pixels, or returns [`None`] if the chart does not
+    /// cover it
+    pub(crate) fn render(&self, tile: TileId) -> Option<Vec<u8>> {
+        let world = f64::from(TILE_SIZE) * f64::from(1u32 << tile.z);
+        let (origin_x, origin_y) = (f64::from(tile.x * TILE_SIZE), f64::from(tile.y * TILE_SIZE));
+        self.render_with(|(column, row)| {
+            let lat = (PI * (1.0 - 2.0 * (origin_y + row) / world))
+                .sinh()
+                .atan()
+                .to_degrees();
+            let lon = (origin_x + column) / world * 360.0 - 180.0;
+            self.transform.wgs84_to_pixel((lat, lon))
+        })
+    }
+
+    /// Renders a tile as RGBA pixels, `source` mapping a position `(column, row)` in the tile
+    /// onto the chart (see [`sample_grid`])
+    ///
+    /// Returns [`None`] if the chart does not cover any pixel of the tile.
+    pub(crate) fn render_with(&self, source: impl Fn((f64, f64)) -> (f64, f64)) -> Option<Vec<u8>> {
+        let (width, height) = (
+            usize::from(self.bsb.width()),
+            usize::from(self.bsb.height()),
+        );
+        let indices = self.bsb.pixel_indices();
+        let mut data = vec![0; (TILE_SIZE * TILE_SIZE * 4) as usize];
+        let mut empty = true;
+        let size = TILE_SIZE as u16;
+        sample_grid((size, size), source, |i, (x, y)| {
+            if !(0.0..width as f64).contains(&x) || !(0.0..height as f64).contains(&y) {
+                return;
+            }
+            let offset = y as usize * width + x as usize;
+            if self.mask[offset] {
+                data[i * 4..i * 4 + 4].copy_from_slice(&self.colors[usize::from(indices[offset])]);
+                empty = false;
+            }
+        });
+        (!empty).then_some(data)
+    }
+
+    /// Renders a Web Mercator tile as a PNG image, or returns [`None`] if the chart does not
+    /// cover it
+    pub(crate) fn render_png(&self, tile: TileId) -> Result<Option<Vec<u8>>> {
+        self.render(tile).as_deref().map(encode_tile).transpose()
+    }
+}
+
+/// Encodes the RGBA pixels of a tile as a PNG image
+pub(crate) fn encode_tile(data: &[u8]) -> Result<Vec<u8>> {
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png).write_image(
+        data,
+        TILE_SIZE,
+        TILE_SIZE,
+        image::ExtendedColorType::Rgba8,
+    )?;
+    Ok(png)
+}
+
+/// Checks that a range of zoom levels is supported
+pub(crate) fn check_zoom(zoom: &RangeInclusive<u8>) -> Result<()> {
+    if zoom.is_empty() || *zoom.end() > MAX_ZOOM {
+        bail!(
+            "Invalid zoom levels {}..{}, zoom levels must be increasing and at most {MAX_ZOOM}",
+            zoom.start(),
+            zoom.end()
+        );
+    }
+    Ok(())
+}
+
+/// Renders a BSB/KAP chart into a Web Mercator tile pyramid
+///
+/// The tiles are written as a `{z}/{x}/{y}.png` directory pyramid, or into a single file if
+/// `output` has the `.mbtiles` (MBTiles), `.gpkg` (OGC GeoPackage) or `.pmtiles` (PMTiles v3)
+/// extension. Pixels outside of the chart's `PLY` border are transparent, and tiles the chart
+/// does not cover are not written. Returns the number of tiles written.
+#[instrument]
+pub fn kap_to_tiles(bsb_file: &Path, output: &Path, options: &TileOptions) -> Result<usize> {
+    check_zoom(&options.zoom)?;
+    let bsb = KapImageFile::from_path(bsb_file)?;
+    let renderer = TileRenderer::new(&bsb, options.palette)?;
+    debug!("Chart bounds: {:?}", renderer.bounds());
+
+    let extension = output
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+    let count = match extension.as_deref() {
+        Some("mbtiles") => mbtiles::write_mbtiles(&renderer, output, options)?,
+        Some("gpkg") => gpkg::write_gpkg(&renderer, output, options)?,
+        Some("pmtiles") => pmtiles::write_pmtiles(&renderer, output, options)?,
+        _ => write_directory(&renderer, output, options)?,
+    };
+    info!("Wrote {count} tiles to {}", output.display());
+    Ok(count)
+}
+
+/// Writes the tiles into a `{z}/{x}/{y}.png` directory pyramid
+fn write_directory(
+    renderer: &TileRenderer,
+    output_dir: &Path,
+    options: &TileOptions,
+) -> Result<usize> {
+    let mut count = 0;
+    for tile in renderer.tiles(options.zoom.clone()) {
+        let Some(png) = renderer.render_png(tile)? else {
+            continue;
+        };
+        let y = if options.tms { tile.tms_y() } else { tile.y };
+        let dir = output_dir.join(tile.z.to_string()).join(tile.x.to_string());
+        fs::create_dir_all(&dir)?;
+        let mut file = BufWriter::new(File::create(dir.join(format!("{y}.png")))?);
+        file.write_all(&png)?;
+        file.flush()?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::chesapeake_header;
+
+    #[test]
+    fn bounds_crossing_antimeridian() {
+        let bounds = Bounds::of_points([(-16.0, 179.0), (-18.0, -179.5), (-17.0, 179.8)]);
+        assert!(bounds.crosses_antimeridian());
+        assert_eq!((bounds.west, bounds.east), (179.0, -179.5));
+        let (lat, lon) = bounds.centre();
+        assert!((lat + 17.0).abs() < 1e-9 && (lon - 179.75).abs() < 1e-9);
+
+        // the two columns on each side of the antimeridian
+        let (columns, rows) = bounds.tiles(4);
+        assert_eq!(columns.collect::<Vec<_>>(), [15, 0]);
+        assert_eq!(rows, 8..=8);
+        assert_eq!(bounds.tile_count(4), 2);
+        assert_eq!(bounds.tile_count(16), 275 * 382);
+    }
+
+    #[test]
+    fn bounds_around_greenwich() {
+        let bounds = Bounds::of_points([(50.0, -1.0), (51.0, 1.0)]);
+        assert!(!bounds.crosses_antimeridian());
+        assert_eq!((bounds.west, bounds.east), (-1.0, 1.0));
+        let (columns, _) = bounds.tiles(4);
+        assert_eq!(columns.collect::<Vec<_>>(), [7, 8]);
+    }
+
+    #[test]
+    fn rendered_tiles_follow_the_chart() -> Result<()> {
+        let mut header = chesapeake_header();
+        header.general_parameters.image_width_height = (116, 98);
+        header.ply = None;
+        // a checkerboard of 4x4 pixel squares
+        let raster = (0..116 * 98)
+            .map(|i| 1 + u8::from((i % 116 / 4 + i / 116 / 4) % 2 == 0))
+            .collect();
+        let bsb = KapImageFile::new(header, raster)?;
+        let renderer = TileRenderer::new(&bsb, ColorPalette::Rgb)?;
+        let transform = renderer.transform();
+
+        let (mut covered, mut mismatches) = (0, 0);
+        for tile in renderer.tiles(15..=15) {
+            let Some(data) = renderer.render(tile) else {
+                continue;
+            };
+            let world = f64::from(TILE_SIZE << tile.z);
+            for (i, pixel) in data.chunks_exact(4).enumerate() {
+                let (column, row) = ((i % 256) as f64 + 0.5, (i / 256) as f64 + 0.5);
+                let y = f64::from(tile.y * TILE_SIZE) + row;
+                let lat = (PI * (1.0 - 2.0 * y / world)).sinh().atan().to_degrees();
+                let lon = (f64::from(tile.x * TILE_SIZE) + column) / world * 360.0 - 180.0;
+                let (x, y) = transform.wgs84_to_pixel((lat, lon));
+                let expected = if (0.0..116.0).contains(&x) && (0.0..98.0).contains(&y) {
+                    let index = bsb.pixel_indices()[y as usize * 116 + x as usize];
+                    renderer.colors[usize::from(index)]
+                } else {
+                    [0; 4]
+                };
+                covered += usize::from(expected[3] != 0);
+                mismatches += usize::from(pixel != expected);
+            }
+        }
+        // the chart pixels are about 8 m wide, and the tile pixels about 3.8 m wide
+        assert!((40_000..60_000).contains(&covered), "{covered}");
+        // only tile pixels on the edges of the squares may differ
+        assert!(mismatches * 100 < covered, "{mismatches} of {covered}");
+        Ok(())
+    }
+}
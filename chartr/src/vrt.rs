@@ -0,0 +1,206 @@
+//! GDAL VRT output with ground control points
+
+use std::{fmt::Write as _, fs, path::Path};
+
+use anyhow::{bail, Result};
+use libbsb::{crs::Crs, ColorPalette, KapImageFile};
+use tracing::{info, warn};
+
+use crate::ImageOptions;
+
+/// Writes a GDAL VRT file referencing an image exported from a BSB/KAP chart, with every `REF`
+/// record of the chart as a ground control point
+///
+/// The GCPs are given in the coordinate reference system of the chart: projected coordinates
+/// if its projection is supported, geographic coordinates otherwise. `paletted` tells whether
+/// the image has a single band of palette indices (paletted GeoTIFFs) or RGB(A) bands.
+pub(crate) fn write_vrt(
+    bsb: &KapImageFile,
+    image_name: &Path,
+    options: ImageOptions,
+    paletted: bool,
+) -> Result<()> {
+    let header = bsb.header();
+    let refs = header.reference_point_record.as_deref().unwrap_or_default();
+    if refs.is_empty() {
+        bail!("The chart has no REF records to use as ground control points");
+    }
+    let crs = header.crs().unwrap_or_else(|e| {
+        warn!("Unsupported chart projection ({e}), using geographic GCPs");
+        Crs::geographic(header.datum_or_wgs84())
+    });
+    let Some(image_file) = image_name.file_name().and_then(|name| name.to_str()) else {
+        bail!("Invalid image file name {}", image_name.display());
+    };
+
+    let mut vrt = format!(
+        "<VRTDataset rasterXSize=\"{}\" rasterYSize=\"{}\">\n",
+        bsb.width(),
+        bsb.height()
+    );
+    // GCPs are written as (longitude, latitude) or (easting, northing)
+    let axis_mapping = if crs.projection.is_some() {
+        "1,2"
+    } else {
+        "2,1"
+    };
+    let _ = writeln!(
+        vrt,
+        "  <GCPList Projection=\"{}\" dataAxisToSRSAxisMapping=\"{axis_mapping}\">",
+        escape(&crs.to_wkt2())
+    );
+    for (i, r) in refs.iter().enumerate() {
+        let (x, y) = crs.projection.as_ref().map_or_else(
+            || (r.coords.1, r.coords.0),
+            |projection| projection.forward(r.coords),
+        );
+        let _ = writeln!(
+            vrt,
+            "    <GCP Id=\"{}\" Pixel=\"{}\" Line=\"{}\" X=\"{x}\" Y=\"{y}\" />",
+            i + 1,
+            r.pixels.0,
+            r.pixels.1
+        );
+    }
+    vrt.push_str("  </GCPList>\n");
+
+    let bands: &[&str] = match (paletted, options.clip) {
+        (true, _) => &["Palette"],
+        (false, false) => &["Red", "Green", "Blue"],
+        (false, true) => &["Red", "Green", "Blue", "Alpha"],
+    };
+    for (band, color_interp) in (1..).zip(bands) {
+        let _ = writeln!(vrt, "  <VRTRasterBand dataType=\"Byte\" band=\"{band}\">");
+        let _ = writeln!(vrt, "    <ColorInterp>{color_interp}</ColorInterp>");
+        if paletted {
+            if options.clip {
+                vrt.push_str("    <NoDataValue>0</NoDataValue>\n");
+            }
+            let palette = header
+                .palette(ColorPalette::Rgb)
+                .ok_or(libbsb::Error::NonExistentPalette)?;
+            vrt.push_str("    <ColorTable>\n");
+            // BSB indexes start from 1
+            for (r, g, b) in std::iter::once((0, 0, 0)).chain(palette.iter().copied()) {
+                let _ = writeln!(
+                    vrt,
+                    "      <Entry c1=\"{r}\" c2=\"{g}\" c3=\"{b}\" c4=\"255\" />"
+                );
+            }
+            vrt.push_str("    </ColorTable>\n");
+        }
+        let _ = writeln!(
+            vrt,
+            "    <SimpleSource>\n      <SourceFilename relativeToVRT=\"1\">{}</SourceFilename>\n      <SourceBand>{band}</SourceBand>\n    </SimpleSource>",
+            escape(image_file)
+        );
+        vrt.push_str("  </VRTRasterBand>\n");
+    }
+    vrt.push_str("</VRTDataset>\n");
+
+    let vrt_name = image_name.with_extension("vrt");
+    fs::write(&vrt_name, vrt)?;
+    info!(
+        "Wrote VRT with {} GCPs to {}",
+        refs.len(),
+        vrt_name.display()
+    );
+    Ok(())
+}
+
+/// Escapes the XML special characters of an attribute or text value
+pub(crate) fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use mktemp::Temp;
+
+    use super::*;
+    use crate::tests::{blank_chart, chesapeake_header};
+
+    /// Returns the attributes of the `element` tags of an XML document
+    fn attributes(xml: &str, element: &str) -> Vec<HashMap<String, String>> {
+        let unescape = |value: &str| {
+            value
+                .replace("&quot;", "\"")
+                .replace("&gt;", ">")
+                .replace("&lt;", "<")
+                .replace("&amp;", "&")
+        };
+        xml.split(&format!("<{element} "))
+            .skip(1)
+            .map(|tag| {
+                let tag = &tag[..tag.find('>').unwrap()];
+                tag.split('"')
+                    .collect::<Vec<_>>()
+                    .chunks_exact(2)
+                    .map(|pair| {
+                        let name = pair[0].trim().trim_end_matches('=');
+                        (name.to_owned(), unescape(pair[1]))
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn vrt_of_chesapeake_bay() -> Result<()> {
+        let mut header = chesapeake_header();
+        // keep the raster small: the REF records still span the whole chart
+        header.general_parameters.image_width_height = (116, 98);
+        let bsb = blank_chart(header);
+        let header = bsb.header();
+
+        let dir = Temp::new_dir()?;
+        write_vrt(
+            &bsb,
+            &dir.as_path().join("chesapeake.tif"),
+            ImageOptions::default(),
+            true,
+        )?;
+        let vrt = fs::read_to_string(dir.as_path().join("chesapeake.vrt"))?;
+
+        let dataset = &attributes(&vrt, "VRTDataset")[0];
+        assert_eq!(dataset["rasterXSize"], "116");
+        assert_eq!(dataset["rasterYSize"], "98");
+
+        let crs = header.crs()?;
+        let gcp_list = &attributes(&vrt, "GCPList")[0];
+        assert_eq!(gcp_list["Projection"], crs.to_wkt2());
+        assert!(gcp_list["Projection"].starts_with(r#"PROJCRS["NAD83 / Mercator""#));
+        assert_eq!(gcp_list["dataAxisToSRSAxisMapping"], "1,2");
+
+        let refs = header.reference_point_record.as_ref().unwrap();
+        let gcps = attributes(&vrt, "GCP");
+        assert_eq!(gcps.len(), refs.len());
+        let projection = crs.projection.unwrap();
+        for (i, (gcp, r)) in gcps.iter().zip(refs).enumerate() {
+            assert_eq!(gcp["Id"], (i + 1).to_string());
+            assert_eq!(gcp["Pixel"], r.pixels.0.to_string());
+            assert_eq!(gcp["Line"], r.pixels.1.to_string());
+            let (x, y) = projection.forward(r.coords);
+            assert!((gcp["X"].parse::<f64>()? - x).abs() < 1e-6, "{gcp:?}");
+            assert!((gcp["Y"].parse::<f64>()? - y).abs() < 1e-6, "{gcp:?}");
+        }
+
+        // a single paletted band, with an unused first entry since BSB indexes start from 1
+        assert_eq!(attributes(&vrt, "VRTRasterBand").len(), 1);
+        assert!(vrt.contains("<ColorInterp>Palette</ColorInterp>"));
+        assert_eq!(
+            attributes(&vrt, "Entry").len(),
+            header.rgb.as_ref().unwrap().len() + 1
+        );
+        assert!(
+            vrt.contains(r#"<SourceFilename relativeToVRT="1">chesapeake.tif</SourceFilename>"#)
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,182 @@
+//! World file (`.pgw`, `.jgw`, `.tfw`, ...) and `.prj` sidecar input and output
+
+use std::{fs, path::Path};
+
+use anyhow::{bail, Result};
+use libbsb::{crs::Crs, geodesy::Datum, georef::Affine, KapImageFile};
+use tracing::{info, warn};
+
+use crate::{
+    import::{crs_from_wkt, ImageGeoreference, PixelModel},
+    model_crs,
+};
+
+/// The rotation, in degrees, above which a chart is reported as rotated
+const MAX_ROTATION: f64 = 0.01;
+
+/// Writes a world file and a `.prj` file next to an image exported from a BSB/KAP chart
+///
+/// World files can only describe affine transformations. Charts georeferenced through
+/// non-linear polynomials are approximated with the closest affine transformation, and a
+/// warning with the resulting error is emitted. Rotated charts are written exactly but also
+/// emit a warning, since many tools ignore the rotation terms of world files.
+pub(crate) fn write_world_file(bsb: &KapImageFile, image_name: &Path) -> Result<()> {
+    let transform = bsb.geo_transform()?;
+    let affine = if let Some(affine) = transform.affine() {
+        affine
+    } else {
+        let approximation = transform.affine_approximation(bsb.width(), bsb.height())?;
+        warn!(
+            "The georeferencing of the chart is not affine, the world file is approximated with a maximum error of {:.2} px",
+            approximation.max_error
+        );
+        approximation.affine
+    };
+
+    let [_, c1, c2, _, c4, c5] = affine.0;
+    let rotation = c4.atan2(c1).to_degrees();
+    if rotation.abs() > MAX_ROTATION {
+        warn!("The chart is rotated by {rotation:.3}°, the world file contains rotation terms");
+    }
+    // world files give the centre of the top left pixel
+    let (x, y) = affine.apply((0.5, 0.5));
+    let world_file = format!("{c1}\n{c4}\n{c2}\n{c5}\n{x}\n{y}\n");
+    let world_file_name = image_name.with_extension(world_file_extension(image_name));
+    fs::write(&world_file_name, world_file)?;
+    info!("Wrote world file to {}", world_file_name.display());
+
+    let prj_name = image_name.with_extension("prj");
+    fs::write(&prj_name, model_crs(bsb, &transform).to_wkt2())?;
+    info!("Wrote projection file to {}", prj_name.display());
+    Ok(())
+}
+
+/// Reads the world file of an image and the CRS of its `.prj` file, or returns [`None`] if the
+/// image has no world file
+///
+/// The world file is looked up with the extension derived from the image extension (e.g.
+/// `.pgw` for `.png`), then with the `.wld` extension. Images without a `.prj` file are assumed
+/// to be in WGS84 longitude/latitude.
+pub(crate) fn read_world_file(image_name: &Path) -> Result<Option<ImageGeoreference>> {
+    let Some(world_file_name) = [world_file_extension(image_name), "wld".to_owned()]
+        .into_iter()
+        .map(|extension| image_name.with_extension(extension))
+        .find(|name| name.is_file())
+    else {
+        return Ok(None);
+    };
+    let values = fs::read_to_string(&world_file_name)?
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<Vec<f64>, _>>()?;
+    let &[a, d, b, e, c, f] = values.as_slice() else {
+        bail!(
+            "Invalid world file {}: expected 6 values, found {}",
+            world_file_name.display(),
+            values.len()
+        );
+    };
+    info!("Read world file {}", world_file_name.display());
+    // world files give the centre of the top left pixel
+    let affine = Affine([c - 0.5 * (a + b), a, b, f - 0.5 * (d + e), d, e]);
+
+    let prj_name = image_name.with_extension("prj");
+    let crs = if prj_name.is_file() {
+        crs_from_wkt(&fs::read_to_string(&prj_name)?)?
+    } else {
+        warn!(
+            "No projection file {}, assuming {} longitude/latitude",
+            prj_name.display(),
+            Datum::Wgs84
+        );
+        Crs::geographic(Datum::Wgs84)
+    };
+    Ok(Some(ImageGeoreference {
+        crs,
+        model: PixelModel::Affine(affine),
+    }))
+}
+
+/// Returns the world file extension of an image: the first and last letters of the image
+/// extension followed by `w` (e.g. `pgw` for `png`, `tfw` for `tiff`)
+fn world_file_extension(image_name: &Path) -> String {
+    let extension = image_name
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let mut chars = extension.chars();
+    match (chars.next(), chars.next_back()) {
+        (Some(first), Some(last)) => format!("{first}{last}w"),
+        _ => "wld".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mktemp::Temp;
+
+    use super::*;
+    use crate::tests::{blank_chart, chesapeake_header};
+
+    #[test]
+    fn world_file_of_chesapeake_bay() -> Result<()> {
+        let mut header = chesapeake_header();
+        // georeference the chart through its projection, which is affine, and keep the raster
+        // small: the REF records still span the whole chart
+        (header.wpx, header.wpy, header.pwx, header.pwy) = (None, None, None, None);
+        header.general_parameters.image_width_height = (116, 98);
+        let bsb = blank_chart(header);
+        let affine = bsb.geo_transform()?.affine().unwrap();
+
+        let dir = Temp::new_dir()?;
+        let image_name = dir.as_path().join("chesapeake.png");
+        write_world_file(&bsb, &image_name)?;
+
+        let values = fs::read_to_string(dir.as_path().join("chesapeake.pgw"))?
+            .lines()
+            .map(str::parse)
+            .collect::<Result<Vec<f64>, _>>()?;
+        let [c0, c1, c2, c3, c4, c5] = affine.0;
+        let expected = [
+            c1,
+            c4,
+            c2,
+            c5,
+            c1.mul_add(0.5, c2.mul_add(0.5, c0)),
+            c4.mul_add(0.5, c5.mul_add(0.5, c3)),
+        ];
+        assert_eq!(values.len(), 6);
+        for (value, expected) in values.iter().zip(expected) {
+            assert!(
+                (value - expected).abs() < 1e-6,
+                "{values:?} != {expected:?}"
+            );
+        }
+        // the chart is north up, with the 8 m pixels of its `DX`/`DY` fields
+        assert!(c2.abs() < 1e-3 && c4.abs() < 1e-3, "{affine:?}");
+        assert!(
+            (c1 - 8.0).abs() < 1e-3 && (c5 + 8.0).abs() < 1e-3,
+            "{affine:?}"
+        );
+
+        let prj = fs::read_to_string(dir.as_path().join("chesapeake.prj"))?;
+        assert!(
+            prj.starts_with(r#"PROJCRS["NAD83 / Mercator",BASEGEOGCRS["NAD83""#),
+            "{prj}"
+        );
+        assert!(prj.contains(r#"METHOD["Mercator (variant B)",ID["EPSG",9805]]"#));
+        assert!(prj.contains(r#"PARAMETER["Latitude of 1st standard parallel",37.083,"#));
+
+        // the world file reads back as the same georeferencing
+        let georeference = read_world_file(&image_name)?.unwrap();
+        assert_eq!(georeference.crs.datum, Datum::Nad83);
+        let PixelModel::Affine(read) = georeference.model else {
+            panic!("the world file is not affine");
+        };
+        for (a, b) in read.0.iter().zip(affine.0) {
+            assert!((a - b).abs() < 1e-6, "{read:?} != {affine:?}");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,296 @@
+//! Coordinate reference system definitions for BSB/KAP charts
+//!
+//! GIS tools describe coordinates with a coordinate reference system (CRS) rather than with the
+//! `PR`, `PP` and `GD` fields of the `KNP` record. [`Crs`] combines the geodetic datum (see
+//! [`Datum`]) and the projection (see [`Projection`]) of a chart, and can be written as an OGC
+//! WKT2 string (ISO 19162:2019) or as a PROJ string.
+
+use std::fmt::Write;
+
+use crate::{
+    geodesy::Datum,
+    image::header::ImageHeader,
+    projection::{Projection, ProjectionMethod},
+    Error,
+};
+
+const DEGREE: &str = r#"ANGLEUNIT["degree",0.0174532925199433]"#;
+const METRE: &str = r#"LENGTHUNIT["metre",1]"#;
+const UNITY: &str = r#"SCALEUNIT["unity",1]"#;
+
+/// A coordinate reference system: a geodetic datum, optionally combined with a projection
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Crs {
+    /// The geodetic datum
+    pub datum: Datum,
+    /// The projection, or [`None`] for geographic coordinates
+    pub projection: Option<Projection>,
+}
+
+impl Crs {
+    /// Creates a geographic (latitude/longitude) [`Crs`]
+    #[must_use]
+    pub const fn geographic(datum: Datum) -> Self {
+        Self {
+            datum,
+            projection: None,
+        }
+    }
+
+    /// Creates a projected [`Crs`]
+    ///
+    /// The ellipsoid of the projection is replaced by the ellipsoid of the datum.
+    #[must_use]
+    pub const fn projected(datum: Datum, mut projection: Projection) -> Self {
+        projection.ellipsoid = datum.ellipsoid();
+        Self {
+            datum,
+            projection: Some(projection),
+        }
+    }
+
+    /// Creates a [`Crs`] from the `KNP` and `KNQ` records of a header
+    ///
+    /// # Errors
+    ///
+    /// This function errors if the datum (see [`ImageHeader::datum`]) or the projection (see
+    /// [`Projection::from_header`]) is missing or not supported.
+    pub fn from_header(header: &ImageHeader) -> Result<Self, Error> {
+        Ok(Self::projected(header.datum()?, header.projection()?))
+    }
+
+    /// Returns a human readable name, e.g. `NAD83 / Mercator`
+    #[must_use]
+    pub fn name(&self) -> String {
+        let datum = geographic_name(self.datum);
+        self.projection.as_ref().map_or_else(
+            || datum.to_owned(),
+            |projection| format!("{datum} / {}", method_name(&projection.method)),
+        )
+    }
+
+    /// Returns the OGC WKT2 (ISO 19162:2019) definition of the CRS
+    #[must_use]
+    pub fn to_wkt2(&self) -> String {
+        let datum = self.datum;
+        let ellipsoid = datum.ellipsoid();
+        let geographic = format!(
+            r#"["{}",DATUM["{}",ELLIPSOID["{}",{},{},{METRE}]],PRIMEM["Greenwich",0,{DEGREE}]"#,
+            geographic_name(datum),
+            datum.name(),
+            ellipsoid_name(datum),
+            ellipsoid.semi_major_axis,
+            ellipsoid.inverse_flattening(),
+        );
+        let Some(projection) = &self.projection else {
+            return format!(
+                r#"GEOGCRS{geographic},CS[ellipsoidal,2],AXIS["geodetic latitude (Lat)",north,ORDER[1],{DEGREE}],AXIS["geodetic longitude (Lon)",east,ORDER[2],{DEGREE}],ID["EPSG",{}]]"#,
+                datum.epsg()
+            );
+        };
+
+        let (method, epsg, parameters) = wkt_method(projection);
+        let mut conversion = format!(
+            r#"CONVERSION["{}",METHOD["{method}",ID["EPSG",{epsg}]]"#,
+            method_name(&projection.method)
+        );
+        for (name, value, unit, id) in parameters {
+            let _ = write!(
+                conversion,
+                r#",PARAMETER["{name}",{value},{unit},ID["EPSG",{id}]]"#
+            );
+        }
+        conversion.push(']');
+        format!(
+            r#"PROJCRS["{}",BASEGEOGCRS{geographic},ID["EPSG",{}]],{conversion},CS[Cartesian,2],AXIS["easting (E)",east,ORDER[1],{METRE}],AXIS["northing (N)",north,ORDER[2],{METRE}]]"#,
+            self.name(),
+            datum.epsg()
+        )
+    }
+
+    /// Returns the PROJ string (e.g. `+proj=merc +lat_ts=37.083 ...`) of the CRS
+    #[must_use]
+    pub fn to_proj_string(&self) -> String {
+        let datum = match self.datum {
+            Datum::Wgs84 => "+datum=WGS84".to_owned(),
+            Datum::Nad83 => "+datum=NAD83".to_owned(),
+            Datum::Nad27 => "+datum=NAD27".to_owned(),
+            Datum::Ed50 => {
+                let (x, y, z) = Datum::Ed50.to_wgs84().translation;
+                format!("+ellps=intl +towgs84={x},{y},{z},0,0,0,0")
+            }
+        };
+        let Some(projection) = &self.projection else {
+            return format!("+proj=longlat {datum} +no_defs");
+        };
+        let method = match projection.method {
+            ProjectionMethod::Mercator {
+                central_meridian,
+                latitude_of_true_scale,
+            } => format!("+proj=merc +lat_ts={latitude_of_true_scale} +lon_0={central_meridian}"),
+            ProjectionMethod::TransverseMercator {
+                central_meridian,
+                latitude_of_origin,
+                scale_factor,
+            } => format!(
+                "+proj=tmerc +lat_0={latitude_of_origin} +lon_0={central_meridian} +k={scale_factor}"
+            ),
+            ProjectionMethod::LambertConformalConic {
+                central_meridian,
+                latitude_of_origin,
+                standard_parallels: (first, second),
+            } => format!(
+                "+proj=lcc +lat_1={first} +lat_2={second} +lat_0={latitude_of_origin} +lon_0={central_meridian}"
+            ),
+            ProjectionMethod::Polyconic {
+                central_meridian,
+                latitude_of_origin,
+            } => format!("+proj=poly +lat_0={latitude_of_origin} +lon_0={central_meridian}"),
+        };
+        format!(
+            "{method} +x_0={} +y_0={} {datum} +units=m +no_defs",
+            projection.false_easting, projection.false_northing
+        )
+    }
+}
+
+impl ImageHeader {
+    /// Returns the coordinate reference system of the chart
+    ///
+    /// # Errors
+    ///
+    /// See [`Crs::from_header`]
+    pub fn crs(&self) -> Result<Crs, Error> {
+        Crs::from_header(self)
+    }
+
+    /// Returns the OGC WKT2 definition of the coordinate reference system of the chart
+    ///
+    /// # Errors
+    ///
+    /// See [`Crs::from_header`]
+    pub fn to_wkt2(&self) -> Result<String, Error> {
+        Ok(self.crs()?.to_wkt2())
+    }
+
+    /// Returns the PROJ string of the coordinate reference system of the chart
+    ///
+    /// # Errors
+    ///
+    /// See [`Crs::from_header`]
+    pub fn to_proj_string(&self) -> Result<String, Error> {
+        Ok(self.crs()?.to_proj_string())
+    }
+}
+
+/// A WKT2 projection parameter: name, value, unit and EPSG code
+type Parameter = (&'static str, f64, &'static str, u16);
+
+/// Returns the EPSG method name, EPSG method code and parameters of a projection
+fn wkt_method(projection: &Projection) -> (&'static str, u16, Vec<Parameter>) {
+    let natural_origin = |lat: f64, lon: f64| {
+        vec![
+            ("Latitude of natural origin", lat, DEGREE, 8801),
+            ("Longitude of natural origin", lon, DEGREE, 8802),
+        ]
+    };
+    let (method, code, mut parameters) = match projection.method {
+        ProjectionMethod::Mercator {
+            central_meridian,
+            latitude_of_true_scale,
+        } => (
+            "Mercator (variant B)",
+            9805,
+            vec![
+                (
+                    "Latitude of 1st standard parallel",
+                    latitude_of_true_scale,
+                    DEGREE,
+                    8823,
+                ),
+                (
+                    "Longitude of natural origin",
+                    central_meridian,
+                    DEGREE,
+                    8802,
+                ),
+            ],
+        ),
+        ProjectionMethod::TransverseMercator {
+            central_meridian,
+            latitude_of_origin,
+            scale_factor,
+        } => {
+            let mut parameters = natural_origin(latitude_of_origin, central_meridian);
+            parameters.push(("Scale factor at natural origin", scale_factor, UNITY, 8805));
+            ("Transverse Mercator", 9807, parameters)
+        }
+        ProjectionMethod::LambertConformalConic {
+            central_meridian,
+            latitude_of_origin,
+            standard_parallels: (first, second),
+        } => {
+            let parameters = vec![
+                ("Latitude of false origin", latitude_of_origin, DEGREE, 8821),
+                ("Longitude of false origin", central_meridian, DEGREE, 8822),
+                ("Latitude of 1st standard parallel", first, DEGREE, 8823),
+                ("Latitude of 2nd standard parallel", second, DEGREE, 8824),
+                (
+                    "Easting at false origin",
+                    projection.false_easting,
+                    METRE,
+                    8826,
+                ),
+                (
+                    "Northing at false origin",
+                    projection.false_northing,
+                    METRE,
+                    8827,
+                ),
+            ];
+            return ("Lambert Conic Conformal (2SP)", 9802, parameters);
+        }
+        ProjectionMethod::Polyconic {
+            central_meridian,
+            latitude_of_origin,
+        } => (
+            "American Polyconic",
+            9818,
+            natural_origin(latitude_of_origin, central_meridian),
+        ),
+    };
+    parameters.extend([
+        ("False easting", projection.false_easting, METRE, 8806),
+        ("False northing", projection.false_northing, METRE, 8807),
+    ]);
+    (method, code, parameters)
+}
+
+const fn method_name(method: &ProjectionMethod) -> &'static str {
+    match method {
+        ProjectionMethod::Mercator { .. } => "Mercator",
+        ProjectionMethod::TransverseMercator { .. } => "Transverse Mercator",
+        ProjectionMethod::LambertConformalConic { .. } => "Lambert Conformal Conic",
+        ProjectionMethod::Polyconic { .. } => "Polyconic",
+    }
+}
+
+/// Returns the EPSG name of the geographic CRS of a datum
+const fn geographic_name(datum: Datum) -> &'static str {
+    match datum {
+        Datum::Wgs84 => "WGS 84",
+        Datum::Nad83 => "NAD83",
+        Datum::Nad27 => "NAD27",
+        Datum::Ed50 => "ED50",
+    }
+}
+
+/// Returns the EPSG name of the ellipsoid of a datum
+const fn ellipsoid_name(datum: Datum) -> &'static str {
+    match datum {
+        Datum::Wgs84 => "WGS 84",
+        Datum::Nad83 => "GRS 1980",
+        Datum::Nad27 => "Clarke 1866",
+        Datum::Ed50 => "International 1924",
+    }
+}
@@ -28,11 +28,71 @@ pub enum Error {
     #[error("Unsupported depth `{0}`. Suported depths are: 1, 4, 7")]
     UnsupportedDepth(u8),
 
+    /// Error returned if a 1-bit image has pixels of index 0: since indices start from 1, the
+    /// pixels of 1-bit images can only hold index 1
+    #[error("Invalid pixel index 0 in a 1-bit image. 1-bit images can only hold index 1")]
+    ZeroIndexInOneBitImage,
+
     /// Error returned if user attempted to use a palette that does not exist in the BSB/KAP image
     /// header
     #[error("Palette does not exist")]
     NonExistentPalette,
 
+    /// Error returned if the header lacks the records required to georeference the image
+    #[error("Header does not contain the records required for georeferencing")]
+    MissingGeoreference,
+
+    /// Error returned if a georeferencing polynomial has an unsupported order
+    #[error("Unsupported polynomial order `{0}`. Supported orders are: 1, 2")]
+    UnsupportedPolynomialOrder(usize),
+
+    /// Error returned if there are not enough reference points to fit the georeferencing
+    /// polynomials
+    #[error("Not enough reference points: {required} required, {found} found")]
+    InsufficientReferencePoints {
+        /// number of reference points required
+        required: usize,
+        /// number of reference points found
+        found: usize,
+    },
+
+    /// Error returned if the reference points do not allow a unique fit (e.g. they are
+    /// collinear or duplicated)
+    #[error("Reference points are degenerate")]
+    DegenerateReferencePoints,
+
+    /// Error returned if the projection of the chart is not supported
+    #[error("Unsupported projection `{0}`. Supported projections are: Mercator, Transverse Mercator, Lambert Conformal Conic, Polyconic")]
+    UnsupportedProjection(String),
+
+    /// Error returned if a header field required by the projection is missing or invalid
+    #[error("Missing or invalid projection parameter `{0}`")]
+    MissingProjectionParameter(&'static str),
+
+    /// Error returned if the geodetic datum of the chart is not supported
+    #[error("Unsupported geodetic datum `{0}`. Supported datums are: WGS84, NAD83, NAD27, ED50")]
+    UnsupportedDatum(String),
+
+    /// Error returned if a pixel window is empty or does not fit within the image
+    #[error("Invalid window of {size:?} pixels at {origin:?} for an image of {image:?} pixels")]
+    InvalidWindow {
+        /// top left pixel of the window
+        origin: (u16, u16),
+        /// width and height of the window
+        size: (u16, u16),
+        /// width and height of the image
+        image: (u16, u16),
+    },
+
+    /// Error returned if an image would exceed the maximum width or height of a BSB/KAP file
+    #[error("Image size {0}x{1} exceeds the maximum BSB/KAP image size of 65535x65535")]
+    ImageTooLarge(usize, usize),
+
+    /// Error returned if mosaicked charts do not cover a single area without holes, which a
+    /// single `PLY` border cannot describe
+    #[error("The charts do not cover a single area without holes")]
+    DisjointCoverage,
+
     #[error("Other: `{0}`")]
     /// Other bubbled errors, such as conversion errors
     Other(String),
@@ -0,0 +1,502 @@
+//! Ellipsoids and geodetic datums used by BSB/KAP charts
+//!
+//! The geodetic datum of a chart is found in the `GD` field of the `KNP` record (see
+//! [`crate::image::raw::header::DetailedParameters::geodetic_datum_name`]). Coordinates in the
+//! chart datum are converted to WGS84 with a [`DatumShift`]: either the constant shift of the
+//! `DTM` record, or a [`Helmert`] transformation with built-in parameters for the datum.
+
+use std::{fmt, str::FromStr};
+
+use tracing::warn;
+
+use crate::{georef::normalize_longitude, image::header::ImageHeader, Error};
+
+/// A reference ellipsoid, defined by its semi-major axis and flattening
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ellipsoid {
+    /// Semi-major axis in metres
+    pub semi_major_axis: f64,
+    /// Flattening
+    pub flattening: f64,
+}
+
+impl Ellipsoid {
+    /// World Geodetic System 1984
+    pub const WGS84: Self = Self::new(6_378_137.0, 1.0 / 298.257_223_563);
+    /// Geodetic Reference System 1980 (used by NAD83)
+    pub const GRS80: Self = Self::new(6_378_137.0, 1.0 / 298.257_222_101);
+    /// Clarke 1866 (used by NAD27)
+    pub const CLARKE_1866: Self = Self::new(6_378_206.4, 1.0 / 294.978_698_213_898);
+    /// International 1924, also known as Hayford 1909 (used by ED50)
+    pub const INTERNATIONAL_1924: Self = Self::new(6_378_388.0, 1.0 / 297.0);
+
+    /// Creates a new [`Ellipsoid`] from its semi-major axis (in metres) and flattening
+    #[must_use]
+    pub const fn new(semi_major_axis: f64, flattening: f64) -> Self {
+        Self {
+            semi_major_axis,
+            flattening,
+        }
+    }
+
+    /// Creates a sphere of the given radius (in metres)
+    #[must_use]
+    pub const fn sphere(radius: f64) -> Self {
+        Self::new(radius, 0.0)
+    }
+
+    /// Returns the semi-minor axis in metres
+    #[must_use]
+    pub fn semi_minor_axis(&self) -> f64 {
+        self.semi_major_axis * (1.0 - self.flattening)
+    }
+
+    /// Returns the inverse flattening, or `0.0` for a sphere
+    #[must_use]
+    pub fn inverse_flattening(&self) -> f64 {
+        if self.flattening == 0.0 {
+            0.0
+        } else {
+            1.0 / self.flattening
+        }
+    }
+
+    /// Returns the square of the first eccentricity
+    #[must_use]
+    pub fn eccentricity_squared(&self) -> f64 {
+        self.flattening * (2.0 - self.flattening)
+    }
+
+    /// Returns the first eccentricity
+    #[must_use]
+    pub fn eccentricity(&self) -> f64 {
+        self.eccentricity_squared().sqrt()
+    }
+
+    /// Returns the length of the meridian arc from the equator to `lat` (in radians), in metres
+    #[must_use]
+    #[allow(clippy::suboptimal_flops)]
+    pub fn meridian_arc(&self, lat: f64) -> f64 {
+        let e2 = self.eccentricity_squared();
+        let (e4, e6) = (e2 * e2, e2 * e2 * e2);
+        self.semi_major_axis
+            * ((1.0 - e2 / 4.0 - 3.0 * e4 / 64.0 - 5.0 * e6 / 256.0) * lat
+                - (3.0 * e2 / 8.0 + 3.0 * e4 / 32.0 + 45.0 * e6 / 1024.0) * (2.0 * lat).sin()
+                + (15.0 * e4 / 256.0 + 45.0 * e6 / 1024.0) * (4.0 * lat).sin()
+                - (35.0 * e6 / 3072.0) * (6.0 * lat).sin())
+    }
+
+    /// Returns the radius of curvature in the prime vertical at `lat` (in radians), in metres
+    #[must_use]
+    pub fn prime_vertical_radius(&self, lat: f64) -> f64 {
+        self.semi_major_axis
+            / self
+                .eccentricity_squared()
+                .mul_add(-lat.sin().powi(2), 1.0)
+                .sqrt()
+    }
+
+    /// Converts geodetic `(latitude, longitude, height)` (in degrees and metres) into
+    /// geocentric cartesian `(x, y, z)` coordinates, in metres
+    #[must_use]
+    pub fn to_geocentric(&self, (lat, lon, height): (f64, f64, f64)) -> (f64, f64, f64) {
+        let (lat, lon) = (lat.to_radians(), lon.to_radians());
+        let n = self.prime_vertical_radius(lat);
+        let z = n.mul_add(1.0 - self.eccentricity_squared(), height) * lat.sin();
+        let horizontal = (n + height) * lat.cos();
+        (horizontal * lon.cos(), horizontal * lon.sin(), z)
+    }
+
+    /// Converts geocentric cartesian `(x, y, z)` coordinates (in metres) into geodetic
+    /// `(latitude, longitude, height)` (in degrees and metres)
+    #[must_use]
+    #[allow(clippy::many_single_char_names)]
+    pub fn from_geocentric(&self, (x, y, z): (f64, f64, f64)) -> (f64, f64, f64) {
+        let e2 = self.eccentricity_squared();
+        let p = x.hypot(y);
+        let mut lat = z.atan2(p * (1.0 - e2));
+        let mut height = 0.0;
+        for _ in 0..10 {
+            let n = self.prime_vertical_radius(lat);
+            height = p / lat.cos() - n;
+            let next = z.atan2(p * (1.0 - e2 * n / (n + height)));
+            if (next - lat).abs() < 1e-15 {
+                lat = next;
+                break;
+            }
+            lat = next;
+        }
+        (lat.to_degrees(), y.atan2(x).to_degrees(), height)
+    }
+
+    /// Returns the shortest path on the ellipsoid from `from` to `to`, both `(latitude,
+    /// longitude)` in degrees, using Vincenty's inverse formulae
+    ///
+    /// The formulae are accurate to less than a millimetre, but may not converge for nearly
+    /// antipodal points, in which case the last iteration is returned.
+    #[must_use]
+    #[allow(
+        clippy::suboptimal_flops,
+        clippy::similar_names,
+        clippy::many_single_char_names
+    )]
+    pub fn geodesic(&self, from: (f64, f64), to: (f64, f64)) -> Geodesic {
+        let (a, f) = (self.semi_major_axis, self.flattening);
+        let b = self.semi_minor_axis();
+        let l = normalize_longitude(to.1 - from.1).to_radians();
+        let u1 = ((1.0 - f) * from.0.to_radians().tan()).atan();
+        let u2 = ((1.0 - f) * to.0.to_radians().tan()).atan();
+        let ((sin_u1, cos_u1), (sin_u2, cos_u2)) = (u1.sin_cos(), u2.sin_cos());
+
+        let mut lambda = l;
+        let (mut sin_sigma, mut cos_sigma, mut sigma) = (0.0, 1.0, 0.0);
+        let (mut cos2_alpha, mut cos_2sigma_m) = (1.0, 0.0);
+        for _ in 0..200 {
+            let (sin_lambda, cos_lambda) = lambda.sin_cos();
+            sin_sigma = (cos_u2 * sin_lambda).hypot(cos_u1 * sin_u2 - sin_u1 * cos_u2 * cos_lambda);
+            if sin_sigma == 0.0 {
+                // coincident points
+                return Geodesic::default();
+            }
+            cos_sigma = sin_u1 * sin_u2 + cos_u1 * cos_u2 * cos_lambda;
+            sigma = sin_sigma.atan2(cos_sigma);
+            let sin_alpha = cos_u1 * cos_u2 * sin_lambda / sin_sigma;
+            cos2_alpha = 1.0 - sin_alpha * sin_alpha;
+            // equatorial lines have cos²α = 0
+            cos_2sigma_m = if cos2_alpha == 0.0 {
+                0.0
+            } else {
+                cos_sigma - 2.0 * sin_u1 * sin_u2 / cos2_alpha
+            };
+            let c = f / 16.0 * cos2_alpha * (4.0 + f * (4.0 - 3.0 * cos2_alpha));
+            let previous = lambda;
+            lambda = l
+                + (1.0 - c)
+                    * f
+                    * sin_alpha
+                    * (sigma
+                        + c * sin_sigma
+                            * (cos_2sigma_m
+                                + c * cos_sigma * (-1.0 + 2.0 * cos_2sigma_m * cos_2sigma_m)));
+            if (lambda - previous).abs() < 1e-12 {
+                break;
+            }
+        }
+
+        let u_squared = cos2_alpha * (a * a - b * b) / (b * b);
+        let big_a = 1.0
+            + u_squared / 16384.0
+                * (4096.0 + u_squared * (-768.0 + u_squared * (320.0 - 175.0 * u_squared)));
+        let big_b = u_squared / 1024.0
+            * (256.0 + u_squared * (-128.0 + u_squared * (74.0 - 47.0 * u_squared)));
+        let delta_sigma = big_b
+            * sin_sigma
+            * (cos_2sigma_m
+                + big_b / 4.0
+                    * (cos_sigma * (-1.0 + 2.0 * cos_2sigma_m * cos_2sigma_m)
+                        - big_b / 6.0
+                            * cos_2sigma_m
+                            * (-3.0 + 4.0 * sin_sigma * sin_sigma)
+                            * (-3.0 + 4.0 * cos_2sigma_m * cos_2sigma_m)));
+        let (sin_lambda, cos_lambda) = lambda.sin_cos();
+        let bearing = |y: f64, x: f64| y.atan2(x).to_degrees().rem_euclid(360.0);
+        Geodesic {
+            distance: b * big_a * (sigma - delta_sigma),
+            initial_bearing: bearing(
+                cos_u2 * sin_lambda,
+                cos_u1 * sin_u2 - sin_u1 * cos_u2 * cos_lambda,
+            ),
+            final_bearing: bearing(
+                cos_u1 * sin_lambda,
+                -sin_u1 * cos_u2 + cos_u1 * sin_u2 * cos_lambda,
+            ),
+        }
+    }
+}
+
+impl Default for Ellipsoid {
+    fn default() -> Self {
+        Self::WGS84
+    }
+}
+
+/// The shortest path between two points on an [`Ellipsoid`] (see [`Ellipsoid::geodesic`])
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Geodesic {
+    /// The length of the path, in metres
+    pub distance: f64,
+    /// The true bearing of the path at its start, in degrees clockwise from the north
+    pub initial_bearing: f64,
+    /// The true bearing of the path at its end, in degrees clockwise from the north
+    pub final_bearing: f64,
+}
+
+/// Geodetic datums commonly found in the `GD` field of BSB/KAP charts
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Datum {
+    /// World Geodetic System 1984
+    #[default]
+    Wgs84,
+    /// North American Datum 1983
+    Nad83,
+    /// North American Datum 1927
+    Nad27,
+    /// European Datum 1950
+    Ed50,
+}
+
+impl Datum {
+    /// Returns the reference ellipsoid of the datum
+    #[must_use]
+    pub const fn ellipsoid(self) -> Ellipsoid {
+        match self {
+            Self::Wgs84 => Ellipsoid::WGS84,
+            Self::Nad83 => Ellipsoid::GRS80,
+            Self::Nad27 => Ellipsoid::CLARKE_1866,
+            Self::Ed50 => Ellipsoid::INTERNATIONAL_1924,
+        }
+    }
+
+    /// Returns the full name of the datum, e.g. `North American Datum 1983`
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Wgs84 => "World Geodetic System 1984",
+            Self::Nad83 => "North American Datum 1983",
+            Self::Nad27 => "North American Datum 1927",
+            Self::Ed50 => "European Datum 1950",
+        }
+    }
+
+    /// Returns the EPSG code of the geographic coordinate reference system of the datum
+    #[must_use]
+    pub const fn epsg(self) -> u16 {
+        match self {
+            Self::Wgs84 => 4326,
+            Self::Nad83 => 4269,
+            Self::Nad27 => 4267,
+            Self::Ed50 => 4230,
+        }
+    }
+
+    /// Returns the built-in [`Helmert`] transformation from this datum to WGS84
+    ///
+    /// The parameters are the mean three-parameter transformations published by NIMA
+    /// (TR8350.2): NAD27 for the conterminous United States and ED50 for western Europe. NAD83
+    /// is treated as equal to WGS84, which is accurate to about a metre.
+    #[must_use]
+    pub const fn to_wgs84(self) -> Helmert {
+        match self {
+            Self::Wgs84 | Self::Nad83 => Helmert::translation(0.0, 0.0, 0.0),
+            Self::Nad27 => Helmert::translation(-8.0, 160.0, 176.0),
+            Self::Ed50 => Helmert::translation(-87.0, -98.0, -121.0),
+        }
+    }
+
+    /// Converts `(latitude, longitude)` from this datum into WGS84
+    #[must_use]
+    pub fn transform_to_wgs84(self, coords: (f64, f64)) -> (f64, f64) {
+        self.to_wgs84()
+            .transform(&self.ellipsoid(), &Ellipsoid::WGS84, coords)
+    }
+
+    /// Converts WGS84 `(latitude, longitude)` into this datum
+    #[must_use]
+    pub fn transform_from_wgs84(self, coords: (f64, f64)) -> (f64, f64) {
+        let mut estimate =
+            self.to_wgs84()
+                .inverse()
+                .transform(&Ellipsoid::WGS84, &self.ellipsoid(), coords);
+        // the inverse transformation ignores ellipsoidal heights, refine the estimate so that
+        // it maps back onto `coords`
+        for _ in 0..2 {
+            let (lat, lon) = self.transform_to_wgs84(estimate);
+            estimate = (
+                estimate.0 + coords.0 - lat,
+                normalize_longitude(estimate.1 + normalize_longitude(coords.1 - lon)),
+            );
+        }
+        estimate
+    }
+}
+
+impl FromStr for Datum {
+    type Err = Error;
+
+    /// Parses a datum name as found in the `GD` field, e.g. `NAD83`, `WGS 84` or
+    /// `EUROPEAN 1950`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized: String = s
+            .chars()
+            .filter(char::is_ascii_alphanumeric)
+            .map(|c| c.to_ascii_uppercase())
+            .collect();
+        match normalized.as_str() {
+            "WGS84" | "WGS1984" | "WORLDGEODETICSYSTEM1984" => Ok(Self::Wgs84),
+            "NAD83" | "NAD1983" | "NORTHAMERICAN1983" | "NORTHAMERICANDATUM1983" => Ok(Self::Nad83),
+            "NAD27" | "NAD1927" | "NORTHAMERICAN1927" | "NORTHAMERICANDATUM1927" => Ok(Self::Nad27),
+            "ED50" | "ED1950" | "EUROPEAN1950" | "EUROPEANDATUM1950" => Ok(Self::Ed50),
+            _ => Err(Error::UnsupportedDatum(s.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for Datum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Wgs84 => "WGS84",
+            Self::Nad83 => "NAD83",
+            Self::Nad27 => "NAD27",
+            Self::Ed50 => "ED50",
+        })
+    }
+}
+
+/// A seven-parameter Helmert transformation between geocentric coordinates (position vector
+/// convention)
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Helmert {
+    /// Translation `(x, y, z)` in metres
+    pub translation: (f64, f64, f64),
+    /// Rotation `(x, y, z)` in arc-seconds
+    pub rotation: (f64, f64, f64),
+    /// Scale difference in parts per million
+    pub scale: f64,
+}
+
+impl Helmert {
+    /// Creates a three-parameter (translation only) transformation, in metres
+    #[must_use]
+    pub const fn translation(x: f64, y: f64, z: f64) -> Self {
+        Self {
+            translation: (x, y, z),
+            rotation: (0.0, 0.0, 0.0),
+            scale: 0.0,
+        }
+    }
+
+    /// Returns the inverse transformation
+    ///
+    /// This is exact for translations, and accurate to well below a millimetre for the small
+    /// rotations used between geodetic datums.
+    #[must_use]
+    pub fn inverse(&self) -> Self {
+        let (tx, ty, tz) = self.translation;
+        let (rx, ry, rz) = self.rotation;
+        Self {
+            translation: (-tx, -ty, -tz),
+            rotation: (-rx, -ry, -rz),
+            scale: -self.scale,
+        }
+    }
+
+    /// Applies the transformation to geocentric `(x, y, z)` coordinates
+    #[must_use]
+    pub fn apply(&self, (x, y, z): (f64, f64, f64)) -> (f64, f64, f64) {
+        let (tx, ty, tz) = self.translation;
+        let [rx, ry, rz] =
+            [self.rotation.0, self.rotation.1, self.rotation.2].map(|r| (r / 3600.0).to_radians());
+        let scale = self.scale.mul_add(1e-6, 1.0);
+        (
+            scale.mul_add(ry.mul_add(z, rz.mul_add(-y, x)), tx),
+            scale.mul_add(rx.mul_add(-z, rz.mul_add(x, y)), ty),
+            scale.mul_add(ry.mul_add(-x, rx.mul_add(y, z)), tz),
+        )
+    }
+
+    /// Converts `(latitude, longitude)` on the `from` ellipsoid into `(latitude, longitude)` on
+    /// the `to` ellipsoid, going through geocentric coordinates
+    #[must_use]
+    pub fn transform(
+        &self,
+        from: &Ellipsoid,
+        to: &Ellipsoid,
+        (lat, lon): (f64, f64),
+    ) -> (f64, f64) {
+        let (lat, lon, _) = to.from_geocentric(self.apply(from.to_geocentric((lat, lon, 0.0))));
+        (lat, normalize_longitude(lon))
+    }
+}
+
+/// Conversion of coordinates from the chart datum into WGS84
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum DatumShift {
+    /// A constant `(latitude, longitude)` shift in degrees, as found in the `DTM` record
+    Offset(f64, f64),
+    /// The built-in transformation of a geodetic datum (see [`Datum::to_wgs84`])
+    Datum(Datum),
+}
+
+impl DatumShift {
+    /// Returns the [`DatumShift`] of a chart
+    ///
+    /// The `DTM` record is used when present. Otherwise the built-in transformation of the
+    /// geodetic datum is used, if the datum is supported. If neither is available, the chart
+    /// datum is assumed to be WGS84.
+    #[must_use]
+    pub fn from_header(header: &ImageHeader) -> Self {
+        if let Some((lat, lon)) = header.dtm {
+            return Self::Offset(lat / 3600.0, lon / 3600.0);
+        }
+        header.datum().map_or_else(|_| Self::default(), Self::Datum)
+    }
+
+    /// Converts `(latitude, longitude)` from the chart datum into WGS84
+    #[must_use]
+    pub fn to_wgs84(&self, (lat, lon): (f64, f64)) -> (f64, f64) {
+        match *self {
+            Self::Offset(dlat, dlon) => (lat + dlat, normalize_longitude(lon + dlon)),
+            Self::Datum(datum) => datum.transform_to_wgs84((lat, lon)),
+        }
+    }
+
+    /// Converts WGS84 `(latitude, longitude)` into the chart datum
+    #[must_use]
+    pub fn from_wgs84(&self, (lat, lon): (f64, f64)) -> (f64, f64) {
+        match *self {
+            Self::Offset(dlat, dlon) => (lat - dlat, normalize_longitude(lon - dlon)),
+            Self::Datum(datum) => datum.transform_from_wgs84((lat, lon)),
+        }
+    }
+}
+
+impl Default for DatumShift {
+    fn default() -> Self {
+        Self::Offset(0.0, 0.0)
+    }
+}
+
+impl ImageHeader {
+    /// Returns the [`DatumShift`] converting coordinates in the chart datum into WGS84
+    ///
+    /// See [`DatumShift::from_header`]
+    #[must_use]
+    pub fn datum_shift(&self) -> DatumShift {
+        DatumShift::from_header(self)
+    }
+
+    /// Returns the geodetic datum found in the `GD` field of the `KNP` record
+    ///
+    /// # Errors
+    ///
+    /// This function errors if the header has no `GD` field or if the datum is not supported.
+    pub fn datum(&self) -> Result<Datum, Error> {
+        self.detailed_parameters
+            .as_ref()
+            .and_then(|p| p.geodetic_datum_name.as_deref())
+            .ok_or(Error::MissingProjectionParameter("GD"))?
+            .parse()
+    }
+
+    /// Returns the geodetic datum of the chart (see [`ImageHeader::datum`]), or WGS84 with a
+    /// warning if the datum is missing or not supported
+    #[must_use]
+    pub fn datum_or_wgs84(&self) -> Datum {
+        self.datum().unwrap_or_else(|e| {
+            warn!("Unknown chart datum ({e}), assuming {}", Datum::Wgs84);
+            Datum::Wgs84
+        })
+    }
+}
@@ -0,0 +1,174 @@
+use crate::Error;
+
+use super::{fit::fit_polynomial, GeoTransform, Model, PolynomialOrder};
+
+/// The number of samples along each axis used to fit an [`AffineApproximation`]
+const SAMPLES: u16 = 16;
+
+/// An affine transformation from pixel coordinates to model coordinates
+///
+/// The coefficients follow the order of GDAL geotransforms:
+///
+/// - `X = c[0] + c[1] * x + c[2] * y`
+/// - `Y = c[3] + c[4] * x + c[5] * y`
+///
+/// where `(x, y)` is the pixel, `(0, 0)` being the top left corner of the top left pixel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Affine(pub [f64; 6]);
+
+impl Affine {
+    /// Applies the transformation to a pixel `(x, y)`
+    #[must_use]
+    pub const fn apply(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        let [c0, c1, c2, c3, c4, c5] = self.0;
+        (
+            c2.mul_add(y, c1.mul_add(x, c0)),
+            c5.mul_add(y, c4.mul_add(x, c3)),
+        )
+    }
+
+    /// Returns the inverse transformation, or [`None`] if the transformation is singular
+    #[must_use]
+    pub fn inverse(&self) -> Option<Self> {
+        let [c0, c1, c2, c3, c4, c5] = self.0;
+        let determinant = c1.mul_add(c5, -c2 * c4);
+        if determinant == 0.0 || !determinant.is_finite() {
+            return None;
+        }
+        let (i1, i2, i4, i5) = (
+            c5 / determinant,
+            -c2 / determinant,
+            -c4 / determinant,
+            c1 / determinant,
+        );
+        Some(Self([
+            -i1.mul_add(c0, i2 * c3),
+            i1,
+            i2,
+            -i4.mul_add(c0, i5 * c3),
+            i4,
+            i5,
+        ]))
+    }
+
+    /// Returns `true` if the transformation has no rotation or shear term
+    #[must_use]
+    pub fn is_north_up(&self) -> bool {
+        self.0[2] == 0.0 && self.0[4] == 0.0
+    }
+}
+
+/// The best [`Affine`] approximation of a [`GeoTransform`] over an image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AffineApproximation {
+    /// The affine transformation from pixels into model coordinates
+    pub affine: Affine,
+    /// The largest distance, in pixels, between a pixel and its position according to the
+    /// approximation
+    pub max_error: f64,
+}
+
+impl GeoTransform {
+    /// Converts a pixel `(x, y)` into model coordinates in the chart datum
+    ///
+    /// The model coordinates are the projected `(easting, northing)` in metres if the transform
+    /// uses a [`crate::projection::Projection`] (see [`Self::projection`]), and
+    /// `(longitude, latitude)` in degrees otherwise. Longitudes are not normalized, so they stay
+    /// continuous across the antimeridian.
+    #[must_use]
+    pub fn pixel_to_model(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        match &self.model {
+            Model::Polynomials { pwx, pwy, .. } => {
+                (pwx.evaluate(x, y) + self.phase_shift, pwy.evaluate(x, y))
+            }
+            Model::Projected {
+                to_projected: [easting, northing],
+                ..
+            } => (easting.evaluate(x, y), northing.evaluate(x, y)),
+        }
+    }
+
+    /// Converts model coordinates (see [`Self::pixel_to_model`]) into a pixel `(x, y)`
+    #[must_use]
+    pub fn model_to_pixel(&self, (u, v): (f64, f64)) -> (f64, f64) {
+        match &self.model {
+            Model::Polynomials { wpx, wpy, .. } => {
+                let lon = u - self.phase_shift;
+                (wpx.evaluate(lon, v), wpy.evaluate(lon, v))
+            }
+            Model::Projected {
+                to_pixel: [x, y], ..
+            } => (x.evaluate(u, v), y.evaluate(u, v)),
+        }
+    }
+
+    /// Returns the transformation from pixels into model coordinates (see
+    /// [`Self::pixel_to_model`]) if it is exactly affine
+    ///
+    /// This is the case for charts georeferenced through their projection, and for charts
+    /// with linear `PWX`/`PWY` polynomials.
+    #[must_use]
+    pub fn affine(&self) -> Option<Affine> {
+        let ([c0, c1, c2, ..], [c3, c4, c5, ..], offset) = match &self.model {
+            Model::Polynomials { pwx, pwy, .. } => {
+                if pwx.poly[3..]
+                    .iter()
+                    .chain(&pwy.poly[3..])
+                    .any(|&c| c != 0.0)
+                {
+                    return None;
+                }
+                (pwx.poly, pwy.poly, self.phase_shift)
+            }
+            Model::Projected {
+                to_projected: [easting, northing],
+                ..
+            } => (easting.poly, northing.poly, 0.0),
+        };
+        Some(Affine([c0 + offset, c1, c2, c3, c4, c5]))
+    }
+
+    /// Fits the affine transformation closest to [`Self::pixel_to_model`] over an image of
+    /// `width * height` pixels
+    ///
+    /// The transformation is sampled on a regular grid and fitted using least squares. The
+    /// error of the approximation is measured in pixels on the same grid.
+    ///
+    /// # Errors
+    ///
+    /// This function errors if the image is empty.
+    pub fn affine_approximation(
+        &self,
+        width: u16,
+        height: u16,
+    ) -> Result<AffineApproximation, Error> {
+        let (width, height) = (f64::from(width), f64::from(height));
+        let steps = f64::from(SAMPLES - 1);
+        let pixels: Vec<_> = (0..SAMPLES)
+            .flat_map(|j| {
+                (0..SAMPLES)
+                    .map(move |i| (f64::from(i) * width / steps, f64::from(j) * height / steps))
+            })
+            .collect();
+        let affine = if let Some(affine) = self.affine() {
+            affine
+        } else {
+            let model: Vec<_> = pixels.iter().map(|&p| self.pixel_to_model(p)).collect();
+            let component =
+                |f: fn(&(f64, f64)) -> f64| -> Vec<f64> { model.iter().map(f).collect() };
+            let [c0, c1, c2, ..] =
+                fit_polynomial(&pixels, &component(|m| m.0), PolynomialOrder::Linear)?.poly;
+            let [c3, c4, c5, ..] =
+                fit_polynomial(&pixels, &component(|m| m.1), PolynomialOrder::Linear)?.poly;
+            Affine([c0, c1, c2, c3, c4, c5])
+        };
+        let max_error = pixels
+            .iter()
+            .map(|&(x, y)| {
+                let (px, py) = self.model_to_pixel(affine.apply((x, y)));
+                (px - x).hypot(py - y)
+            })
+            .fold(0.0, f64::max);
+        Ok(AffineApproximation { affine, max_error })
+    }
+}
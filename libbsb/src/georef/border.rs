@@ -0,0 +1,240 @@
+use crate::{image::header::ImageHeader, ColorPalette, Error, KapImageFile};
+
+use super::GeoTransform;
+
+/// The border polygon of a chart (`PLY` records) in pixel coordinates
+///
+/// The border delimits the valid area of the chart: everything outside of it is collar or
+/// margin. A pixel `(x, y)` is considered inside the border if its centre
+/// `(x + 0.5, y + 0.5)` is inside the polygon.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Border {
+    vertices: Vec<(f64, f64)>,
+}
+
+impl Border {
+    /// Creates a new [`Border`] from its vertices, in pixel coordinates
+    #[must_use]
+    pub const fn new(vertices: Vec<(f64, f64)>) -> Self {
+        Self { vertices }
+    }
+
+    /// Returns the vertices of the border, in pixel coordinates
+    #[must_use]
+    pub fn vertices(&self) -> &[(f64, f64)] {
+        &self.vertices
+    }
+
+    /// Returns the bounding box of the border as `((min_x, min_y), (max_x, max_y))`
+    #[must_use]
+    pub fn bounds(&self) -> ((f64, f64), (f64, f64)) {
+        self.vertices.iter().fold(
+            ((f64::MAX, f64::MAX), (f64::MIN, f64::MIN)),
+            |((min_x, min_y), (max_x, max_y)), &(x, y)| {
+                ((min_x.min(x), min_y.min(y)), (max_x.max(x), max_y.max(y)))
+            },
+        )
+    }
+
+    /// Clips the border to the rectangle `((min_x, min_y), (max_x, max_y))`, e.g. the window
+    /// of a cropped image
+    ///
+    /// The returned border has no vertices if the border does not intersect the rectangle.
+    #[must_use]
+    pub fn clip(&self, ((min_x, min_y), (max_x, max_y)): ((f64, f64), (f64, f64))) -> Self {
+        let vertices = [
+            (Axis::X, min_x, true),
+            (Axis::X, max_x, false),
+            (Axis::Y, min_y, true),
+            (Axis::Y, max_y, false),
+        ]
+        .into_iter()
+        .fold(self.vertices.clone(), |vertices, (axis, bound, above)| {
+            clip_half_plane(&vertices, axis, bound, above)
+        });
+        Self::new(vertices)
+    }
+
+    /// Returns `true` if the point `(x, y)` lies inside the border (even-odd rule)
+    #[must_use]
+    pub fn contains(&self, (x, y): (f64, f64)) -> bool {
+        self.edges()
+            .filter(|&((x1, y1), (x2, y2))| {
+                (y1 > y) != (y2 > y) && x < (x2 - x1) * (y - y1) / (y2 - y1) + x1
+            })
+            .count()
+            % 2
+            == 1
+    }
+
+    /// Returns `true` if the centre of the pixel `(x, y)` lies inside the border
+    #[must_use]
+    pub fn contains_pixel(&self, (x, y): (u16, u16)) -> bool {
+        self.contains((f64::from(x) + 0.5, f64::from(y) + 0.5))
+    }
+
+    /// Returns a row-major mask of `width * height` pixels, `true` for pixels inside the border
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn mask(&self, width: u16, height: u16) -> Vec<bool> {
+        let max_x = f64::from(width);
+        let mut mask = vec![false; usize::from(width) * usize::from(height)];
+        let mut crossings = Vec::new();
+        for (y, row) in (0..height).zip(mask.chunks_exact_mut(usize::from(width).max(1))) {
+            let centre = f64::from(y) + 0.5;
+            crossings.clear();
+            crossings.extend(
+                self.edges()
+                    .filter(|&((_, y1), (_, y2))| (y1 > centre) != (y2 > centre))
+                    .map(|((x1, y1), (x2, y2))| (x2 - x1) * (centre - y1) / (y2 - y1) + x1),
+            );
+            crossings.sort_by(f64::total_cmp);
+            for span in crossings.chunks_exact(2) {
+                // pixels whose centre lies within [span[0], span[1])
+                let start = (span[0] - 0.5).ceil().clamp(0.0, max_x) as usize;
+                let end = (span[1] - 0.5).ceil().clamp(0.0, max_x) as usize;
+                row[start..end.max(start)].fill(true);
+            }
+        }
+        mask
+    }
+
+    /// Iterates over the edges of the (closed) polygon
+    fn edges(&self) -> impl Iterator<Item = ((f64, f64), (f64, f64))> + '_ {
+        self.vertices
+            .iter()
+            .copied()
+            .zip(self.vertices.iter().copied().cycle().skip(1))
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Axis {
+    X,
+    Y,
+}
+
+/// Clips a polygon to the half plane where the `axis` coordinate is above (or below) `bound`
+/// (Sutherland–Hodgman)
+fn clip_half_plane(
+    vertices: &[(f64, f64)],
+    axis: Axis,
+    bound: f64,
+    above: bool,
+) -> Vec<(f64, f64)> {
+    let value = |(x, y): (f64, f64)| match axis {
+        Axis::X => x,
+        Axis::Y => y,
+    };
+    let inside = |point| {
+        if above {
+            value(point) >= bound
+        } else {
+            value(point) <= bound
+        }
+    };
+    let mut clipped = Vec::with_capacity(vertices.len() + 2);
+    for (i, &current) in vertices.iter().enumerate() {
+        let previous = vertices[(i + vertices.len() - 1) % vertices.len()];
+        if inside(current) != inside(previous) {
+            let t = (bound - value(previous)) / (value(current) - value(previous));
+            let along = |a: f64, b: f64| (b - a).mul_add(t, a);
+            clipped.push(match axis {
+                Axis::X => (bound, along(previous.1, current.1)),
+                Axis::Y => (along(previous.0, current.0), bound),
+            });
+        }
+        if inside(current) {
+            clipped.push(current);
+        }
+    }
+    clipped
+}
+
+impl GeoTransform {
+    /// Projects a polygon given in `(latitude, longitude)` (e.g. [`ImageHeader::ply`]) into a
+    /// [`Border`] in pixel coordinates
+    #[must_use]
+    pub fn border(&self, ply: &[(f64, f64)]) -> Border {
+        Border::new(ply.iter().map(|&c| self.coords_to_pixel(c)).collect())
+    }
+}
+
+impl ImageHeader {
+    /// Returns the [`Border`] described by the `PLY` records, or [`None`] if the header has no
+    /// `PLY` records
+    ///
+    /// # Errors
+    ///
+    /// This function errors if the header has `PLY` records but cannot be georeferenced (see
+    /// [`GeoTransform::from_header`]).
+    pub fn border(&self) -> Result<Option<Border>, Error> {
+        match self.ply.as_deref() {
+            Some(ply) if !ply.is_empty() => Ok(Some(self.geo_transform()?.border(ply))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns `true` if the centre of the pixel `(x, y)` lies inside the image and inside the
+    /// `PLY` border (if any)
+    ///
+    /// # Errors
+    ///
+    /// See [`Self::border`]
+    pub fn contains_pixel(&self, (x, y): (u16, u16)) -> Result<bool, Error> {
+        if x >= self.width() || y >= self.height() {
+            return Ok(false);
+        }
+        Ok(self.border()?.is_none_or(|b| b.contains_pixel((x, y))))
+    }
+
+    /// Returns `true` if the `(latitude, longitude)` position (in the chart datum) lies inside
+    /// the image and inside the `PLY` border (if any)
+    ///
+    /// # Errors
+    ///
+    /// This function errors if the header cannot be georeferenced (see
+    /// [`GeoTransform::from_header`]).
+    pub fn contains_coords(&self, coords: (f64, f64)) -> Result<bool, Error> {
+        let (x, y) = self.geo_transform()?.coords_to_pixel(coords);
+        let inside_image = (0.0..f64::from(self.width())).contains(&x)
+            && (0.0..f64::from(self.height())).contains(&y);
+        Ok(inside_image && self.border()?.is_none_or(|b| b.contains((x, y))))
+    }
+}
+
+impl KapImageFile {
+    /// Returns a row-major mask of the image, `true` for pixels inside the `PLY` border
+    ///
+    /// All pixels are considered inside if the header has no `PLY` records.
+    ///
+    /// # Errors
+    ///
+    /// See [`ImageHeader::border`]
+    pub fn border_mask(&self) -> Result<Vec<bool>, Error> {
+        let (width, height) = (self.width(), self.height());
+        Ok(self.header().border()?.map_or_else(
+            || vec![true; usize::from(width) * usize::from(height)],
+            |border| border.mask(width, height),
+        ))
+    }
+
+    /// Returns an iterator over the RGBA colors the pixel indices correspond to, with pixels
+    /// outside of the `PLY` border made fully transparent
+    ///
+    /// See [`Self::as_palette_iter`] and [`Self::border_mask`].
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the palette does not exist, or if the border cannot be computed.
+    pub fn as_rgba_palette_iter(
+        &self,
+        palette: ColorPalette,
+    ) -> Result<impl Iterator<Item = [u8; 4]> + '_, Error> {
+        let mask = self.border_mask()?;
+        Ok(self
+            .as_palette_iter(palette)?
+            .zip(mask)
+            .map(|([r, g, b], inside)| if inside { [r, g, b, 255] } else { [0; 4] }))
+    }
+}
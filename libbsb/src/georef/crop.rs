@@ -0,0 +1,119 @@
+use crate::{Error, KapImageFile};
+
+use super::{normalize_longitude, Affine};
+
+/// The number of intervals sampled along each edge of a bounding box to find the pixels it
+/// covers
+const EDGE_SAMPLES: u16 = 16;
+
+impl KapImageFile {
+    /// Extracts the window of `size` pixels whose top left pixel is `origin`
+    ///
+    /// The georeferencing of the header follows the window: the `REF` records and the
+    /// polynomials are shifted, and the `PLY` border is clipped to the window. `REF` records
+    /// outside of the window are dropped, and the corners of the window are used if fewer than
+    /// 3 remain. See [`Self::crop_to_coords`] to crop a bounding box instead.
+    ///
+    /// # Errors
+    ///
+    /// This function errors if the window is empty or does not fit within the image, or if the
+    /// header has a `PLY` border but cannot be georeferenced.
+    pub fn crop(&self, origin: (u16, u16), size: (u16, u16)) -> Result<Self, Error> {
+        let ((x, y), (width, height)) = (origin, size);
+        if width == 0
+            || height == 0
+            || u32::from(x) + u32::from(width) > u32::from(self.width())
+            || u32::from(y) + u32::from(height) > u32::from(self.height())
+        {
+            return Err(Error::InvalidWindow {
+                origin,
+                size,
+                image: (self.width(), self.height()),
+            });
+        }
+        let row = usize::from(self.width());
+        let (x, y, width, height) = (
+            usize::from(x),
+            usize::from(y),
+            usize::from(width),
+            usize::from(height),
+        );
+        let raster = (y..y + height)
+            .flat_map(|j| &self.pixel_indices()[j * row + x..j * row + x + width])
+            .copied()
+            .collect();
+
+        let mut header = self.header().clone();
+        if let (Some(border), Some(ply)) = (self.header().border()?, self.header().ply.as_ref()) {
+            let transform = self.geo_transform()?;
+            let (x, y) = (f64::from(origin.0), f64::from(origin.1));
+            let window = ((x, y), (x + f64::from(size.0), y + f64::from(size.1)));
+            let clipped = border.clip(window);
+            // keep the original coordinates of the vertices inside the window
+            let vertices: Vec<_> = clipped
+                .vertices()
+                .iter()
+                .map(|&vertex| {
+                    border
+                        .vertices()
+                        .iter()
+                        .position(|&v| v == vertex)
+                        .map_or_else(|| transform.pixel_to_coords(vertex), |i| ply[i])
+                })
+                .collect();
+            header.ply = (vertices.len() >= 3).then_some(vertices);
+        }
+        header.remap_pixels(
+            Affine([
+                -f64::from(origin.0),
+                1.0,
+                0.0,
+                -f64::from(origin.1),
+                0.0,
+                1.0,
+            ]),
+            size,
+        )?;
+        Self::new(header, raster)
+    }
+
+    /// Extracts the smallest window covering the `(latitude, longitude)` bounding box from
+    /// `south_west` to `north_east`, in the chart datum, clamped to the image
+    ///
+    /// The bounding box crosses the antimeridian if its western longitude is greater than its
+    /// eastern longitude. See [`Self::crop`].
+    ///
+    /// # Errors
+    ///
+    /// This function errors if the image cannot be georeferenced, if the bounding box does not
+    /// intersect the image, or for any of the reasons listed in [`Self::crop`].
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn crop_to_coords(
+        &self,
+        (south, west): (f64, f64),
+        (north, east): (f64, f64),
+    ) -> Result<Self, Error> {
+        let transform = self.geo_transform()?;
+        let span = (east - west).rem_euclid(360.0);
+        let (mut min, mut max) = ((f64::MAX, f64::MAX), (f64::MIN, f64::MIN));
+        for i in 0..=EDGE_SAMPLES {
+            let t = f64::from(i) / f64::from(EDGE_SAMPLES);
+            let (lat, lon) = ((north - south).mul_add(t, south), span.mul_add(t, west));
+            for coords in [(south, lon), (north, lon), (lat, west), (lat, west + span)] {
+                let (x, y) = transform.coords_to_pixel((coords.0, normalize_longitude(coords.1)));
+                min = (min.0.min(x), min.1.min(y));
+                max = (max.0.max(x), max.1.max(y));
+            }
+        }
+        let clamp = |value: f64, size: u16| value.clamp(0.0, f64::from(size)) as u16;
+        let origin = (
+            clamp(min.0.floor(), self.width()),
+            clamp(min.1.floor(), self.height()),
+        );
+        let end = (
+            clamp(max.0.ceil(), self.width()),
+            clamp(max.1.ceil(), self.height()),
+        );
+        self.crop(origin, (end.0 - origin.0, end.1 - origin.1))
+    }
+}
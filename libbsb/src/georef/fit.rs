@@ -0,0 +1,288 @@
+use crate::{
+    geodesy::DatumShift,
+    image::header::{ImageHeader, Polynomial, Ref},
+    Error,
+};
+
+use super::{normalize_longitude, GeoTransform, Model};
+
+/// Order of the polynomials fitted by [`ImageHeader::fit_polynomials`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PolynomialOrder {
+    /// First order polynomials (affine transformation). Requires at least 3 reference points
+    Linear,
+    /// Second order polynomials. Requires at least 6 reference points
+    #[default]
+    Quadratic,
+}
+
+impl PolynomialOrder {
+    /// Returns the number of coefficients used by a polynomial of this order
+    #[must_use]
+    pub const fn terms(self) -> usize {
+        match self {
+            Self::Linear => 3,
+            Self::Quadratic => 6,
+        }
+    }
+
+    /// Returns the highest order supported by `count` reference points
+    #[must_use]
+    pub const fn for_point_count(count: usize) -> Self {
+        if count >= Self::Quadratic.terms() {
+            Self::Quadratic
+        } else {
+            Self::Linear
+        }
+    }
+}
+
+impl From<PolynomialOrder> for usize {
+    fn from(value: PolynomialOrder) -> Self {
+        match value {
+            PolynomialOrder::Linear => 1,
+            PolynomialOrder::Quadratic => 2,
+        }
+    }
+}
+
+impl TryFrom<usize> for PolynomialOrder {
+    type Error = Error;
+
+    fn try_from(value: usize) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::Linear),
+            2 => Ok(Self::Quadratic),
+            o => Err(Error::UnsupportedPolynomialOrder(o)),
+        }
+    }
+}
+
+impl GeoTransform {
+    /// Fits a [`GeoTransform`] to a set of reference points using least squares
+    ///
+    /// The phase shift is set to 180 degrees if the reference points span the antimeridian.
+    ///
+    /// # Errors
+    ///
+    /// This function errors if there are fewer reference points than the number of
+    /// coefficients required by `order` (see [`PolynomialOrder::terms`]), or if the reference
+    /// points are degenerate (e.g. all on a single line).
+    pub fn fit(refs: &[Ref], order: PolynomialOrder) -> Result<Self, Error> {
+        let phase_shift = detect_phase_shift(refs);
+        let [wpx, wpy, pwx, pwy] = fit_polynomials(refs, order, phase_shift)?;
+        Ok(Self {
+            model: Model::Polynomials { wpx, wpy, pwx, pwy },
+            phase_shift,
+            datum_shift: DatumShift::default(),
+        })
+    }
+
+    /// Fits a [`GeoTransform`] to the `REF` records of a header lacking polynomials
+    pub(super) fn fit_header(header: &ImageHeader) -> Result<Self, Error> {
+        let refs = header.reference_point_record.as_deref().unwrap_or_default();
+        if refs.len() < PolynomialOrder::Linear.terms() {
+            return Err(Error::MissingGeoreference);
+        }
+        let phase_shift = header
+            .phase_shift
+            .unwrap_or_else(|| detect_phase_shift(refs));
+        let order = PolynomialOrder::for_point_count(refs.len());
+        let [wpx, wpy, pwx, pwy] = fit_polynomials(refs, order, phase_shift)?;
+        Ok(Self {
+            model: Model::Polynomials { wpx, wpy, pwx, pwy },
+            phase_shift,
+            datum_shift: header.datum_shift(),
+        })
+    }
+}
+
+impl ImageHeader {
+    /// Fits the `WPX`, `WPY`, `PWX` and `PWY` polynomials to the reference points found in
+    /// [`ImageHeader::reference_point_record`], replacing any existing polynomials
+    ///
+    /// If [`ImageHeader::phase_shift`] is [`None`] and the reference points span the
+    /// antimeridian, it is set to 180 degrees.
+    ///
+    /// # Errors
+    ///
+    /// This function errors if the header has no reference points, or for any of the reasons
+    /// listed in [`GeoTransform::fit`].
+    pub fn fit_polynomials(&mut self, order: PolynomialOrder) -> Result<(), Error> {
+        let refs = self.reference_point_record.as_deref().ok_or_else(|| {
+            Error::InsufficientReferencePoints {
+                required: order.terms(),
+                found: 0,
+            }
+        })?;
+        let phase_shift = self.phase_shift.unwrap_or_else(|| detect_phase_shift(refs));
+        let [wpx, wpy, pwx, pwy] = fit_polynomials(refs, order, phase_shift)?;
+        if phase_shift != 0.0 {
+            self.phase_shift = Some(phase_shift);
+        }
+        self.wpx = Some(wpx);
+        self.wpy = Some(wpy);
+        self.pwx = Some(pwx);
+        self.pwy = Some(pwy);
+        Ok(())
+    }
+}
+
+/// Returns a phase shift of 180 degrees if the reference points span the antimeridian
+fn detect_phase_shift(refs: &[Ref]) -> f64 {
+    let (min, max) = refs.iter().fold((f64::MAX, f64::MIN), |(min, max), r| {
+        (min.min(r.coords.1), max.max(r.coords.1))
+    });
+    if max - min > 180.0 {
+        180.0
+    } else {
+        0.0
+    }
+}
+
+/// Fits the `[WPX, WPY, PWX, PWY]` polynomials to the reference points
+#[allow(clippy::cast_precision_loss)]
+fn fit_polynomials(
+    refs: &[Ref],
+    order: PolynomialOrder,
+    phase_shift: f64,
+) -> Result<[Polynomial; 4], Error> {
+    if refs.len() < order.terms() {
+        return Err(Error::InsufficientReferencePoints {
+            required: order.terms(),
+            found: refs.len(),
+        });
+    }
+    let coords: Vec<_> = refs
+        .iter()
+        .map(|r| {
+            let (lat, lon) = r.coords;
+            (normalize_longitude(lon - phase_shift), lat)
+        })
+        .collect();
+    let pixels: Vec<_> = refs
+        .iter()
+        .map(|r| (r.pixels.0 as f64, r.pixels.1 as f64))
+        .collect();
+
+    let component = |values: &[(f64, f64)], f: fn(&(f64, f64)) -> f64| -> Vec<f64> {
+        values.iter().map(f).collect()
+    };
+    Ok([
+        fit_polynomial(&coords, &component(&pixels, |p| p.0), order)?,
+        fit_polynomial(&coords, &component(&pixels, |p| p.1), order)?,
+        fit_polynomial(&pixels, &component(&coords, |c| c.0), order)?,
+        fit_polynomial(&pixels, &component(&coords, |c| c.1), order)?,
+    ])
+}
+
+/// Fits a polynomial `f(u, v) = w` of the given order using least squares
+///
+/// The inputs are normalized before fitting to keep the normal equations well conditioned;
+/// the resulting coefficients are then expanded back into the original input space.
+pub(super) fn fit_polynomial(
+    inputs: &[(f64, f64)],
+    outputs: &[f64],
+    order: PolynomialOrder,
+) -> Result<Polynomial, Error> {
+    debug_assert_eq!(inputs.len(), outputs.len());
+    let terms = order.terms();
+    if inputs.len() < terms {
+        return Err(Error::InsufficientReferencePoints {
+            required: terms,
+            found: inputs.len(),
+        });
+    }
+
+    let (u_norm, v_norm) = (
+        Normalization::new(inputs.iter().map(|i| i.0)),
+        Normalization::new(inputs.iter().map(|i| i.1)),
+    );
+
+    // accumulate the normal equations (AᵀA)x = Aᵀb
+    let mut ata = [[0.0; 6]; 6];
+    let mut atb = [0.0; 6];
+    for (&(u, v), &w) in inputs.iter().zip(outputs) {
+        let row = monomials(u_norm.apply(u), v_norm.apply(v));
+        for i in 0..terms {
+            for j in 0..terms {
+                ata[i][j] += row[i] * row[j];
+            }
+            atb[i] += row[i] * w;
+        }
+    }
+    let solution = solve(ata, atb, terms).ok_or(Error::DegenerateReferencePoints)?;
+
+    let normalized = Polynomial::new(usize::from(order), solution);
+    Ok(normalized.compose(&u_norm.as_polynomial(false), &v_norm.as_polynomial(true)))
+}
+
+/// Returns the monomials `[1, u, v, u*u, u*v, v*v]`
+const fn monomials(u: f64, v: f64) -> [f64; 6] {
+    [1.0, u, v, u * u, u * v, v * v]
+}
+
+/// Solves the linear system `a * x = b` of size `n` using Gaussian elimination with partial
+/// pivoting. Returns [`None`] if the system is singular
+fn solve(mut a: [[f64; 6]; 6], mut b: [f64; 6], n: usize) -> Option<[f64; 6]> {
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        let pivot_row = a[col];
+        for row in col + 1..n {
+            let factor = a[row][col] / pivot_row[col];
+            for (value, pivot_value) in a[row][col..n].iter_mut().zip(&pivot_row[col..n]) {
+                *value -= factor * pivot_value;
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+    let mut x = [0.0; 6];
+    for row in (0..n).rev() {
+        let sum: f64 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+/// Centers and scales values into roughly `[-1, 1]`
+struct Normalization {
+    offset: f64,
+    scale: f64,
+}
+
+impl Normalization {
+    fn new(values: impl Iterator<Item = f64>) -> Self {
+        let (min, max) = values.fold((f64::MAX, f64::MIN), |(min, max), v| {
+            (min.min(v), max.max(v))
+        });
+        let half_range = (max - min) / 2.0;
+        Self {
+            offset: f64::midpoint(max, min),
+            scale: if half_range > f64::EPSILON {
+                half_range
+            } else {
+                1.0
+            },
+        }
+    }
+
+    fn apply(&self, value: f64) -> f64 {
+        (value - self.offset) / self.scale
+    }
+
+    /// Returns the normalization as a linear polynomial of `u` (or `v` if `is_v`)
+    fn as_polynomial(&self, is_v: bool) -> Polynomial {
+        let factor = 1.0 / self.scale;
+        let constant = -self.offset / self.scale;
+        if is_v {
+            Polynomial::new(1, [constant, 0.0, factor, 0.0, 0.0, 0.0])
+        } else {
+            Polynomial::new(1, [constant, factor, 0.0, 0.0, 0.0, 0.0])
+        }
+    }
+}
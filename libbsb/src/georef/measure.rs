@@ -0,0 +1,71 @@
+use crate::{
+    geodesy::{Ellipsoid, Geodesic},
+    Error, GeoTransform, KapImageFile,
+};
+
+impl KapImageFile {
+    /// Returns the shortest path on the ellipsoid of the chart datum between the pixel
+    /// coordinates `from` and `to`, e.g. to check the length and true bearing of a leg plotted
+    /// on the chart
+    ///
+    /// Charts with a missing or unknown datum are measured on the WGS84 ellipsoid.
+    ///
+    /// # Errors
+    ///
+    /// This function errors if the image cannot be georeferenced (see
+    /// [`super::GeoTransform::from_header`]).
+    pub fn geodesic_between_pixels(
+        &self,
+        from: (f64, f64),
+        to: (f64, f64),
+    ) -> Result<Geodesic, Error> {
+        let transform = self.geo_transform()?;
+        Ok(self.geodesic(&transform, from, transform.pixel_to_coords(to)))
+    }
+
+    /// Returns the shortest path on the ellipsoid of the chart datum from the pixel coordinates
+    /// `from` to the `(latitude, longitude)` `to`, in the chart datum
+    ///
+    /// # Errors
+    ///
+    /// See [`Self::geodesic_between_pixels`]
+    pub fn geodesic_to_coords(&self, from: (f64, f64), to: (f64, f64)) -> Result<Geodesic, Error> {
+        Ok(self.geodesic(&self.geo_transform()?, from, to))
+    }
+
+    /// Returns the width and height in metres of a pixel centred on the pixel coordinates
+    /// `(x, y)`
+    ///
+    /// # Errors
+    ///
+    /// See [`Self::geodesic_between_pixels`]
+    pub fn pixel_size_at(&self, (x, y): (f64, f64)) -> Result<(f64, f64), Error> {
+        let ellipsoid = self.ellipsoid();
+        let transform = self.geo_transform()?;
+        let distance = |from: (f64, f64), to: (f64, f64)| {
+            ellipsoid
+                .geodesic(
+                    transform.pixel_to_coords(from),
+                    transform.pixel_to_coords(to),
+                )
+                .distance
+        };
+        Ok((
+            distance((x - 0.5, y), (x + 0.5, y)),
+            distance((x, y - 0.5), (x, y + 0.5)),
+        ))
+    }
+
+    /// Returns the shortest path from the pixel coordinates `from`, georeferenced with
+    /// `transform`, to the `(latitude, longitude)` `to`
+    fn geodesic(&self, transform: &GeoTransform, from: (f64, f64), to: (f64, f64)) -> Geodesic {
+        self.ellipsoid()
+            .geodesic(transform.pixel_to_coords(from), to)
+    }
+
+    /// Returns the ellipsoid of the chart datum, or the WGS84 ellipsoid if the datum is missing
+    /// or unknown
+    fn ellipsoid(&self) -> Ellipsoid {
+        self.header().datum_or_wgs84().ellipsoid()
+    }
+}
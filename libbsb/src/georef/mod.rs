@@ -0,0 +1,266 @@
+//! Georeferencing of BSB/KAP image files
+//!
+//! BSB/KAP headers describe the relationship between pixel coordinates and geographical
+//! coordinates through four polynomials:
+//!
+//! - `WPX`/`WPY` map a (longitude, latitude) pair to a pixel `x`/`y`
+//! - `PWX`/`PWY` map a pixel (x, y) pair to a longitude/latitude
+//!
+//! [`GeoTransform`] evaluates these polynomials, taking care of the longitude phase shift
+//! (`CPH`) and the datum shift (`DTM`) records. The polynomials can be fitted from the `REF`
+//! records with [`ImageHeader::fit_polynomials`], and checked against them with
+//! [`ImageHeader::residual_report`].
+//!
+//! The `PLY` border of a chart can be projected into pixel coordinates with
+//! [`ImageHeader::border`], to test whether a position lies inside the valid area of the chart
+//! or to mask out its collar (see [`KapImageFile::as_rgba_palette_iter`]).
+//!
+//! Charts without polynomials are georeferenced through their projection instead (see
+//! [`crate::projection`]): the `REF` records are projected, and an affine transformation is
+//! fitted between projected coordinates and pixels.
+//!
+//! [`KapImageFile::geodesic_between_pixels`] and [`KapImageFile::geodesic_to_coords`] measure
+//! the distance and true bearing between positions on a chart, and
+//! [`KapImageFile::pixel_size_at`] the size of its pixels in metres.
+//!
+//! GIS formats usually expect an [`Affine`] transformation between pixels and model coordinates
+//! (projected coordinates, or longitude/latitude for charts georeferenced through polynomials).
+//! [`GeoTransform::affine`] returns it when the georeferencing is exactly affine, and
+//! [`GeoTransform::affine_approximation`] fits the closest one otherwise.
+//!
+//! [`KapImageFile::crop`] and [`KapImageFile::crop_to_coords`] extract a window of a chart,
+//! keeping its georeferencing, and [`KapImageFile::split`] cuts a chart into sub-charts (see
+//! [`SplitLayout`]).
+//!
+//! [`KapImageFile::resize`] scales a chart with palette-safe filters (see [`ResizeFilter`]),
+//! keeping its georeferencing.
+//!
+//! [`KapImageFile::reorient`] rotates or flips a chart (see [`Reorientation`]), and
+//! [`KapImageFile::north_up`] rotates a skewed chart so that the north points up, both keeping
+//! its georeferencing.
+//!
+//! [`KapImageFile::reproject`] resamples a chart into another projection (see [`crate::Crs`]),
+//! rewriting the georeferencing of its header, and [`KapImageFile::mosaic`] merges several
+//! charts into one.
+//!
+//! Coordinates are always given as `(latitude, longitude)` in decimal degrees, matching the
+//! order used by the `REF` and `PLY` records. Pixel coordinates are given as `(x, y)`.
+
+mod affine;
+mod border;
+mod crop;
+mod fit;
+mod measure;
+mod mosaic;
+mod orient;
+mod projected;
+mod remap;
+mod reproject;
+mod resample;
+mod residual;
+mod resize;
+mod split;
+
+pub use affine::{Affine, AffineApproximation};
+pub use border::Border;
+pub use fit::PolynomialOrder;
+pub use orient::Reorientation;
+pub use resample::sample_grid;
+pub use residual::{Residual, ResidualReport};
+pub use resize::ResizeFilter;
+pub use split::SplitLayout;
+
+use crate::{
+    geodesy::DatumShift,
+    image::header::{ImageHeader, Polynomial},
+    projection::Projection,
+    Error, KapImageFile,
+};
+
+/// Maps pixel coordinates to geographical coordinates and back
+///
+/// A [`GeoTransform`] is usually obtained through [`ImageHeader::geo_transform`] or
+/// [`KapImageFile::geo_transform`].
+///
+/// [`Self::pixel_to_coords`] and [`Self::coords_to_pixel`] work with coordinates in the chart
+/// datum (see [`crate::image::raw::header::DetailedParameters::geodetic_datum_name`]).
+/// [`Self::pixel_to_wgs84`] and [`Self::wgs84_to_pixel`] additionally convert between the chart
+/// datum and WGS84 (see [`DatumShift`]).
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoTransform {
+    model: Model,
+    /// Longitude phase shift in degrees
+    phase_shift: f64,
+    /// Conversion from the chart datum into WGS84
+    datum_shift: DatumShift,
+}
+
+/// The mapping between pixels and coordinates in the chart datum
+#[derive(Debug, Clone, PartialEq)]
+enum Model {
+    /// The `WPX`, `WPY`, `PWX` and `PWY` polynomials
+    Polynomials {
+        wpx: Polynomial,
+        wpy: Polynomial,
+        pwx: Polynomial,
+        pwy: Polynomial,
+    },
+    /// A projection followed by an affine transformation into pixels
+    Projected {
+        projection: Projection,
+        /// Projected coordinates to pixel `x`/`y`
+        to_pixel: [Polynomial; 2],
+        /// Pixel to projected easting/northing
+        to_projected: [Polynomial; 2],
+    },
+}
+
+impl GeoTransform {
+    /// Creates a [`GeoTransform`] from the `WPX`, `WPY`, `PWX`, `PWY`, `CPH` and `DTM` records of
+    /// an [`ImageHeader`]
+    ///
+    /// If the polynomials are missing, the chart is georeferenced through its projection and
+    /// the `REF` records (see [`Self::from_projection`]). If the projection is missing or not
+    /// supported, the polynomials are fitted to the `REF` records instead, using the highest
+    /// [`PolynomialOrder`] the number of reference points allows.
+    ///
+    /// # Errors
+    ///
+    /// This function errors if the polynomials are missing and cannot be derived from the
+    /// projection or the reference points, or if a polynomial has an unsupported order.
+    pub fn from_header(header: &ImageHeader) -> Result<Self, Error> {
+        let (Some(wpx), Some(wpy), Some(pwx), Some(pwy)) = (
+            header.wpx.as_ref(),
+            header.wpy.as_ref(),
+            header.pwx.as_ref(),
+            header.pwy.as_ref(),
+        ) else {
+            return match Projection::from_header(header) {
+                Ok(projection) => Self::from_projection_header(header, projection),
+                Err(Error::UnsupportedProjection(_) | Error::MissingProjectionParameter("PR")) => {
+                    Self::fit_header(header)
+                }
+                Err(e) => Err(e),
+            };
+        };
+        for poly in [wpx, wpy, pwx, pwy] {
+            if !(1..=2).contains(&poly.corner) {
+                return Err(Error::UnsupportedPolynomialOrder(poly.corner));
+            }
+        }
+        Ok(Self {
+            model: Model::Polynomials {
+                wpx: wpx.clone(),
+                wpy: wpy.clone(),
+                pwx: pwx.clone(),
+                pwy: pwy.clone(),
+            },
+            phase_shift: header.phase_shift.unwrap_or_default(),
+            datum_shift: header.datum_shift(),
+        })
+    }
+
+    /// Converts a pixel `(x, y)` into `(latitude, longitude)` in the chart datum
+    #[must_use]
+    pub fn pixel_to_coords(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        match &self.model {
+            Model::Polynomials { pwx, pwy, .. } => {
+                let lon = pwx.evaluate(x, y);
+                let lat = pwy.evaluate(x, y);
+                (lat, normalize_longitude(lon + self.phase_shift))
+            }
+            Model::Projected {
+                projection,
+                to_projected: [easting, northing],
+                ..
+            } => projection.inverse((easting.evaluate(x, y), northing.evaluate(x, y))),
+        }
+    }
+
+    /// Converts `(latitude, longitude)` in the chart datum into a pixel `(x, y)`
+    #[must_use]
+    pub fn coords_to_pixel(&self, (lat, lon): (f64, f64)) -> (f64, f64) {
+        match &self.model {
+            Model::Polynomials { wpx, wpy, .. } => {
+                // bring the longitude into the phase used by the polynomials, undoing the shift
+                // of `pixel_to_coords` for polynomial longitudes in the `[-180, 180)` range
+                let lon = normalize_longitude(lon - self.phase_shift);
+                (wpx.evaluate(lon, lat), wpy.evaluate(lon, lat))
+            }
+            Model::Projected {
+                projection,
+                to_pixel: [x, y],
+                ..
+            } => {
+                let (easting, northing) = projection.forward((lat, lon));
+                (x.evaluate(easting, northing), y.evaluate(easting, northing))
+            }
+        }
+    }
+
+    /// Converts a pixel `(x, y)` into WGS84 `(latitude, longitude)`, applying the datum shift
+    #[must_use]
+    pub fn pixel_to_wgs84(&self, pixel: (f64, f64)) -> (f64, f64) {
+        self.datum_shift.to_wgs84(self.pixel_to_coords(pixel))
+    }
+
+    /// Converts WGS84 `(latitude, longitude)` into a pixel `(x, y)`, applying the datum shift
+    #[must_use]
+    pub fn wgs84_to_pixel(&self, coords: (f64, f64)) -> (f64, f64) {
+        self.coords_to_pixel(self.datum_shift.from_wgs84(coords))
+    }
+
+    /// Returns the conversion from the chart datum into WGS84
+    #[must_use]
+    pub const fn datum_shift(&self) -> DatumShift {
+        self.datum_shift
+    }
+
+    /// Replaces the conversion from the chart datum into WGS84
+    ///
+    /// Transforms created with [`Self::fit`] or [`Self::from_projection`] assume the chart
+    /// datum is WGS84.
+    #[must_use]
+    pub const fn with_datum_shift(mut self, datum_shift: DatumShift) -> Self {
+        self.datum_shift = datum_shift;
+        self
+    }
+
+    /// Returns the `CPH` longitude phase shift in degrees
+    #[must_use]
+    pub const fn phase_shift(&self) -> f64 {
+        self.phase_shift
+    }
+}
+
+/// Brings a longitude into the `[-180, 180)` range
+#[must_use]
+pub fn normalize_longitude(lon: f64) -> f64 {
+    if (-180.0..180.0).contains(&lon) {
+        lon
+    } else {
+        (lon + 180.0).rem_euclid(360.0) - 180.0
+    }
+}
+
+impl ImageHeader {
+    /// Returns the [`GeoTransform`] described by this header
+    ///
+    /// # Errors
+    ///
+    /// See [`GeoTransform::from_header`]
+    pub fn geo_transform(&self) -> Result<GeoTransform, Error> {
+        GeoTransform::from_header(self)
+    }
+}
+
+impl KapImageFile {
+    /// Returns the [`GeoTransform`] described by the image header
+    ///
+    /// # Errors
+    ///
+    /// See [`GeoTransform::from_header`]
+    pub fn geo_transform(&self) -> Result<GeoTransform, Error> {
+        self.header().geo_transform()
+    }
+}
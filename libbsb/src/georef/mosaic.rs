@@ -0,0 +1,459 @@
+use std::collections::HashMap;
+
+use crate::{crs::Crs, image::header::ImageHeader, ColorPalette, Depth, Error, KapImageFile};
+
+use super::{
+    reproject::{centre_pixel_size, projected_extent, DatumConversion, Grid},
+    resample::sample_grid,
+    GeoTransform, PolynomialOrder,
+};
+
+/// The largest number of colours of a BSB/KAP palette
+const MAX_COLORS: usize = 127;
+/// The largest distance in pixels between the outline of the pixels covered by the charts and
+/// the `PLY` border of the mosaic
+const OUTLINE_TOLERANCE: f64 = 1.0;
+/// The offsets of the vertices of pixel corners along each direction of an outline: east,
+/// south, west and north, clockwise in pixel coordinates
+const DIRECTIONS: [(isize, isize); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+/// A colour of a merged palette
+struct PaletteEntry {
+    /// The colour in each of the merged palettes
+    colors: Vec<(u8, u8, u8)>,
+    /// The number of pixels of the mosaic using the colour
+    count: usize,
+}
+
+impl PaletteEntry {
+    fn distance(&self, other: &Self) -> u32 {
+        self.colors
+            .iter()
+            .zip(&other.colors)
+            .map(|(a, b)| {
+                let d = |a: u8, b: u8| u32::from(a.abs_diff(b)).pow(2);
+                d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+            })
+            .sum()
+    }
+}
+
+/// Merges the palettes of `charts` into a single palette of at most [`MAX_COLORS`] entries
+///
+/// `counts[chart][index]` is the number of pixels of the mosaic using `index` of `chart`.
+/// Identical colours are merged first, then the closest pairs of colours, the least used colour
+/// of each pair being replaced by the other one. Returns the merged palette and the index of the
+/// merged palette of each index of each chart.
+fn merge_palettes(
+    charts: &[KapImageFile],
+    palettes: &[ColorPalette],
+    counts: &[[usize; 256]],
+) -> (Vec<PaletteEntry>, Vec<[u8; 256]>) {
+    let mut entries: Vec<PaletteEntry> = Vec::new();
+    let mut positions = HashMap::new();
+    let mut maps: Vec<[usize; 256]> = Vec::with_capacity(charts.len());
+    for (chart, counts) in charts.iter().zip(counts) {
+        let mut map = [usize::MAX; 256];
+        for (index, &count) in counts.iter().enumerate().skip(1) {
+            let colors: Option<Vec<_>> = palettes
+                .iter()
+                .map(|&p| chart.header().palette(p)?.get(index - 1).copied())
+                .collect();
+            let Some(colors) = colors.filter(|_| count > 0) else {
+                continue;
+            };
+            let position = *positions.entry(colors.clone()).or_insert_with(|| {
+                entries.push(PaletteEntry { colors, count: 0 });
+                entries.len() - 1
+            });
+            entries[position].count += count;
+            map[index] = position;
+        }
+        maps.push(map);
+    }
+
+    // `merged[i]` is the entry replacing entry `i`
+    let mut merged: Vec<_> = (0..entries.len()).collect();
+    let mut remaining: Vec<_> = (0..entries.len()).collect();
+    while remaining.len() > MAX_COLORS {
+        let mut closest = (u32::MAX, 0, 0);
+        for (i, &a) in remaining.iter().enumerate() {
+            for &b in &remaining[i + 1..] {
+                let distance = entries[a].distance(&entries[b]);
+                if distance < closest.0 {
+                    closest = (distance, a, b);
+                }
+            }
+        }
+        let (_, a, b) = closest;
+        let (kept, replaced) = if entries[a].count >= entries[b].count {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        entries[kept].count += entries[replaced].count;
+        remaining.retain(|&i| i != replaced);
+        for target in &mut merged {
+            if *target == replaced {
+                *target = kept;
+            }
+        }
+    }
+
+    // the new indices, starting from 1
+    let mut indices = vec![0u8; entries.len()];
+    for (i, &entry) in remaining.iter().enumerate() {
+        indices[entry] = u8::try_from(i + 1).unwrap_or(u8::MAX);
+    }
+    let maps = maps
+        .into_iter()
+        .map(|map| {
+            map.map(|entry| {
+                if entry == usize::MAX {
+                    0
+                } else {
+                    indices[merged[entry]]
+                }
+            })
+        })
+        .collect();
+    let mut entries: Vec<_> = entries.into_iter().map(Some).collect();
+    let palette = remaining
+        .into_iter()
+        .filter_map(|i| entries[i].take())
+        .collect();
+    (palette, maps)
+}
+
+/// Replaces the palettes of `header` with the merged `palettes`, whose colours are `entries`
+fn write_palettes(header: &mut ImageHeader, palettes: &[ColorPalette], entries: &[PaletteEntry]) {
+    header.ifm = Depth::for_color_count(entries.len());
+    for p in ColorPalette::ALL {
+        *header.palette_mut(p) = None;
+    }
+    for (i, &p) in palettes.iter().enumerate() {
+        *header.palette_mut(p) = Some(entries.iter().map(|entry| entry.colors[i]).collect());
+    }
+}
+
+/// Returns the outline of the `covered` pixels of a `width * height` image, as the corners of
+/// pixels in clockwise order
+///
+/// # Errors
+///
+/// This function errors if no pixel is covered, or if the covered pixels do not form a single
+/// area without holes: their outline would then consist of several polygons.
+fn outline(covered: &[bool], (width, height): (u16, u16)) -> Result<Vec<(f64, f64)>, Error> {
+    let (width, height) = (usize::from(width), usize::from(height));
+    let is_covered = |x: usize, y: usize| x < width && y < height && covered[y * width + x];
+    // the directions of the edges leaving each pixel corner, as bits, the covered pixels being
+    // on the right of the edges
+    let corners = width + 1;
+    let mut edges = vec![0u8; corners * (height + 1)];
+    let mut remaining = 0usize;
+    let mut start = None;
+    for y in 0..height {
+        for x in (0..width).filter(|&x| is_covered(x, y)) {
+            for (direction, corner, outside) in [
+                (0, (x, y), y == 0 || !is_covered(x, y - 1)),
+                (1, (x + 1, y), !is_covered(x + 1, y)),
+                (2, (x + 1, y + 1), !is_covered(x, y + 1)),
+                (3, (x, y + 1), x == 0 || !is_covered(x - 1, y)),
+            ] {
+                if outside {
+                    edges[corner.1 * corners + corner.0] |= 1 << direction;
+                    remaining += 1;
+                }
+            }
+            start.get_or_insert((x, y));
+        }
+    }
+    let Some(start) = start else {
+        return Err(Error::DisjointCoverage);
+    };
+
+    // follows the edges from the top edge of the first covered pixel, turning away from the
+    // covered pixels where two of them only touch by a corner
+    let mut vertices = Vec::new();
+    let (mut corner, mut direction) = (start, 0);
+    loop {
+        let bits = &mut edges[corner.1 * corners + corner.0];
+        let Some(next) = [3, 0, 1]
+            .map(|turn| (direction + turn) % 4)
+            .into_iter()
+            .find(|&next| *bits & (1 << next) != 0)
+        else {
+            break;
+        };
+        *bits &= !(1 << next);
+        remaining -= 1;
+        if next != direction || vertices.is_empty() {
+            #[allow(clippy::cast_precision_loss)]
+            vertices.push((corner.0 as f64, corner.1 as f64));
+        }
+        direction = next;
+        let (dx, dy) = DIRECTIONS[direction];
+        corner = (
+            corner.0.wrapping_add_signed(dx),
+            corner.1.wrapping_add_signed(dy),
+        );
+    }
+    if remaining > 0 {
+        return Err(Error::DisjointCoverage);
+    }
+    Ok(simplify(&vertices))
+}
+
+/// Simplifies a closed polygon with the Douglas-Peucker algorithm, removing the vertices closer
+/// than [`OUTLINE_TOLERANCE`] to the simplified polygon
+fn simplify(vertices: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    /// Marks the vertices of `points[1..points.len() - 1]` to keep
+    fn keep_vertices(points: &[(f64, f64)], keep: &mut [bool]) {
+        let (Some(&first), Some(&last)) = (points.first(), points.last()) else {
+            return;
+        };
+        let (dx, dy) = (last.0 - first.0, last.1 - first.1);
+        let length = dx.hypot(dy);
+        let distance = |p: (f64, f64)| {
+            if length == 0.0 {
+                (p.0 - first.0).hypot(p.1 - first.1)
+            } else {
+                dx.mul_add(first.1 - p.1, -(dy * (first.0 - p.0))).abs() / length
+            }
+        };
+        let farthest = points[1..points.len().saturating_sub(1)]
+            .iter()
+            .enumerate()
+            .map(|(i, &p)| (i + 1, distance(p)))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((i, _)) = farthest.filter(|&(_, d)| d > OUTLINE_TOLERANCE) {
+            keep[i] = true;
+            keep_vertices(&points[..=i], &mut keep[..=i]);
+            keep_vertices(&points[i..], &mut keep[i..]);
+        }
+    }
+
+    if vertices.len() <= 4 {
+        return vertices.to_vec();
+    }
+    // splits the polygon at its first vertex and at the vertex farthest from it
+    let first = vertices[0];
+    let split = (1..vertices.len())
+        .max_by(|&a, &b| {
+            let distance = |i: usize| (vertices[i].0 - first.0).hypot(vertices[i].1 - first.1);
+            distance(a).total_cmp(&distance(b))
+        })
+        .unwrap_or_default();
+    let mut closed = vertices.to_vec();
+    closed.push(first);
+    let mut keep = vec![false; closed.len()];
+    (keep[0], keep[split]) = (true, true);
+    keep_vertices(&closed[..=split], &mut keep[..=split]);
+    keep_vertices(&closed[split..], &mut keep[split..]);
+    closed.pop();
+    closed
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(vertex, keep)| keep.then_some(vertex))
+        .collect()
+}
+
+/// A chart being mosaicked
+struct Source<'a> {
+    chart: &'a KapImageFile,
+    transform: GeoTransform,
+    datums: DatumConversion,
+}
+
+impl Source<'_> {
+    /// Returns the dimensions of the chart
+    fn dimensions(&self) -> (f64, f64) {
+        (
+            f64::from(self.chart.width()),
+            f64::from(self.chart.height()),
+        )
+    }
+
+    /// Returns the pixel `(x, y)` of the chart in the coordinates of the target projection
+    fn forward(&self, grid: &Grid, pixel: (f64, f64)) -> (f64, f64) {
+        grid.projection.forward(
+            self.datums
+                .chart_to_target(self.transform.pixel_to_coords(pixel)),
+        )
+    }
+
+    /// Samples the pixels of the chart inside its `PLY` border onto the pixels of `grid` not
+    /// sampled yet, tagging them with `tag`
+    ///
+    /// `sources` holds the tag and the index sampled by each pixel of `grid`, and `counts` the
+    /// number of pixels sampled from each index of the chart.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn sample(
+        &self,
+        grid: &Grid,
+        size: (u16, u16),
+        tag: u32,
+        sources: &mut [u32],
+        counts: &mut [usize; 256],
+    ) -> Result<(), Error> {
+        let (w, h) = self.dimensions();
+        let mask = self.chart.border_mask()?;
+        let indices = self.chart.pixel_indices();
+        let row = usize::from(self.chart.width());
+        sample_grid(
+            size,
+            |pixel| {
+                self.transform
+                    .coords_to_pixel(self.datums.target_to_chart(grid.to_coords(pixel)))
+            },
+            |i, (x, y)| {
+                if sources[i] != 0 || !(0.0..w).contains(&x) || !(0.0..h).contains(&y) {
+                    return;
+                }
+                let pixel = y as usize * row + x as usize;
+                if mask[pixel] {
+                    let index = indices[pixel];
+                    sources[i] = tag | u32::from(index);
+                    counts[usize::from(index)] += 1;
+                }
+            },
+        );
+        Ok(())
+    }
+}
+
+impl KapImageFile {
+    /// Mosaics `charts` into a single chart in `target`, e.g. to cover an approach spanning
+    /// several adjacent charts
+    ///
+    /// The charts are reprojected onto a common north-up grid covering all of them, with square
+    /// pixels as small as the smallest pixels at the centre of the charts (see
+    /// [`Self::reproject`]). Each chart is clipped to its `PLY` border, and earlier charts take
+    /// precedence where charts overlap. Pixels outside of all the charts are filled with the
+    /// most common index along the edges of the first chart.
+    ///
+    /// The palettes that all the charts have are merged into palettes of at most 127 colours:
+    /// identical colours are merged, then the closest pairs of colours if there are still too
+    /// many. The other palettes are dropped.
+    ///
+    /// The chart metadata of the first chart is kept, and the georeferencing is rewritten:
+    ///
+    /// - a 3x3 grid of `REF` records, in the target datum
+    /// - the `PLY` border, as the outline of the pixels covered by the charts, simplified to
+    ///   within a pixel
+    /// - quadratic `WPX`, `WPY`, `PWX` and `PWY` polynomials and the `ERR` records, fitted to
+    ///   the `REF` records
+    /// - the projection, datum, scale and pixel size of the `KNP` and `KNQ` records
+    ///
+    /// # Errors
+    ///
+    /// This function errors if `charts` is empty, if `target` has no projection, if the charts
+    /// have no palette in common, if a chart cannot be georeferenced (see
+    /// [`GeoTransform::from_header`]), if the mosaic would be larger than 65535 pixels in
+    /// either direction, or if the charts do not cover a single area without holes
+    /// ([`Error::DisjointCoverage`]), e.g. if some of them neither overlap nor touch the others.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn mosaic(charts: &[Self], target: &Crs) -> Result<Self, Error> {
+        let Some(first) = charts.first() else {
+            return Err(Error::Other("no chart to mosaic".to_owned()));
+        };
+        let projection = target
+            .projection
+            .ok_or(Error::MissingProjectionParameter("PR"))?;
+        let palettes: Vec<_> = ColorPalette::ALL
+            .into_iter()
+            .filter(|&p| charts.iter().all(|c| c.header().palette(p).is_some()))
+            .collect();
+        if palettes.is_empty() {
+            return Err(Error::NonExistentPalette);
+        }
+        let sources = charts
+            .iter()
+            .map(|chart| {
+                let transform = chart.geo_transform()?;
+                let datums = DatumConversion::new(chart.header(), &transform, target.datum);
+                Ok(Source {
+                    chart,
+                    transform,
+                    datums,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        // the extent of all the charts in projected coordinates, and the smallest pixel size
+        let mut grid = Grid {
+            projection,
+            origin: (0.0, 0.0),
+            size: f64::MAX,
+        };
+        let (mut min, mut max) = ((f64::MAX, f64::MAX), (f64::MIN, f64::MIN));
+        for source in &sources {
+            let forward = |pixel| source.forward(&grid, pixel);
+            let (source_min, source_max) = projected_extent(source.dimensions(), forward);
+            min = (min.0.min(source_min.0), min.1.min(source_min.1));
+            max = (max.0.max(source_max.0), max.1.max(source_max.1));
+            grid.size = grid
+                .size
+                .min(centre_pixel_size(source.dimensions(), forward));
+        }
+        grid.origin = (min.0, max.1);
+        let (width, height) = grid.image_size((min, max))?;
+
+        // the chart (starting from 1) and the index sampled by each pixel, 0 if none
+        let mut tags = vec![0u32; usize::from(width) * usize::from(height)];
+        let mut counts = vec![[0usize; 256]; charts.len()];
+        for (i, source) in sources.iter().enumerate() {
+            let tag = (i as u32 + 1) << 8;
+            source.sample(&grid, (width, height), tag, &mut tags, &mut counts[i])?;
+        }
+        let covered: Vec<_> = tags.iter().map(|&tag| tag != 0).collect();
+        let border = outline(&covered, (width, height))?;
+
+        let (palette, maps) = merge_palettes(charts, &palettes, &counts);
+        // the edge colour of the first chart may have been clipped out of the palette
+        let fill = match maps[0][usize::from(first.edge_index())] {
+            0 => 1,
+            index => index,
+        };
+        let raster = tags
+            .into_iter()
+            .map(|tag| match tag >> 8 {
+                0 => fill,
+                chart => maps[chart as usize - 1][(tag & 0xff) as usize],
+            })
+            .collect();
+
+        let mut header = first.header().clone();
+        header.general_parameters.image_width_height = (width, height);
+        write_palettes(&mut header, &palettes, &palette);
+        header.reference_point_record = Some(grid.reference_points((width, height)));
+        header.ply = Some(
+            border
+                .into_iter()
+                .map(|pixel| grid.to_coords(pixel))
+                .collect(),
+        );
+        if !sources[0].datums.identity {
+            header.dtm = None;
+        }
+        header.phase_shift = None;
+        header.err = None;
+
+        let scale = charts
+            .iter()
+            .filter_map(|c| c.header().detailed_parameters.as_ref()?.chart_scale)
+            .min();
+        let knp = header
+            .detailed_parameters
+            .get_or_insert_with(Default::default);
+        knp.chart_scale = scale.or(knp.chart_scale);
+        knp.geodetic_datum_name = Some(target.datum.to_string());
+        knp.skew_angle = Some(0.0);
+        knp.x_resolution = Some(grid.size as f32);
+        knp.y_resolution = Some(grid.size as f32);
+        header.set_projection(&projection);
+        header.fit_polynomials(PolynomialOrder::Quadratic)?;
+        header.update_err()?;
+        Self::new(header, raster)
+    }
+}
@@ -0,0 +1,165 @@
+use crate::{Error, KapImageFile};
+
+use super::Affine;
+
+/// A lossless change of orientation applied by [`KapImageFile::reorient`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reorientation {
+    /// Rotates the image by 90° clockwise
+    Rotate90,
+    /// Rotates the image by 180°
+    Rotate180,
+    /// Rotates the image by 270° clockwise (90° counterclockwise)
+    Rotate270,
+    /// Mirrors the image left to right
+    FlipHorizontal,
+    /// Mirrors the image top to bottom
+    FlipVertical,
+}
+
+impl Reorientation {
+    /// Returns the size of the reoriented image and the transformation from the pixels of a
+    /// `width * height` image into the pixels of the reoriented image
+    fn pixel_transform(self, (width, height): (u16, u16)) -> ((u16, u16), Affine) {
+        let (w, h) = (f64::from(width), f64::from(height));
+        match self {
+            Self::Rotate90 => ((height, width), Affine([h, 0.0, -1.0, 0.0, 1.0, 0.0])),
+            Self::Rotate180 => ((width, height), Affine([w, -1.0, 0.0, h, 0.0, -1.0])),
+            Self::Rotate270 => ((height, width), Affine([0.0, 0.0, 1.0, w, -1.0, 0.0])),
+            Self::FlipHorizontal => ((width, height), Affine([w, -1.0, 0.0, 0.0, 0.0, 1.0])),
+            Self::FlipVertical => ((width, height), Affine([0.0, 1.0, 0.0, h, 0.0, -1.0])),
+        }
+    }
+
+    /// Returns the skew angle of a reoriented image whose skew angle was `skew`
+    ///
+    /// Flips mirror the angle, so that the north still appears `skew` degrees counterclockwise
+    /// from the top of the image.
+    fn skew_angle(self, skew: f64) -> f64 {
+        let skew = match self {
+            Self::Rotate90 => skew - 90.0,
+            Self::Rotate180 => skew + 180.0,
+            Self::Rotate270 => skew + 90.0,
+            Self::FlipHorizontal => -skew,
+            Self::FlipVertical => 180.0 - skew,
+        };
+        skew.rem_euclid(360.0)
+    }
+}
+
+impl KapImageFile {
+    /// Rotates or flips the image
+    ///
+    /// No pixel is resampled, and the georeferencing of the header follows the pixels: the
+    /// `REF` records and the polynomials are moved (see [`Self::crop`]), the skew angle (`SK`) is
+    /// updated, and the pixel sizes (`DX`/`DY`) are swapped by quarter turns. The `PLY` border
+    /// is given in geographical coordinates, so it is unchanged.
+    ///
+    /// # Errors
+    ///
+    /// This function errors if the `ERR` records of the header cannot be recomputed.
+    pub fn reorient(&self, reorientation: Reorientation) -> Result<Self, Error> {
+        let (size, to_new) = reorientation.pixel_transform((self.width(), self.height()));
+        let raster = self.transform_pixels(size, to_new)?;
+
+        let mut header = self.header().clone();
+        header.remap_pixels(to_new, size)?;
+        if let Some(knp) = header.detailed_parameters.as_mut() {
+            let skew = knp.skew_angle.map_or(0.0, f64::from);
+            #[allow(clippy::cast_possible_truncation)]
+            let skew = reorientation.skew_angle(skew) as f32;
+            knp.skew_angle = Some(skew);
+            if size != (self.width(), self.height()) {
+                (knp.x_resolution, knp.y_resolution) = (knp.y_resolution, knp.x_resolution);
+            }
+        }
+        Self::new(header, raster)
+    }
+
+    /// Rotates the image so that the north points up, using the skew angle (`SK`) of the header
+    ///
+    /// Skew angles that are multiples of 90° are handled losslessly by [`Self::reorient`].
+    /// Otherwise the rotated image covers the whole original image, and its pixels are
+    /// resampled using the nearest neighbour: pixels falling outside of the original image are
+    /// filled with the most common index along its edges (usually the colour of the collar).
+    /// The `REF` records and the polynomials are moved (see [`Self::crop`]), the skew angle is
+    /// set to 0, and the corners of the original image are used as the `PLY` border if the
+    /// header had none, masking out the filled pixels.
+    ///
+    /// # Errors
+    ///
+    /// This function errors if the rotated image would be larger than 65535 pixels in either
+    /// direction, or if the original image cannot be georeferenced when a `PLY` border must be
+    /// added.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn north_up(&self) -> Result<Self, Error> {
+        let skew = self
+            .header()
+            .detailed_parameters
+            .as_ref()
+            .and_then(|knp| knp.skew_angle)
+            .map_or(0.0, f64::from)
+            .rem_euclid(360.0);
+        let quarter_turns = (skew / 90.0).round();
+        if (skew - quarter_turns * 90.0).abs() < 1e-6 {
+            let reorientation = match quarter_turns as u8 {
+                1 => Reorientation::Rotate90,
+                2 => Reorientation::Rotate180,
+                3 => Reorientation::Rotate270,
+                _ => {
+                    let mut header = self.header().clone();
+                    if let Some(knp) = header.detailed_parameters.as_mut() {
+                        knp.skew_angle = Some(0.0);
+                    }
+                    return Self::new(header, self.pixel_indices().to_vec());
+                }
+            };
+            return self.reorient(reorientation);
+        }
+
+        // rotates the image clockwise by the skew angle
+        let (sin, cos) = skew.to_radians().sin_cos();
+        let rotate = |(x, y): (f64, f64)| (x.mul_add(cos, -y * sin), x.mul_add(sin, y * cos));
+        let (width, height) = (f64::from(self.width()), f64::from(self.height()));
+        let (mut min, mut max) = ((f64::MAX, f64::MAX), (f64::MIN, f64::MIN));
+        for corner in [(0.0, 0.0), (width, 0.0), (0.0, height), (width, height)] {
+            let (x, y) = rotate(corner);
+            min = (min.0.min(x), min.1.min(y));
+            max = (max.0.max(x), max.1.max(y));
+        }
+        // ignores rounding errors when computing the size of the rotated image
+        let extent = |min: f64, max: f64| ((max - min) - 1e-6).ceil().max(1.0) as usize;
+        let (new_width, new_height) = (extent(min.0, max.0), extent(min.1, max.1));
+        let (Ok(new_width), Ok(new_height)) = (u16::try_from(new_width), u16::try_from(new_height))
+        else {
+            return Err(Error::ImageTooLarge(new_width, new_height));
+        };
+        let to_new = Affine([-min.0, cos, -sin, -min.1, sin, cos]);
+        let size = (new_width, new_height);
+        let raster = self.transform_pixels(size, to_new)?;
+
+        let mut header = self.header().clone();
+        if header.ply.as_ref().is_none_or(Vec::is_empty) {
+            let transform = self.geo_transform()?;
+            header.ply = Some(
+                [(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)]
+                    .map(|pixel| transform.pixel_to_coords(pixel))
+                    .to_vec(),
+            );
+        }
+        header.remap_pixels(to_new, size)?;
+        if let Some(knp) = header.detailed_parameters.as_mut() {
+            knp.skew_angle = Some(0.0);
+        }
+        Self::new(header, raster)
+    }
+
+    /// Returns the pixels of an image of `size` pixels, whose pixels are mapped from the
+    /// original image by `to_new`
+    fn transform_pixels(&self, size: (u16, u16), to_new: Affine) -> Result<Vec<u8>, Error> {
+        let to_original = to_new
+            .inverse()
+            .ok_or_else(|| Error::Other("singular pixel transformation".to_owned()))?;
+        Ok(self.resample(size, |pixel| to_original.apply(pixel)))
+    }
+}
@@ -0,0 +1,80 @@
+use crate::{
+    geodesy::DatumShift,
+    image::header::{ImageHeader, Ref},
+    projection::Projection,
+    Error,
+};
+
+use super::{fit::fit_polynomial, GeoTransform, Model, PolynomialOrder};
+
+impl GeoTransform {
+    /// Creates a [`GeoTransform`] from a [`Projection`] and a set of reference points
+    ///
+    /// The reference points are projected, and an affine transformation between projected
+    /// coordinates and pixels is fitted using least squares. Unlike polynomials fitted directly
+    /// to geographical coordinates, this stays accurate over large charts.
+    ///
+    /// # Errors
+    ///
+    /// This function errors if there are fewer than 3 reference points, or if the reference
+    /// points are degenerate (e.g. all on a single line).
+    pub fn from_projection(projection: Projection, refs: &[Ref]) -> Result<Self, Error> {
+        let order = PolynomialOrder::Linear;
+        if refs.len() < order.terms() {
+            return Err(Error::InsufficientReferencePoints {
+                required: order.terms(),
+                found: refs.len(),
+            });
+        }
+        let projected: Vec<_> = refs.iter().map(|r| projection.forward(r.coords)).collect();
+        #[allow(clippy::cast_precision_loss)]
+        let pixels: Vec<_> = refs
+            .iter()
+            .map(|r| (r.pixels.0 as f64, r.pixels.1 as f64))
+            .collect();
+        let component = |values: &[(f64, f64)], f: fn(&(f64, f64)) -> f64| -> Vec<f64> {
+            values.iter().map(f).collect()
+        };
+        Ok(Self {
+            model: Model::Projected {
+                projection,
+                to_pixel: [
+                    fit_polynomial(&projected, &component(&pixels, |p| p.0), order)?,
+                    fit_polynomial(&projected, &component(&pixels, |p| p.1), order)?,
+                ],
+                to_projected: [
+                    fit_polynomial(&pixels, &component(&projected, |p| p.0), order)?,
+                    fit_polynomial(&pixels, &component(&projected, |p| p.1), order)?,
+                ],
+            },
+            phase_shift: 0.0,
+            datum_shift: DatumShift::default(),
+        })
+    }
+
+    /// Creates a [`GeoTransform`] from the projection and the `REF` records of a header
+    /// lacking polynomials
+    pub(super) fn from_projection_header(
+        header: &ImageHeader,
+        projection: Projection,
+    ) -> Result<Self, Error> {
+        let refs = header.reference_point_record.as_deref().unwrap_or_default();
+        if refs.is_empty() {
+            return Err(Error::MissingGeoreference);
+        }
+        Ok(Self {
+            datum_shift: header.datum_shift(),
+            ..Self::from_projection(projection, refs)?
+        })
+    }
+
+    /// Returns the [`Projection`] used by this transform, if the chart is georeferenced
+    /// through its projection rather than through polynomials
+    #[must_use]
+    pub const fn projection(&self) -> Option<&Projection> {
+        match &self.model {
+            Model::Projected { projection, .. } => Some(projection),
+            Model::Polynomials { .. } => None,
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use crate::{
+    image::header::{ImageHeader, Polynomial, Ref},
+    Error,
+};
+
+use super::Affine;
+
+/// Returns the first order [`Polynomial`] of one of the outputs of an [`Affine`]
+const fn linear([c0, c1, c2]: [f64; 3]) -> Polynomial {
+    Polynomial::new(1, [c0, c1, c2, 0.0, 0.0, 0.0])
+}
+
+impl ImageHeader {
+    /// Rewrites the georeferencing of the header for an image whose pixels are moved by
+    /// `to_new`, an affine transformation from the pixels of the original image into the pixels
+    /// of a new `width * height` image
+    ///
+    /// - the `REF` records are moved, dropping those falling outside of the new image. When a
+    ///   moved pixel has to be rounded, its coordinates are recomputed from the original
+    ///   georeferencing. If fewer than 3 records remain, the corners of the new image are added.
+    /// - the `WPX`, `WPY`, `PWX` and `PWY` polynomials are composed with the transformation
+    /// - the `ERR` records are recomputed
+    ///
+    /// The `PLY` border and the `KNP` record are left untouched.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    pub(crate) fn remap_pixels(
+        &mut self,
+        to_new: Affine,
+        (width, height): (u16, u16),
+    ) -> Result<(), Error> {
+        let to_original = to_new
+            .inverse()
+            .ok_or_else(|| Error::Other("singular pixel transformation".to_owned()))?;
+        let transform = self.geo_transform().ok();
+        let original_coords = |pixel: (f64, f64), coords: (f64, f64)| {
+            transform
+                .as_ref()
+                .map_or(coords, |t| t.pixel_to_coords(to_original.apply(pixel)))
+        };
+        self.general_parameters.image_width_height = (width, height);
+
+        let (w, h) = (f64::from(width), f64::from(height));
+        let mut refs: Vec<_> = self
+            .reference_point_record
+            .take()
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|r| {
+                let (x, y) = to_new.apply((r.pixels.0 as f64, r.pixels.1 as f64));
+                let pixel = (x.round(), y.round());
+                if !(0.0..=w).contains(&pixel.0) || !(0.0..=h).contains(&pixel.1) {
+                    return None;
+                }
+                let exact = (pixel.0 - x).abs() < 1e-9 && (pixel.1 - y).abs() < 1e-9;
+                let coords = if exact {
+                    r.coords
+                } else {
+                    original_coords(pixel, r.coords)
+                };
+                Some(
+                    Ref::builder()
+                        .pixels((pixel.0 as usize, pixel.1 as usize))
+                        .coords(coords)
+                        .build(),
+                )
+            })
+            .collect();
+        if refs.len() < 3 {
+            if let Some(transform) = &transform {
+                refs.extend(
+                    [(0, 0), (width, 0), (width, height), (0, height)].map(|(x, y)| {
+                        let pixel = (f64::from(x), f64::from(y));
+                        Ref::builder()
+                            .pixels((usize::from(x), usize::from(y)))
+                            .coords(transform.pixel_to_coords(to_original.apply(pixel)))
+                            .build()
+                    }),
+                );
+            }
+        }
+        self.reference_point_record = (!refs.is_empty()).then_some(refs);
+
+        let [c0, c1, c2, c3, c4, c5] = to_original.0;
+        let (x, y) = (linear([c0, c1, c2]), linear([c3, c4, c5]));
+        if let (Some(pwx), Some(pwy)) = (&self.pwx, &self.pwy) {
+            (self.pwx, self.pwy) = (Some(pwx.compose(&x, &y)), Some(pwy.compose(&x, &y)));
+        }
+        if let (Some(wpx), Some(wpy)) = (&self.wpx, &self.wpy) {
+            let [c0, c1, c2, c3, c4, c5] = to_new.0;
+            let combine = |constant: f64, a: f64, b: f64| {
+                let mut poly = std::array::from_fn(|i| a.mul_add(wpx.poly[i], b * wpy.poly[i]));
+                poly[0] += constant;
+                Polynomial::new(wpx.corner.max(wpy.corner), poly)
+            };
+            (self.wpx, self.wpy) = (Some(combine(c0, c1, c2)), Some(combine(c3, c4, c5)));
+        }
+        if self.err.is_some() {
+            self.update_err()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+use crate::{
+    crs::Crs,
+    geodesy::{Datum, DatumShift},
+    image::header::{ImageHeader, Ref},
+    projection::Projection,
+    Error, KapImageFile,
+};
+
+use super::{GeoTransform, PolynomialOrder};
+
+/// The number of intervals sampled along each edge of the original image to compute the extent
+/// of the reprojected image
+const EDGE_SAMPLES: u16 = 64;
+
+/// Converts coordinates between the datum of a chart and the datum of a target [`Crs`]
+pub(super) struct DatumConversion {
+    shift: DatumShift,
+    target: Datum,
+    /// Whether the chart already uses the target datum
+    pub(super) identity: bool,
+}
+
+impl DatumConversion {
+    pub(super) fn new(header: &ImageHeader, transform: &GeoTransform, target: Datum) -> Self {
+        Self {
+            shift: transform.datum_shift(),
+            target,
+            identity: header.datum().is_ok_and(|datum| datum == target),
+        }
+    }
+
+    pub(super) fn chart_to_target(&self, coords: (f64, f64)) -> (f64, f64) {
+        if self.identity {
+            return coords;
+        }
+        self.target
+            .transform_from_wgs84(self.shift.to_wgs84(coords))
+    }
+
+    pub(super) fn target_to_chart(&self, coords: (f64, f64)) -> (f64, f64) {
+        if self.identity {
+            return coords;
+        }
+        self.shift
+            .from_wgs84(self.target.transform_to_wgs84(coords))
+    }
+}
+
+/// A north-up grid of square pixels in projected coordinates
+pub(super) struct Grid {
+    pub(super) projection: Projection,
+    /// Easting and northing of the top-left corner of the image
+    pub(super) origin: (f64, f64),
+    /// Size of the pixels in metres
+    pub(super) size: f64,
+}
+
+impl Grid {
+    pub(super) fn to_coords(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        self.projection.inverse((
+            x.mul_add(self.size, self.origin.0),
+            y.mul_add(-self.size, self.origin.1),
+        ))
+    }
+
+    /// Returns the size in pixels of the grid covering the projected `extent`
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub(super) fn image_size(
+        &self,
+        (min, max): ((f64, f64), (f64, f64)),
+    ) -> Result<(u16, u16), Error> {
+        // ignores rounding errors when computing the size of the grid
+        let (width, height) = (
+            ((max.0 - min.0) / self.size - 1e-6).ceil() as usize,
+            ((max.1 - min.1) / self.size - 1e-6).ceil() as usize,
+        );
+        match (u16::try_from(width), u16::try_from(height)) {
+            (Ok(width), Ok(height)) => Ok((width, height)),
+            _ => Err(Error::ImageTooLarge(width, height)),
+        }
+    }
+
+    /// Returns a 3x3 grid of `REF` records covering an image of `width * height` pixels
+    #[allow(clippy::cast_precision_loss)]
+    pub(super) fn reference_points(&self, (width, height): (u16, u16)) -> Vec<Ref> {
+        let (max_x, max_y) = (
+            usize::from(width.saturating_sub(1)),
+            usize::from(height.saturating_sub(1)),
+        );
+        [0, max_y / 2, max_y]
+            .into_iter()
+            .flat_map(|y| [0, max_x / 2, max_x].map(|x| (x, y)))
+            .map(|pixels| {
+                let coords = self.to_coords((pixels.0 as f64, pixels.1 as f64));
+                Ref::builder().pixels(pixels).coords(coords).build()
+            })
+            .collect()
+    }
+}
+
+/// Returns the extent in projected coordinates of a `width * height` image, whose pixels are
+/// projected by `forward`
+pub(super) fn projected_extent(
+    (width, height): (f64, f64),
+    forward: impl Fn((f64, f64)) -> (f64, f64),
+) -> ((f64, f64), (f64, f64)) {
+    let (mut min, mut max) = ((f64::MAX, f64::MAX), (f64::MIN, f64::MIN));
+    for i in 0..=EDGE_SAMPLES {
+        let t = f64::from(i) / f64::from(EDGE_SAMPLES);
+        for pixel in [
+            (t * width, 0.0),
+            (t * width, height),
+            (0.0, t * height),
+            (width, t * height),
+        ] {
+            let (easting, northing) = forward(pixel);
+            min = (min.0.min(easting), min.1.min(northing));
+            max = (max.0.max(easting), max.1.max(northing));
+        }
+    }
+    (min, max)
+}
+
+/// Returns the side of a square with the area of the pixel at the centre of a `width * height`
+/// image, whose pixels are projected by `forward`
+pub(super) fn centre_pixel_size(
+    (width, height): (f64, f64),
+    forward: impl Fn((f64, f64)) -> (f64, f64),
+) -> f64 {
+    let centre = forward((width / 2.0, height / 2.0));
+    let right = forward((width / 2.0 + 1.0, height / 2.0));
+    let down = forward((width / 2.0, height / 2.0 + 1.0));
+    (right.0 - centre.0)
+        .mul_add(
+            down.1 - centre.1,
+            -(right.1 - centre.1) * (down.0 - centre.0),
+        )
+        .abs()
+        .sqrt()
+}
+
+impl KapImageFile {
+    /// Reprojects the image into `target`, e.g. to normalize Transverse Mercator or Polyconic
+    /// charts into Mercator charts
+    ///
+    /// The reprojected image is north-up in the target projection and covers the whole
+    /// original image, with square pixels of the same area as the pixels at the centre of the
+    /// original image. Since the pixels are palette indices, they are resampled using the
+    /// nearest neighbour. Pixels falling outside of the original image are filled with the most
+    /// common index along its edges (usually the colour of the collar).
+    ///
+    /// The palettes and the chart metadata are kept, and the georeferencing is rewritten:
+    ///
+    /// - a 3x3 grid of `REF` records and the `PLY` border (the corners of the original image if
+    ///   it had none) in the target datum
+    /// - the projection, datum and pixel size of the `KNP` and `KNQ` records
+    /// - the `WPX`, `WPY`, `PWX` and `PWY` polynomials and the `ERR` records, refitted to the new
+    ///   `REF` records if the original header had polynomials, and removed otherwise
+    ///
+    /// The `DTM` record is removed if the datum changes.
+    ///
+    /// # Errors
+    ///
+    /// This function errors if `target` has no projection, if the image cannot be georeferenced
+    /// (see [`GeoTransform::from_header`]), or if the reprojected image would be larger than
+    /// 65535 pixels in either direction.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn reproject(&self, target: &Crs) -> Result<Self, Error> {
+        let projection = target
+            .projection
+            .ok_or(Error::MissingProjectionParameter("PR"))?;
+        let transform = self.geo_transform()?;
+        let datums = DatumConversion::new(self.header(), &transform, target.datum);
+        let forward =
+            |pixel| projection.forward(datums.chart_to_target(transform.pixel_to_coords(pixel)));
+        let (width, height) = (f64::from(self.width()), f64::from(self.height()));
+
+        // the extent of the original image in projected coordinates
+        let (min, max) = projected_extent((width, height), forward);
+        let grid = Grid {
+            projection,
+            origin: (min.0, max.1),
+            size: centre_pixel_size((width, height), forward),
+        };
+        let (new_width, new_height) = grid.image_size((min, max))?;
+        let raster = self.resample((new_width, new_height), |pixel| {
+            transform.coords_to_pixel(datums.target_to_chart(grid.to_coords(pixel)))
+        });
+
+        let mut header = self.header().clone();
+        header.general_parameters.image_width_height = (new_width, new_height);
+        header.reference_point_record = Some(grid.reference_points((new_width, new_height)));
+        header.ply = Some(match self.header().ply.as_deref() {
+            Some(ply) if !ply.is_empty() => {
+                ply.iter().map(|&c| datums.chart_to_target(c)).collect()
+            }
+            _ => [(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)]
+                .map(|pixel| datums.chart_to_target(transform.pixel_to_coords(pixel)))
+                .to_vec(),
+        });
+        if !datums.identity {
+            header.dtm = None;
+        }
+        header.phase_shift = None;
+        header.err = None;
+        let refit = header.wpx.is_some();
+        (header.wpx, header.wpy, header.pwx, header.pwy) = (None, None, None, None);
+
+        let knp = header
+            .detailed_parameters
+            .get_or_insert_with(Default::default);
+        knp.geodetic_datum_name = Some(target.datum.to_string());
+        knp.skew_angle = Some(0.0);
+        knp.x_resolution = Some(grid.size as f32);
+        knp.y_resolution = Some(grid.size as f32);
+        header.set_projection(&projection);
+        if refit {
+            header.fit_polynomials(PolynomialOrder::Quadratic)?;
+            header.update_err()?;
+        }
+        Self::new(header, raster)
+    }
+}
@@ -0,0 +1,105 @@
+use crate::KapImageFile;
+
+/// The spacing in pixels of the grid on which the resampled pixels are mapped exactly onto the
+/// original image; the mapping is interpolated bilinearly in between
+const GRID_STEP: u16 = 16;
+
+/// Interpolates linearly between two points
+fn lerp(a: (f64, f64), b: (f64, f64), t: f64) -> (f64, f64) {
+    ((b.0 - a.0).mul_add(t, a.0), (b.1 - a.1).mul_add(t, a.1))
+}
+
+/// Calls `sample` with the index and the position in the original image of the centre of each
+/// pixel of a `width * height` image, `source_pixel` mapping a position in this image onto the
+/// original image
+///
+/// `source_pixel` is evaluated on a coarse grid and interpolated bilinearly in between, which
+/// is much faster than evaluating it for every pixel when it involves datum shifts, projections
+/// or polynomial inverses. It is evaluated exactly where the grid is not finite.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+pub fn sample_grid(
+    (width, height): (u16, u16),
+    source_pixel: impl Fn((f64, f64)) -> (f64, f64),
+    mut sample: impl FnMut(usize, (f64, f64)),
+) {
+    let columns = usize::from(width.div_ceil(GRID_STEP)) + 1;
+    let rows = usize::from(height.div_ceil(GRID_STEP)) + 1;
+    let step = f64::from(GRID_STEP);
+    let nodes: Vec<_> = (0..rows)
+        .flat_map(|j| (0..columns).map(move |i| (i as f64 * step, j as f64 * step)))
+        .map(&source_pixel)
+        .collect();
+
+    for y in 0..height {
+        let v = (f64::from(y) + 0.5) / step;
+        let (j, ty) = (v as usize, v.fract());
+        for x in 0..width {
+            let u = (f64::from(x) + 0.5) / step;
+            let (i, tx) = (u as usize, u.fract());
+            let corners = [
+                nodes[j * columns + i],
+                nodes[j * columns + i + 1],
+                nodes[(j + 1) * columns + i],
+                nodes[(j + 1) * columns + i + 1],
+            ];
+            let source = if corners.iter().all(|c| c.0.is_finite() && c.1.is_finite()) {
+                lerp(
+                    lerp(corners[0], corners[1], tx),
+                    lerp(corners[2], corners[3], tx),
+                    ty,
+                )
+            } else {
+                source_pixel((f64::from(x) + 0.5, f64::from(y) + 0.5))
+            };
+            sample(usize::from(y) * usize::from(width) + usize::from(x), source);
+        }
+    }
+}
+
+impl KapImageFile {
+    /// Resamples the image into an image of `size` pixels using the nearest neighbour,
+    /// `source_pixel` mapping a position in the resampled image onto the original image
+    /// (see [`sample_grid`])
+    ///
+    /// Pixels falling outside of the original image are filled with [`Self::edge_index`].
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub(super) fn resample(
+        &self,
+        (width, height): (u16, u16),
+        source_pixel: impl Fn((f64, f64)) -> (f64, f64),
+    ) -> Vec<u8> {
+        let indices = self.pixel_indices();
+        let (source_width, source_height) = (self.width(), self.height());
+        let mut raster = vec![self.edge_index(); usize::from(width) * usize::from(height)];
+        sample_grid((width, height), source_pixel, |i, (sx, sy)| {
+            if (0.0..f64::from(source_width)).contains(&sx)
+                && (0.0..f64::from(source_height)).contains(&sy)
+            {
+                raster[i] = indices[sy as usize * usize::from(source_width) + sx as usize];
+            }
+        });
+        raster
+    }
+
+    /// Returns the most common pixel index along the edges of the image
+    pub(super) fn edge_index(&self) -> u8 {
+        let (width, height) = (usize::from(self.width()), usize::from(self.height()));
+        let indices = self.pixel_indices();
+        if indices.is_empty() {
+            return 0;
+        }
+        let mut counts = [0usize; 256];
+        let rows = (0..width).flat_map(|x| [x, (height - 1) * width + x]);
+        let columns = (0..height).flat_map(|y| [y * width, y * width + width - 1]);
+        for i in rows.chain(columns) {
+            counts[usize::from(indices[i])] += 1;
+        }
+        (0..=u8::MAX)
+            .max_by_key(|&index| counts[usize::from(index)])
+            .unwrap_or_default()
+    }
+}
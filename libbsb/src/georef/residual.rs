@@ -0,0 +1,159 @@
+use crate::{
+    image::header::{ImageHeader, Ref},
+    Error,
+};
+
+use super::{normalize_longitude, GeoTransform};
+
+/// Residuals of a single reference point
+///
+/// Residuals are signed differences between the value computed by the polynomials and the
+/// value stored in the `REF` record. The `ERR` records hold their absolute values, in the
+/// order `x, y, latitude, longitude` (see [`Residual::as_err_record`]).
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Residual {
+    /// `WPX(lon, lat) - x`, in pixels
+    pub x: f64,
+    /// `WPY(lon, lat) - y`, in pixels
+    pub y: f64,
+    /// `PWY(x, y) - lat`, in degrees
+    pub lat: f64,
+    /// `PWX(x, y) - lon`, in degrees
+    pub lon: f64,
+    /// The inverse (`PWX`/`PWY`) residual converted into pixels
+    pub inverse_pixels: f64,
+}
+
+impl Residual {
+    /// Returns the magnitude of the forward (`WPX`/`WPY`) residual, in pixels
+    #[must_use]
+    pub fn forward_pixels(&self) -> f64 {
+        self.x.hypot(self.y)
+    }
+
+    /// Returns the residual in the format of an `ERR` record
+    #[must_use]
+    pub const fn as_err_record(&self) -> [f64; 4] {
+        [self.x.abs(), self.y.abs(), self.lat.abs(), self.lon.abs()]
+    }
+}
+
+/// Report of the residuals of all reference points of a chart
+///
+/// Created with [`ImageHeader::residual_report`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResidualReport {
+    /// The residuals of each reference point, in the order of the `REF` records
+    pub residuals: Vec<Residual>,
+    /// Indices (into the `REF` records) of the reference points whose forward or inverse
+    /// residual exceeds the tolerance
+    pub flagged: Vec<usize>,
+    /// The tolerance used to flag reference points, in pixels
+    pub tolerance: f64,
+}
+
+impl ResidualReport {
+    /// Returns `true` if no reference point exceeds the tolerance
+    #[must_use]
+    pub const fn is_ok(&self) -> bool {
+        self.flagged.is_empty()
+    }
+
+    /// Returns the largest forward or inverse residual, in pixels
+    #[must_use]
+    pub fn max_pixels(&self) -> f64 {
+        self.residuals
+            .iter()
+            .map(|r| r.forward_pixels().max(r.inverse_pixels))
+            .fold(0.0, f64::max)
+    }
+
+    /// Returns the root mean square of the forward residuals, in pixels
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn rms_pixels(&self) -> f64 {
+        if self.residuals.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self
+            .residuals
+            .iter()
+            .map(|r| r.forward_pixels().powi(2))
+            .sum();
+        (sum / self.residuals.len() as f64).sqrt()
+    }
+}
+
+impl GeoTransform {
+    /// Computes the forward and inverse residuals of each reference point
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn residuals(&self, refs: &[Ref]) -> Vec<Residual> {
+        refs.iter()
+            .map(|r| {
+                let pixel = (r.pixels.0 as f64, r.pixels.1 as f64);
+                let (x, y) = self.coords_to_pixel(r.coords);
+                let (lat, lon) = self.pixel_to_coords(pixel);
+                // express the inverse residual in pixels using the forward polynomials
+                let predicted = self.coords_to_pixel((lat, lon));
+                Residual {
+                    x: x - pixel.0,
+                    y: y - pixel.1,
+                    lat: lat - r.coords.0,
+                    lon: normalize_longitude(lon - r.coords.1),
+                    inverse_pixels: (predicted.0 - x).hypot(predicted.1 - y),
+                }
+            })
+            .collect()
+    }
+}
+
+impl ImageHeader {
+    /// Computes the residuals of the reference points found in
+    /// [`ImageHeader::reference_point_record`]
+    ///
+    /// # Errors
+    ///
+    /// This function errors if the header cannot be georeferenced (see
+    /// [`GeoTransform::from_header`]).
+    pub fn residuals(&self) -> Result<Vec<Residual>, Error> {
+        let transform = self.geo_transform()?;
+        Ok(transform.residuals(self.reference_point_record.as_deref().unwrap_or_default()))
+    }
+
+    /// Recomputes the `ERR` records from the reference points and the polynomials
+    ///
+    /// # Errors
+    ///
+    /// See [`Self::residuals`]
+    pub fn update_err(&mut self) -> Result<(), Error> {
+        let errs: Vec<_> = self
+            .residuals()?
+            .iter()
+            .map(Residual::as_err_record)
+            .collect();
+        self.err = (!errs.is_empty()).then_some(errs);
+        Ok(())
+    }
+
+    /// Creates a [`ResidualReport`], flagging reference points with a forward or inverse
+    /// residual larger than `tolerance` pixels
+    ///
+    /// # Errors
+    ///
+    /// See [`Self::residuals`]
+    pub fn residual_report(&self, tolerance: f64) -> Result<ResidualReport, Error> {
+        let residuals = self.residuals()?;
+        let flagged = residuals
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| r.forward_pixels() > tolerance || r.inverse_pixels > tolerance)
+            .map(|(i, _)| i)
+            .collect();
+        Ok(ResidualReport {
+            residuals,
+            flagged,
+            tolerance,
+        })
+    }
+}
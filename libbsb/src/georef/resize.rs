@@ -0,0 +1,152 @@
+use crate::{Error, KapImageFile};
+
+use super::Affine;
+
+/// The maximum weight of the indices that are rare over the whole image (see
+/// [`ResizeFilter::Mode`])
+const MAX_RARITY_BOOST: f64 = 8.0;
+
+/// The filter used by [`KapImageFile::resize`]
+///
+/// Pixels are palette indices, so they cannot be averaged: both filters pick one of the
+/// original indices.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ResizeFilter {
+    /// The index of the original pixel under the centre of the resized pixel
+    Nearest,
+    /// The most common index among the original pixels covered by the resized pixel
+    ///
+    /// Indices covering at least a line across the block of original pixels (as many pixels as
+    /// its shorter side, and at least 2) are weighted by their rarity over the whole image, up
+    /// to 8 times. This keeps thin lines, soundings and text (drawn in colours covering little
+    /// of the chart) visible when downsampling, where [`Self::Nearest`] drops them at random,
+    /// while isolated pixels such as scan noise are dropped. When upsampling, this is the same
+    /// as [`Self::Nearest`].
+    #[default]
+    Mode,
+}
+
+impl KapImageFile {
+    /// Resizes the image to `width * height` pixels using `filter`
+    ///
+    /// The georeferencing of the header is scaled with the image: the `REF` records and the
+    /// polynomials are moved (see [`Self::crop`]), and the drawing units (`DU`) and pixel size
+    /// (`DX`/`DY`) are updated so that the scale of the chart is unchanged.
+    ///
+    /// # Errors
+    ///
+    /// This function errors if the original or the resized image is empty.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    pub fn resize(&self, (width, height): (u16, u16), filter: ResizeFilter) -> Result<Self, Error> {
+        if width == 0 || height == 0 || self.width() == 0 || self.height() == 0 {
+            return Err(Error::InvalidWindow {
+                origin: (0, 0),
+                size: (width, height),
+                image: (self.width(), self.height()),
+            });
+        }
+        // the size of a resized pixel in original pixels
+        let scale = (
+            f64::from(self.width()) / f64::from(width),
+            f64::from(self.height()) / f64::from(height),
+        );
+        let raster = match filter {
+            ResizeFilter::Mode if scale.0 > 1.0 || scale.1 > 1.0 => {
+                self.mode_of_blocks((width, height), scale)
+            }
+            _ => self.resample((width, height), |(x, y)| (x * scale.0, y * scale.1)),
+        };
+
+        let mut header = self.header().clone();
+        header.remap_pixels(
+            Affine([0.0, 1.0 / scale.0, 0.0, 0.0, 0.0, 1.0 / scale.1]),
+            (width, height),
+        )?;
+        let factor = (scale.0 * scale.1).sqrt();
+        if let Some(du) = header.general_parameters.drawing_units.as_mut() {
+            *du = ((*du as f64 / factor).round() as usize).max(1);
+        }
+        if let Some(knp) = header.detailed_parameters.as_mut() {
+            if let Some(dx) = knp.x_resolution.as_mut() {
+                *dx = (f64::from(*dx) * scale.0) as f32;
+            }
+            if let Some(dy) = knp.y_resolution.as_mut() {
+                *dy = (f64::from(*dy) * scale.1) as f32;
+            }
+        }
+        Self::new(header, raster)
+    }
+
+    /// Downsamples the image, each resized pixel taking the most common index of the block of
+    /// original pixels it covers (see [`ResizeFilter::Mode`])
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    fn mode_of_blocks(&self, (width, height): (u16, u16), scale: (f64, f64)) -> Vec<u8> {
+        let indices = self.pixel_indices();
+        let (source_width, source_height) = (usize::from(self.width()), usize::from(self.height()));
+        let mut totals = [0usize; 256];
+        for &index in indices {
+            totals[usize::from(index)] += 1;
+        }
+        // the rarity of each index, relative to the most common index of the image
+        let most_common = totals.iter().copied().max().unwrap_or_default() as f64;
+        let weights = totals.map(|total| {
+            if total == 0 {
+                1.0
+            } else {
+                (most_common / total as f64).clamp(1.0, MAX_RARITY_BOOST)
+            }
+        });
+
+        // the range of original pixels covered by each resized column or row
+        let block = |i: u16, scale: f64, size: usize| {
+            let start = ((f64::from(i) * scale) as usize).min(size - 1);
+            let end = ((f64::from(i + 1) * scale).ceil() as usize).clamp(start + 1, size);
+            start..end
+        };
+        let mut raster = Vec::with_capacity(usize::from(width) * usize::from(height));
+        // the counts of the current block
+        let mut counts = [0usize; 256];
+        for y in 0..height {
+            let rows = block(y, scale.1, source_height);
+            for x in 0..width {
+                let columns = block(x, scale.0, source_width);
+                let block = || {
+                    rows.clone().flat_map(|row| {
+                        &indices
+                            [row * source_width + columns.start..row * source_width + columns.end]
+                    })
+                };
+                // the number of pixels of a line across the block
+                let line = rows.len().min(columns.len()).max(2);
+                let (mut mode, mut best) = (0, 0.0);
+                for &index in block() {
+                    let count = &mut counts[usize::from(index)];
+                    *count += 1;
+                    // scores only increase with the counts, so the best score is tracked as the
+                    // block is counted
+                    let score = if *count >= line {
+                        *count as f64 * weights[usize::from(index)]
+                    } else {
+                        *count as f64
+                    };
+                    if score > best {
+                        (mode, best) = (index, score);
+                    }
+                }
+                for &index in block() {
+                    counts[usize::from(index)] = 0;
+                }
+                raster.push(mode);
+            }
+        }
+        raster
+    }
+}
@@ -0,0 +1,86 @@
+use crate::{Error, KapImageFile};
+
+/// How [`KapImageFile::split`] cuts a chart
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitLayout {
+    /// A grid of `columns * rows` sub-charts
+    Grid {
+        /// The number of sub-charts across
+        columns: u16,
+        /// The number of sub-charts down
+        rows: u16,
+    },
+    /// The smallest grid of sub-charts at most this many pixels wide and high
+    MaxSize(u16),
+}
+
+impl SplitLayout {
+    /// Returns the number of columns and rows of sub-charts of a `width * height` image, or no
+    /// sub-chart for a maximum size of 0
+    const fn grid(self, (width, height): (u16, u16)) -> (u16, u16) {
+        match self {
+            Self::Grid { columns, rows } => (columns, rows),
+            Self::MaxSize(0) => (0, 0),
+            Self::MaxSize(size) => (width.div_ceil(size), height.div_ceil(size)),
+        }
+    }
+}
+
+/// Returns the start of the `i`th of `count` equal parts of `length` pixels
+fn boundary(i: u16, count: u16, length: u16) -> u16 {
+    let start = u32::from(i) * u32::from(length) / u32::from(count);
+    u16::try_from(start).unwrap_or(length)
+}
+
+impl KapImageFile {
+    /// Splits the image into sub-charts of (almost) equal sizes, in row-major order
+    ///
+    /// Each sub-chart is cropped from the image (see [`Self::crop`]), so its georeferencing
+    /// follows it, and unused colors are removed from its palettes (see
+    /// [`Self::trim_palette`]). The `i`th of `n` sub-charts, starting from 1, has `i/n`
+    /// appended to its name (`NA`) after a space, and `-i` appended to its number (`NU`).
+    ///
+    /// # Errors
+    ///
+    /// This function errors if the layout has no sub-chart (including a [`SplitLayout::MaxSize`]
+    /// of 0), if the sub-charts would be empty, or for any of the reasons listed in
+    /// [`Self::crop`].
+    pub fn split(&self, layout: SplitLayout) -> Result<Vec<Self>, Error> {
+        let (width, height) = (self.width(), self.height());
+        let (columns, rows) = layout.grid((width, height));
+        if columns == 0 || rows == 0 || columns > width || rows > height {
+            return Err(Error::InvalidWindow {
+                origin: (0, 0),
+                size: (
+                    width.checked_div(columns).unwrap_or(0),
+                    height.checked_div(rows).unwrap_or(0),
+                ),
+                image: (width, height),
+            });
+        }
+        let count = usize::from(columns) * usize::from(rows);
+        let mut charts = Vec::with_capacity(count);
+        for row in 0..rows {
+            let (y, end_y) = (boundary(row, rows, height), boundary(row + 1, rows, height));
+            for column in 0..columns {
+                let (x, end_x) = (
+                    boundary(column, columns, width),
+                    boundary(column + 1, columns, width),
+                );
+                let chart = self.crop((x, y), (end_x - x, end_y - y))?.trim_palette();
+                let mut header = chart.header().clone();
+                let number = charts.len() + 1;
+                let parameters = &mut header.general_parameters;
+                if let Some(name) = parameters.chart_name.as_mut() {
+                    *name = format!("{name} {number}/{count}");
+                }
+                if let Some(chart_number) = parameters.chart_number.as_mut() {
+                    *chart_number = format!("{chart_number}-{number}");
+                }
+                let chart = Self::new(header, chart.pixel_indices().to_vec())?;
+                charts.push(chart);
+            }
+        }
+        Ok(charts)
+    }
+}
@@ -76,10 +76,13 @@ impl BsbDecompressor<1> for Decompressor<1> {
             if count > image_width {
                 count = image_width;
             }
+            // indices start from 1, so the pixels of 1-bit images can only hold index 1
+            if pixel & 0x01 == 0 {
+                return Err(Error::ZeroIndexInOneBitImage);
+            }
             image_width = image_width.saturating_sub(count);
             while count != 0 {
-                // TODO: test
-                decompressed_row_buf[(xout >> 3) as usize] |= pixel << (7 - (xout & 0x7));
+                decompressed_row_buf[xout as usize] = pixel & 0x01;
                 xout += 1;
                 count -= 1;
             }
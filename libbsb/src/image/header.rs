@@ -1,7 +1,7 @@
 use bon::Builder;
 use chrono::NaiveDate;
 
-use crate::image::Depth;
+use crate::image::{ColorPalette, Depth};
 
 /// Raw image header, holding all possible records and fields for BSB/KAP image files
 ///
@@ -14,7 +14,7 @@ use crate::image::Depth;
 /// 2. [`ImageHeader::ifm`] must have [`Depth`]
 ///
 // See the research materials [readme](../../../../research/readme.md) for more details
-#[derive(Default, Debug, PartialEq, PartialOrd, Builder)]
+#[derive(Default, Debug, Clone, PartialEq, PartialOrd, Builder)]
 #[non_exhaustive]
 pub struct ImageHeader {
     /// Comments
@@ -188,11 +188,14 @@ pub struct ImageHeader {
 /// Represents a Polynomial used to map L <-> X || L <-> Y
 #[derive(Default, Debug, Clone, PartialEq, PartialOrd)]
 pub struct Polynomial {
-    /// values appear to be 1-4 going from 1=SW 2=NW 3=NE 4=SE
-    // TODO: unclear what this is - perhaps the corner of the chart?
+    /// The order of the polynomial (1 for linear, 2 for quadratic)
+    ///
+    /// `imgkap`, `GDAL` and `OpenCPN` all treat this value as the polynomial order. Since
+    /// [`Polynomial::poly`] holds 6 coefficients, orders above 2 are not supported.
+    // NOTE: kept as `corner` to avoid breaking the API
     pub corner: usize,
 
-    /// The coordinates used by the polynomial
+    /// The coefficients of the polynomial
     pub poly: [f64; 6],
 }
 
@@ -200,6 +203,47 @@ impl Polynomial {
     pub(crate) const fn new(corner: usize, poly: [f64; 6]) -> Self {
         Self { corner, poly }
     }
+
+    /// Evaluates the polynomial at `(u, v)`
+    ///
+    /// The coefficients are applied in the order used by BSB/KAP files:
+    /// `c0 + c1*u + c2*v + c3*u*u + c4*u*v + c5*v*v`.
+    ///
+    /// For [`ImageHeader::wpx`] and [`ImageHeader::wpy`], `(u, v)` is `(longitude, latitude)`.
+    /// For [`ImageHeader::pwx`] and [`ImageHeader::pwy`], `(u, v)` is the pixel `(x, y)`.
+    #[must_use]
+    #[allow(clippy::suboptimal_flops)]
+    pub fn evaluate(&self, u: f64, v: f64) -> f64 {
+        let c = &self.poly;
+        c[0] + c[1] * u + c[2] * v + c[3] * u * u + c[4] * u * v + c[5] * v * v
+    }
+
+    /// Substitutes the inputs of the polynomial with the linear polynomials `u` and `v`,
+    /// i.e. returns `p(u(s, t), v(s, t))` as a polynomial of `(s, t)`
+    #[allow(clippy::suboptimal_flops, clippy::many_single_char_names)]
+    pub(crate) fn compose(&self, u: &Self, v: &Self) -> Self {
+        debug_assert!(u.poly[3..].iter().chain(&v.poly[3..]).all(|&c| c == 0.0));
+        let a = &self.poly;
+        let [p0, p1, p2, ..] = u.poly;
+        let [q0, q1, q2, ..] = v.poly;
+        let poly = [
+            a[0] + a[1] * p0 + a[2] * q0 + a[3] * p0 * p0 + a[4] * p0 * q0 + a[5] * q0 * q0,
+            a[1] * p1
+                + a[2] * q1
+                + 2.0 * a[3] * p0 * p1
+                + a[4] * (p0 * q1 + q0 * p1)
+                + 2.0 * a[5] * q0 * q1,
+            a[1] * p2
+                + a[2] * q2
+                + 2.0 * a[3] * p0 * p2
+                + a[4] * (p0 * q2 + q0 * p2)
+                + 2.0 * a[5] * q0 * q2,
+            a[3] * p1 * p1 + a[4] * p1 * q1 + a[5] * q1 * q1,
+            2.0 * a[3] * p1 * p2 + a[4] * (p1 * q2 + p2 * q1) + 2.0 * a[5] * q1 * q2,
+            a[3] * p2 * p2 + a[4] * p2 * q2 + a[5] * q2 * q2,
+        ];
+        Self::new(self.corner, poly)
+    }
 }
 
 impl ImageHeader {
@@ -214,6 +258,38 @@ impl ImageHeader {
     pub(crate) const fn height(&self) -> u16 {
         self.general_parameters.image_width_height.1
     }
+
+    /// Returns the colors of the given palette, or [`None`] if the header does not contain it
+    #[must_use]
+    pub fn palette(&self, palette: ColorPalette) -> Option<&[(u8, u8, u8)]> {
+        match palette {
+            ColorPalette::Rgb => self.rgb.as_deref(),
+            ColorPalette::Day => self.day.as_deref(),
+            ColorPalette::Dsk => self.dsk.as_deref(),
+            ColorPalette::Ngt => self.ngt.as_deref(),
+            ColorPalette::Ngr => self.ngr.as_deref(),
+            ColorPalette::Gry => self.gry.as_deref(),
+            ColorPalette::Prc => self.prc.as_deref(),
+            ColorPalette::Prg => self.prg.as_deref(),
+        }
+    }
+
+    /// Returns a mutable reference to the record of the given palette
+    pub(crate) const fn palette_mut(
+        &mut self,
+        palette: ColorPalette,
+    ) -> &mut Option<Vec<(u8, u8, u8)>> {
+        match palette {
+            ColorPalette::Rgb => &mut self.rgb,
+            ColorPalette::Day => &mut self.day,
+            ColorPalette::Dsk => &mut self.dsk,
+            ColorPalette::Ngt => &mut self.ngt,
+            ColorPalette::Ngr => &mut self.ngr,
+            ColorPalette::Gry => &mut self.gry,
+            ColorPalette::Prc => &mut self.prc,
+            ColorPalette::Prg => &mut self.prg,
+        }
+    }
 }
 
 /// Record identifier: BSB
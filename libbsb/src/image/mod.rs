@@ -5,6 +5,7 @@ pub(crate) mod compress;
 pub(crate) mod decompress;
 pub(crate) mod header;
 pub(crate) mod index;
+mod palette;
 
 /// Module containing raw types
 ///
@@ -85,6 +86,20 @@ pub enum ColorPalette {
     Prg,
 }
 
+impl ColorPalette {
+    /// All the palettes, in the order of their records
+    pub(crate) const ALL: [Self; 8] = [
+        Self::Rgb,
+        Self::Day,
+        Self::Dsk,
+        Self::Ngt,
+        Self::Ngr,
+        Self::Gry,
+        Self::Prc,
+        Self::Prg,
+    ];
+}
+
 #[derive(Default, Debug, Eq, PartialEq, PartialOrd, Ord, Copy, Clone)]
 /// Image depth
 /// BSB/KAP image files only support 1, 4, and 7 pixel depth
@@ -99,12 +114,22 @@ pub enum Depth {
     Seven,
 }
 
+/// Checks that no pixel of a 1-bit image has the index 0, since the pixels of 1-bit images can
+/// only hold index 1
+fn check_indices(depth: Depth, raster_data: &[u8]) -> Result<(), Error> {
+    if depth == Depth::One && raster_data.contains(&0) {
+        return Err(Error::ZeroIndexInOneBitImage);
+    }
+    Ok(())
+}
+
 impl KapImageFile {
     /// Creates a new [`Self`]
     ///
     /// # Errors
     /// This function errors if the width and height of the image header don't match
-    /// the width and height of the bitmap
+    /// the width and height of the bitmap, or if the image is a 1-bit image with pixels of
+    /// index 0
     ///
     pub fn new(header: ImageHeader, raster_data: Vec<u8>) -> Result<Self, Error> {
         let width = header.width();
@@ -115,6 +140,7 @@ impl KapImageFile {
                 raster_length: raster_data.len(),
             });
         }
+        check_indices(header.ifm, &raster_data)?;
         Ok(Self {
             header,
             bitmap: BitMap::new(width, height, raster_data),
@@ -229,9 +255,12 @@ impl KapImageFile {
     ///
     /// # Errors
     ///
-    /// This will error if unable to open and/or write to the provided filename
+    /// This will error if unable to open and/or write to the provided filename, or if the
+    /// image is a 1-bit image with pixels of index 0 (e.g. a 4-bit raster read from a file whose
+    /// header claims a depth of 1)
     ///
     pub fn into_file(mut self, filename: impl AsRef<Path>) -> Result<(), crate::Error> {
+        check_indices(self.header.ifm, self.pixel_indices())?;
         let f = File::options()
             .create(true)
             .write(true)
@@ -288,16 +317,7 @@ impl KapImageFile {
         &self,
         palette: ColorPalette,
     ) -> Result<impl Iterator<Item = [u8; 3]> + '_, crate::Error> {
-        let Some(rgbs) = (match palette {
-            ColorPalette::Rgb => self.header().rgb.as_ref(),
-            ColorPalette::Day => self.header().day.as_ref(),
-            ColorPalette::Dsk => self.header().dsk.as_ref(),
-            ColorPalette::Ngt => self.header().ngt.as_ref(),
-            ColorPalette::Ngr => self.header().ngr.as_ref(),
-            ColorPalette::Gry => self.header().gry.as_ref(),
-            ColorPalette::Prc => self.header().prc.as_ref(),
-            ColorPalette::Prg => self.header().prg.as_ref(),
-        }) else {
+        let Some(rgbs) = self.header().palette(palette) else {
             return Err(crate::Error::NonExistentPalette);
         };
         // let rgbs = self.header.rgb.as_ref().context("RGB not found")?;
@@ -321,6 +341,19 @@ impl KapImageFile {
     }
 }
 
+impl Depth {
+    /// Returns the smallest depth of a palette of `count` colors
+    pub(crate) const fn for_color_count(count: usize) -> Self {
+        if count <= 1 {
+            Self::One
+        } else if count <= 15 {
+            Self::Four
+        } else {
+            Self::Seven
+        }
+    }
+}
+
 impl From<Depth> for u8 {
     fn from(value: Depth) -> Self {
         match value {
@@ -0,0 +1,44 @@
+use super::{bitmap::BitMap, ColorPalette, Depth, KapImageFile};
+
+impl KapImageFile {
+    /// Removes the colors that no pixel uses from the palettes, renumbering the pixel indices
+    ///
+    /// The remaining colors keep their order, and the depth (`IFM`) is lowered if the trimmed
+    /// palettes fit in fewer bits.
+    #[must_use]
+    pub fn trim_palette(&self) -> Self {
+        let mut used = [false; 256];
+        for &index in self.pixel_indices() {
+            used[usize::from(index)] = true;
+        }
+        // the new index of each used index, starting from 1
+        let mut indices = [0u8; 256];
+        let mut count = 0;
+        for (index, _) in used.iter().enumerate().skip(1).filter(|(_, &used)| used) {
+            count += 1;
+            indices[index] = count;
+        }
+
+        let mut header = self.header().clone();
+        for palette in ColorPalette::ALL {
+            if let Some(colors) = header.palette_mut(palette) {
+                *colors = colors
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| used[i + 1])
+                    .map(|(_, &color)| color)
+                    .collect();
+            }
+        }
+        header.ifm = header.ifm.min(Depth::for_color_count(usize::from(count)));
+        let raster = self
+            .pixel_indices()
+            .iter()
+            .map(|&index| indices[usize::from(index)])
+            .collect();
+        Self {
+            bitmap: BitMap::new(self.width(), self.height(), raster),
+            header,
+        }
+    }
+}
@@ -17,8 +17,10 @@
 //!
 //! This library currently **only** supports the BSB image file.
 //!
-//! While the BSB/KAP image file supposedly supports a image depth of `1`, I haven't found any examples
-//!   to test this functionality yet.
+//! BSB/KAP image files with a depth of `1` are supported, although I haven't found any examples
+//!   in the wild: they are tested against charts generated in the tests. Since indices start
+//!   from 1, a 1-bit image can only hold a single color, and 1-bit images with pixels of index 0
+//!   are rejected when they are created, read or written.
 //!
 //!
 //! Comments inside BSB/KAP are currently ignored, since it remains unclear how they should be
@@ -163,15 +165,22 @@
     missing_docs
 )]
 
+pub mod crs;
 mod error;
+pub mod geodesy;
+pub mod georef;
 /// Module containing types for BSB/KAP image files
 pub mod image;
+pub mod projection;
 mod serde;
 
+pub use crs::Crs;
 pub use error::Error;
+pub use georef::GeoTransform;
 pub use image::ColorPalette;
 pub use image::Depth;
 pub use image::KapImageFile;
+pub use projection::Projection;
 
 const CTRL_Z: u8 = 0x1a;
 // Carriage return and line feed (BSB/KAP files use windows-style linebreaks)
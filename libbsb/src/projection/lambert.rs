@@ -0,0 +1,60 @@
+//! Lambert Conformal Conic projection with one or two standard parallels
+
+use super::{isometric_t, latitude_from_t};
+use crate::geodesy::Ellipsoid;
+
+/// Constants of the cone, derived from the standard parallels and the origin
+pub struct Cone {
+    /// First eccentricity
+    e: f64,
+    /// Cone constant
+    n: f64,
+    /// `a * F`, the radius of the cone at `t = 1`
+    scale: f64,
+    /// Radius of the parallel of origin
+    rho_origin: f64,
+}
+
+impl Cone {
+    /// Creates a new [`Cone`] from the latitude of origin and the standard parallels, all given
+    /// in radians
+    pub fn new(ellipsoid: &Ellipsoid, lat_origin: f64, (first, second): (f64, f64)) -> Self {
+        let e = ellipsoid.eccentricity();
+        let m = |lat: f64| ellipsoid.prime_vertical_radius(lat) * lat.cos();
+        let n = if (first - second).abs() < 1e-12 {
+            first.sin()
+        } else {
+            (m(first).ln() - m(second).ln())
+                / (isometric_t(e, first).ln() - isometric_t(e, second).ln())
+        };
+        let scale = m(first) / (n * isometric_t(e, first).powf(n));
+        Self {
+            e,
+            n,
+            scale,
+            rho_origin: scale * isometric_t(e, lat_origin).powf(n),
+        }
+    }
+
+    pub fn forward(&self, lat: f64, dlon: f64) -> (f64, f64) {
+        let rho = self.scale * isometric_t(self.e, lat).powf(self.n);
+        let theta = self.n * dlon;
+        (
+            rho * theta.sin(),
+            rho.mul_add(-theta.cos(), self.rho_origin),
+        )
+    }
+
+    pub fn inverse(&self, x: f64, y: f64) -> (f64, f64) {
+        let sign = self.n.signum();
+        let dy = self.rho_origin - y;
+        let rho = sign * x.hypot(dy);
+        let theta = (sign * x).atan2(sign * dy);
+        let lat = if rho == 0.0 {
+            sign * std::f64::consts::FRAC_PI_2
+        } else {
+            latitude_from_t(self.e, (rho / self.scale).powf(1.0 / self.n))
+        };
+        (lat, theta / self.n)
+    }
+}
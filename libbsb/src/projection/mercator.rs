@@ -0,0 +1,25 @@
+//! Mercator projection (variant with a latitude of true scale)
+
+use super::{isometric_t, latitude_from_t};
+use crate::geodesy::Ellipsoid;
+
+/// Returns `a * k0`, the radius of the parallel of true scale
+fn scaled_radius(ellipsoid: &Ellipsoid, lat_ts: f64) -> f64 {
+    ellipsoid.prime_vertical_radius(lat_ts) * lat_ts.cos()
+}
+
+pub fn forward(ellipsoid: &Ellipsoid, lat_ts: f64, lat: f64, dlon: f64) -> (f64, f64) {
+    let r = scaled_radius(ellipsoid, lat_ts);
+    (
+        r * dlon,
+        -r * isometric_t(ellipsoid.eccentricity(), lat).ln(),
+    )
+}
+
+pub fn inverse(ellipsoid: &Ellipsoid, lat_ts: f64, x: f64, y: f64) -> (f64, f64) {
+    let r = scaled_radius(ellipsoid, lat_ts);
+    (
+        latitude_from_t(ellipsoid.eccentricity(), (-y / r).exp()),
+        x / r,
+    )
+}
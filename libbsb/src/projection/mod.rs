@@ -0,0 +1,415 @@
+//! Cartographic projections described by the `KNP` and `KNQ` records
+//!
+//! BSB/KAP charts name their projection in the `PR` field of the `KNP` record (see
+//! [`DetailedParameters::projection_name`]), with its parameters spread over the `PP` field and
+//! the `P1` to `P8` fields of the `KNQ` record (see [`AdditionalParameters`]).
+//! [`Projection::from_header`] turns these fields into a typed [`Projection`] which converts
+//! geographical coordinates into projected coordinates (in metres) and back.
+//!
+//! The following projections are supported:
+//!
+//! | `PR`                        | Parameters                                                        |
+//! |-----------------------------|-------------------------------------------------------------------|
+//! | `MERCATOR`                  | latitude of true scale: `PP` (or `P2`)                            |
+//! | `TRANSVERSE MERCATOR`       | central meridian: `P5` (or `PP`), scale factor: `P3`, origin: `P6` |
+//! | `LAMBERT CONFORMAL CONIC`   | standard parallels: `P3`, `P4`, central meridian: `P5` (or `PP`), origin: `P6` |
+//! | `POLYCONIC`                 | central meridian: `PP` (or `P5`), origin: `P6`                    |
+//!
+//! The false easting and northing are read from `P7` and `P8` when present. Since the
+//! georeferencing of a chart is fitted to its `REF` records in projected space (see
+//! [`crate::GeoTransform::from_projection`]), these only offset the projected coordinates.
+//!
+//! [`DetailedParameters::projection_name`]: crate::image::raw::header::DetailedParameters::projection_name
+//! [`AdditionalParameters`]: crate::image::raw::header::AdditionalParameters
+
+mod lambert;
+mod mercator;
+mod polyconic;
+mod transverse_mercator;
+
+use crate::{
+    geodesy::Ellipsoid,
+    georef::normalize_longitude,
+    image::header::{AdditionalParameters, ImageHeader},
+    Error,
+};
+
+/// A cartographic projection on a given [`Ellipsoid`]
+///
+/// Geographical coordinates are given as `(latitude, longitude)` in decimal degrees, and
+/// projected coordinates as `(easting, northing)` in metres.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Projection {
+    /// The projection method and its parameters
+    pub method: ProjectionMethod,
+    /// The ellipsoid of the geodetic datum
+    pub ellipsoid: Ellipsoid,
+    /// False easting in metres
+    pub false_easting: f64,
+    /// False northing in metres
+    pub false_northing: f64,
+}
+
+/// Projection methods supported by [`Projection`]
+///
+/// All angles are given in decimal degrees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum ProjectionMethod {
+    /// Mercator, defined by its latitude of true scale
+    Mercator {
+        /// Longitude of the central meridian
+        central_meridian: f64,
+        /// Latitude at which the scale is true
+        latitude_of_true_scale: f64,
+    },
+    /// Transverse Mercator
+    TransverseMercator {
+        /// Longitude of the central meridian
+        central_meridian: f64,
+        /// Latitude of the origin
+        latitude_of_origin: f64,
+        /// Scale factor on the central meridian
+        scale_factor: f64,
+    },
+    /// Lambert Conformal Conic with one or two standard parallels
+    LambertConformalConic {
+        /// Longitude of the central meridian
+        central_meridian: f64,
+        /// Latitude of the origin
+        latitude_of_origin: f64,
+        /// First and second standard parallels (equal for the one parallel variant)
+        standard_parallels: (f64, f64),
+    },
+    /// American Polyconic
+    Polyconic {
+        /// Longitude of the central meridian
+        central_meridian: f64,
+        /// Latitude of the origin
+        latitude_of_origin: f64,
+    },
+}
+
+impl ProjectionMethod {
+    /// Returns the longitude of the central meridian
+    #[must_use]
+    pub const fn central_meridian(&self) -> f64 {
+        match *self {
+            Self::Mercator {
+                central_meridian, ..
+            }
+            | Self::TransverseMercator {
+                central_meridian, ..
+            }
+            | Self::LambertConformalConic {
+                central_meridian, ..
+            }
+            | Self::Polyconic {
+                central_meridian, ..
+            } => central_meridian,
+        }
+    }
+}
+
+impl Projection {
+    /// Creates a new [`Projection`] without false easting and northing
+    #[must_use]
+    pub const fn new(method: ProjectionMethod, ellipsoid: Ellipsoid) -> Self {
+        Self {
+            method,
+            ellipsoid,
+            false_easting: 0.0,
+            false_northing: 0.0,
+        }
+    }
+
+    /// Creates a [`Projection`] from the `KNP` and `KNQ` records of a header
+    ///
+    /// The ellipsoid is derived from the geodetic datum (see [`ImageHeader::datum`]), falling
+    /// back to WGS84 when the datum is missing or unknown.
+    ///
+    /// # Errors
+    ///
+    /// This function errors if the header has no projection name, if the projection is not
+    /// supported, or if a parameter required by the projection is missing.
+    pub fn from_header(header: &ImageHeader) -> Result<Self, Error> {
+        let knp = header
+            .detailed_parameters
+            .as_ref()
+            .ok_or(Error::MissingProjectionParameter("PR"))?;
+        let name = knp
+            .projection_name
+            .as_deref()
+            .ok_or(Error::MissingProjectionParameter("PR"))?;
+        let knq = header.additional_parameters.clone().unwrap_or_default();
+        let pp = knp.projection_parameter.map(widen);
+        let p = Parameters(&knq);
+
+        let normalized = name.to_ascii_uppercase();
+        let method = if normalized.contains("TRANSVERSE") {
+            ProjectionMethod::TransverseMercator {
+                central_meridian: p
+                    .get(5)
+                    .or(pp)
+                    .ok_or(Error::MissingProjectionParameter("P5"))?,
+                latitude_of_origin: p.get(6).unwrap_or_default(),
+                scale_factor: p.get(3).filter(|&k| k > 0.0).unwrap_or(1.0),
+            }
+        } else if normalized.contains("MERCATOR") {
+            ProjectionMethod::Mercator {
+                central_meridian: p.get(5).unwrap_or_default(),
+                latitude_of_true_scale: pp.or_else(|| p.get(2)).unwrap_or_default(),
+            }
+        } else if normalized.contains("LAMBERT") {
+            let first = p.get(3).ok_or(Error::MissingProjectionParameter("P3"))?;
+            let second = p.get(4).unwrap_or(first);
+            ProjectionMethod::LambertConformalConic {
+                central_meridian: p
+                    .get(5)
+                    .or(pp)
+                    .ok_or(Error::MissingProjectionParameter("P5"))?,
+                latitude_of_origin: p.get(6).unwrap_or_else(|| f64::midpoint(first, second)),
+                standard_parallels: (first, second),
+            }
+        } else if normalized.contains("POLYCONIC") {
+            ProjectionMethod::Polyconic {
+                central_meridian: pp
+                    .or_else(|| p.get(5))
+                    .ok_or(Error::MissingProjectionParameter("PP"))?,
+                latitude_of_origin: p.get(6).unwrap_or_default(),
+            }
+        } else {
+            return Err(Error::UnsupportedProjection(name.to_owned()));
+        };
+
+        Ok(Self {
+            method,
+            ellipsoid: header
+                .datum()
+                .map(crate::geodesy::Datum::ellipsoid)
+                .unwrap_or_default(),
+            false_easting: p.get(7).unwrap_or_default(),
+            false_northing: p.get(8).unwrap_or_default(),
+        })
+    }
+
+    /// Projects `(latitude, longitude)` into `(easting, northing)`
+    #[must_use]
+    pub fn forward(&self, (lat, lon): (f64, f64)) -> (f64, f64) {
+        let lat = lat.to_radians();
+        let dlon = normalize_longitude(lon - self.method.central_meridian()).to_radians();
+        let e = &self.ellipsoid;
+        let (x, y) = match self.method {
+            ProjectionMethod::Mercator {
+                latitude_of_true_scale,
+                ..
+            } => mercator::forward(e, latitude_of_true_scale.to_radians(), lat, dlon),
+            ProjectionMethod::TransverseMercator {
+                latitude_of_origin,
+                scale_factor,
+                ..
+            } => transverse_mercator::forward(
+                e,
+                latitude_of_origin.to_radians(),
+                scale_factor,
+                lat,
+                dlon,
+            ),
+            ProjectionMethod::LambertConformalConic {
+                latitude_of_origin,
+                standard_parallels,
+                ..
+            } => lambert::Cone::new(
+                e,
+                latitude_of_origin.to_radians(),
+                (
+                    standard_parallels.0.to_radians(),
+                    standard_parallels.1.to_radians(),
+                ),
+            )
+            .forward(lat, dlon),
+            ProjectionMethod::Polyconic {
+                latitude_of_origin, ..
+            } => polyconic::forward(e, latitude_of_origin.to_radians(), lat, dlon),
+        };
+        (x + self.false_easting, y + self.false_northing)
+    }
+
+    /// Converts `(easting, northing)` back into `(latitude, longitude)`
+    #[must_use]
+    pub fn inverse(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        let (x, y) = (x - self.false_easting, y - self.false_northing);
+        let e = &self.ellipsoid;
+        let (lat, dlon) = match self.method {
+            ProjectionMethod::Mercator {
+                latitude_of_true_scale,
+                ..
+            } => mercator::inverse(e, latitude_of_true_scale.to_radians(), x, y),
+            ProjectionMethod::TransverseMercator {
+                latitude_of_origin,
+                scale_factor,
+                ..
+            } => {
+                transverse_mercator::inverse(e, latitude_of_origin.to_radians(), scale_factor, x, y)
+            }
+            ProjectionMethod::LambertConformalConic {
+                latitude_of_origin,
+                standard_parallels,
+                ..
+            } => lambert::Cone::new(
+                e,
+                latitude_of_origin.to_radians(),
+                (
+                    standard_parallels.0.to_radians(),
+                    standard_parallels.1.to_radians(),
+                ),
+            )
+            .inverse(x, y),
+            ProjectionMethod::Polyconic {
+                latitude_of_origin, ..
+            } => polyconic::inverse(e, latitude_of_origin.to_radians(), x, y),
+        };
+        (
+            lat.to_degrees(),
+            normalize_longitude(dlon.to_degrees() + self.method.central_meridian()),
+        )
+    }
+}
+
+impl ImageHeader {
+    /// Returns the [`Projection`] described by the `KNP` and `KNQ` records
+    ///
+    /// # Errors
+    ///
+    /// See [`Projection::from_header`]
+    pub fn projection(&self) -> Result<Projection, Error> {
+        Projection::from_header(self)
+    }
+
+    /// Writes a [`Projection`] into the `PR` and `PP` fields of the `KNP` record and the `P1` to
+    /// `P8` fields of the `KNQ` record, so that [`Projection::from_header`] reads it back
+    ///
+    /// The other fields of these records are left untouched: in particular, the geodetic datum
+    /// (`GD`) is not changed to match the ellipsoid of the projection.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn set_projection(&mut self, projection: &Projection) {
+        let text = |value: f64| Some(value.to_string());
+        let (name, parameter) = match projection.method {
+            ProjectionMethod::Mercator {
+                latitude_of_true_scale,
+                ..
+            } => ("MERCATOR", latitude_of_true_scale),
+            ProjectionMethod::TransverseMercator {
+                central_meridian, ..
+            } => ("TRANSVERSE MERCATOR", central_meridian),
+            ProjectionMethod::LambertConformalConic {
+                central_meridian, ..
+            } => ("LAMBERT CONFORMAL CONIC", central_meridian),
+            ProjectionMethod::Polyconic {
+                central_meridian, ..
+            } => ("POLYCONIC", central_meridian),
+        };
+        let knp = self
+            .detailed_parameters
+            .get_or_insert_with(Default::default);
+        knp.projection_name = Some(name.to_owned());
+        knp.projection_parameter = Some(parameter as f32);
+
+        let knq = self
+            .additional_parameters
+            .get_or_insert_with(Default::default);
+        knq.p1 = None;
+        knq.p2 = None;
+        knq.p3 = None;
+        knq.p4 = None;
+        knq.p5 = text(projection.method.central_meridian());
+        knq.p6 = None;
+        knq.p7 = text(projection.false_easting);
+        knq.p8 = text(projection.false_northing);
+        knq.pc = None;
+        match projection.method {
+            ProjectionMethod::Mercator {
+                latitude_of_true_scale,
+                ..
+            } => knq.p2 = Some(latitude_of_true_scale as f32),
+            ProjectionMethod::TransverseMercator {
+                latitude_of_origin,
+                scale_factor,
+                ..
+            } => {
+                knq.p3 = text(scale_factor);
+                knq.p6 = text(latitude_of_origin);
+                knq.pc = Some("TC".to_owned());
+            }
+            ProjectionMethod::LambertConformalConic {
+                latitude_of_origin,
+                standard_parallels: (first, second),
+                ..
+            } => {
+                knq.p3 = text(first);
+                knq.p4 = text(second);
+                knq.p6 = text(latitude_of_origin);
+            }
+            ProjectionMethod::Polyconic {
+                latitude_of_origin, ..
+            } => knq.p6 = text(latitude_of_origin),
+        }
+    }
+}
+
+/// Numerical access to the `P1` to `P8` fields of the `KNQ` record
+///
+/// Values such as `NOT_APPLICABLE` or `UNKNOWN` are treated as missing.
+struct Parameters<'a>(&'a AdditionalParameters);
+
+impl Parameters<'_> {
+    fn get(&self, index: usize) -> Option<f64> {
+        let knq = self.0;
+        if index == 2 {
+            return knq.p2.map(widen);
+        }
+        let value = match index {
+            1 => knq.p1.as_deref(),
+            3 => knq.p3.as_deref(),
+            4 => knq.p4.as_deref(),
+            5 => knq.p5.as_deref(),
+            6 => knq.p6.as_deref(),
+            7 => knq.p7.as_deref(),
+            8 => knq.p8.as_deref(),
+            _ => None,
+        };
+        value.and_then(|v| v.trim().parse().ok())
+    }
+}
+
+/// Converts a header `f32` into an `f64` through its decimal representation, so that e.g.
+/// `37.083` does not become `37.08300018310547`
+fn widen(value: f32) -> f64 {
+    value
+        .to_string()
+        .parse()
+        .unwrap_or_else(|_| f64::from(value))
+}
+
+/// Returns the isometric latitude function `t(lat)` used by the conformal projections
+fn isometric_t(e: f64, lat: f64) -> f64 {
+    let sin = e * lat.sin();
+    (std::f64::consts::FRAC_PI_4 - lat / 2.0).tan() / ((1.0 - sin) / (1.0 + sin)).powf(e / 2.0)
+}
+
+/// Inverts [`isometric_t`] by fixed point iteration
+fn latitude_from_t(e: f64, t: f64) -> f64 {
+    let mut lat = 2.0f64.mul_add(-t.atan(), std::f64::consts::FRAC_PI_2);
+    for _ in 0..15 {
+        let sin = e * lat.sin();
+        let next = 2.0f64.mul_add(
+            -(t * ((1.0 - sin) / (1.0 + sin)).powf(e / 2.0)).atan(),
+            std::f64::consts::FRAC_PI_2,
+        );
+        if (next - lat).abs() < 1e-14 {
+            return next;
+        }
+        lat = next;
+    }
+    lat
+}
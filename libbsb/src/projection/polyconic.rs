@@ -0,0 +1,46 @@
+//! American Polyconic projection
+//!
+//! The inverse has no closed form and is computed with Newton's method.
+
+use crate::geodesy::Ellipsoid;
+
+pub fn forward(ellipsoid: &Ellipsoid, lat_origin: f64, lat: f64, dlon: f64) -> (f64, f64) {
+    let arc_origin = ellipsoid.meridian_arc(lat_origin);
+    if lat.abs() < 1e-12 {
+        return (ellipsoid.semi_major_axis * dlon, -arc_origin);
+    }
+    let cot = ellipsoid.prime_vertical_radius(lat) / lat.tan();
+    let e = dlon * lat.sin();
+    (
+        cot * e.sin(),
+        cot.mul_add(1.0 - e.cos(), ellipsoid.meridian_arc(lat) - arc_origin),
+    )
+}
+
+pub fn inverse(ellipsoid: &Ellipsoid, lat_origin: f64, x: f64, y: f64) -> (f64, f64) {
+    const STEP: f64 = 1e-7;
+    let a = ellipsoid.semi_major_axis;
+    let mut lat = (y + ellipsoid.meridian_arc(lat_origin)) / a;
+    let mut dlon = x / (a * lat.cos());
+    for _ in 0..30 {
+        let (fx, fy) = forward(ellipsoid, lat_origin, lat, dlon);
+        let (rx, ry) = (fx - x, fy - y);
+        // numerical jacobian of the forward projection
+        let (x_lat, y_lat) = forward(ellipsoid, lat_origin, lat + STEP, dlon);
+        let (x_lon, y_lon) = forward(ellipsoid, lat_origin, lat, dlon + STEP);
+        let (j11, j12) = ((x_lat - fx) / STEP, (x_lon - fx) / STEP);
+        let (j21, j22) = ((y_lat - fy) / STEP, (y_lon - fy) / STEP);
+        let det = j11.mul_add(j22, -j12 * j21);
+        if det.abs() < f64::EPSILON {
+            break;
+        }
+        let step_lat = j22.mul_add(rx, -j12 * ry) / det;
+        let step_lon = j11.mul_add(ry, -j21 * rx) / det;
+        lat -= step_lat;
+        dlon -= step_lon;
+        if step_lat.abs() < 1e-13 && step_lon.abs() < 1e-13 {
+            break;
+        }
+    }
+    (lat, dlon)
+}
@@ -0,0 +1,121 @@
+//! Transverse Mercator projection, using the Krüger series to the fourth order in `n`
+//!
+//! The series are accurate to well below a millimetre within a few thousand kilometres of the
+//! central meridian.
+
+use crate::geodesy::Ellipsoid;
+
+/// Series coefficients derived from the third flattening of the ellipsoid
+struct Series {
+    /// Radius of the rectifying sphere
+    rectifying_radius: f64,
+    /// First eccentricity
+    e: f64,
+    /// Forward series coefficients
+    alpha: [f64; 4],
+    /// Inverse series coefficients
+    beta: [f64; 4],
+    /// Conformal to geodetic latitude series coefficients
+    delta: [f64; 4],
+}
+
+impl Series {
+    #[allow(clippy::suboptimal_flops)]
+    fn new(ellipsoid: &Ellipsoid) -> Self {
+        let f = ellipsoid.flattening;
+        let n = f / (2.0 - f);
+        let (n2, n3, n4) = (n * n, n * n * n, n * n * n * n);
+        Self {
+            rectifying_radius: ellipsoid.semi_major_axis / (1.0 + n) * (1.0 + n2 / 4.0 + n4 / 64.0),
+            e: ellipsoid.eccentricity(),
+            alpha: [
+                n / 2.0 - 2.0 * n2 / 3.0 + 5.0 * n3 / 16.0 + 41.0 * n4 / 180.0,
+                13.0 * n2 / 48.0 - 3.0 * n3 / 5.0 + 557.0 * n4 / 1440.0,
+                61.0 * n3 / 240.0 - 103.0 * n4 / 140.0,
+                49561.0 * n4 / 161_280.0,
+            ],
+            beta: [
+                n / 2.0 - 2.0 * n2 / 3.0 + 37.0 * n3 / 96.0 - n4 / 360.0,
+                n2 / 48.0 + n3 / 15.0 - 437.0 * n4 / 1440.0,
+                17.0 * n3 / 480.0 - 37.0 * n4 / 840.0,
+                4397.0 * n4 / 161_280.0,
+            ],
+            delta: [
+                2.0 * n - 2.0 * n2 / 3.0 - 2.0 * n3 + 116.0 * n4 / 45.0,
+                7.0 * n2 / 3.0 - 8.0 * n3 / 5.0 - 227.0 * n4 / 45.0,
+                56.0 * n3 / 15.0 - 136.0 * n4 / 35.0,
+                4279.0 * n4 / 630.0,
+            ],
+        }
+    }
+
+    /// Returns the Gauss-Krüger coordinates `(xi, eta)` of a point
+    #[allow(clippy::cast_precision_loss)]
+    fn gauss_kruger(&self, lat: f64, dlon: f64) -> (f64, f64) {
+        let t = self
+            .e
+            .mul_add(-(self.e * lat.sin()).atanh(), lat.sin().atanh())
+            .sinh();
+        let xi_prime = t.atan2(dlon.cos());
+        let eta_prime = (dlon.sin() / t.hypot(1.0)).atanh();
+        self.alpha
+            .iter()
+            .enumerate()
+            .fold((xi_prime, eta_prime), |(xi, eta), (j, alpha)| {
+                let k = 2.0 * (j + 1) as f64;
+                (
+                    alpha.mul_add((k * xi_prime).sin() * (k * eta_prime).cosh(), xi),
+                    alpha.mul_add((k * xi_prime).cos() * (k * eta_prime).sinh(), eta),
+                )
+            })
+    }
+}
+
+pub fn forward(
+    ellipsoid: &Ellipsoid,
+    lat_origin: f64,
+    scale_factor: f64,
+    lat: f64,
+    dlon: f64,
+) -> (f64, f64) {
+    let series = Series::new(ellipsoid);
+    let scale = scale_factor * series.rectifying_radius;
+    let (xi, eta) = series.gauss_kruger(lat, dlon);
+    let (xi_origin, _) = series.gauss_kruger(lat_origin, 0.0);
+    (scale * eta, scale * (xi - xi_origin))
+}
+
+#[allow(clippy::cast_precision_loss)]
+pub fn inverse(
+    ellipsoid: &Ellipsoid,
+    lat_origin: f64,
+    scale_factor: f64,
+    x: f64,
+    y: f64,
+) -> (f64, f64) {
+    let series = Series::new(ellipsoid);
+    let scale = scale_factor * series.rectifying_radius;
+    let (xi_origin, _) = series.gauss_kruger(lat_origin, 0.0);
+    let (xi, eta) = (y / scale + xi_origin, x / scale);
+    let (xi_prime, eta_prime) =
+        series
+            .beta
+            .iter()
+            .enumerate()
+            .fold((xi, eta), |(xi_prime, eta_prime), (j, beta)| {
+                let k = 2.0 * (j + 1) as f64;
+                (
+                    (-beta).mul_add((k * xi).sin() * (k * eta).cosh(), xi_prime),
+                    (-beta).mul_add((k * xi).cos() * (k * eta).sinh(), eta_prime),
+                )
+            });
+    let chi = (xi_prime.sin() / eta_prime.cosh()).asin();
+    let lat = series
+        .delta
+        .iter()
+        .enumerate()
+        .fold(chi, |lat, (j, delta)| {
+            delta.mul_add((2.0 * (j + 1) as f64 * chi).sin(), lat)
+        });
+    (lat, eta_prime.sinh().atan2(xi_prime.cos()))
+}
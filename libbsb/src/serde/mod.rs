@@ -19,8 +19,8 @@ use utils::{
     parse_ref, parse_till_comma_or_newline,
 };
 use Field::{
-    BD, BF, DU, DX, DY, EC, ED, GC, GD, NA, ND, NE, NU, P1, P2, P3, P4, PC, PI, PP, PR, RA, RE, RM,
-    SC, SD, SE, SK, SP, TA, UN, VC,
+    BD, BF, DU, DX, DY, EC, ED, GC, GD, NA, ND, NE, NU, P1, P2, P3, P4, P5, P6, P7, P8, PC, PI, PP,
+    PR, RA, RE, RM, SC, SD, SE, SK, SP, TA, UN, VC,
 };
 
 const AMERICAN_DATE_FORMAT: &str = "%m/%d/%Y";
@@ -201,16 +201,15 @@ impl ImageHeader {
             P2.serialize_field(self),
             P3.serialize_field(self),
             P4.serialize_field(self),
-            GC.serialize_field(self),
-            RM.serialize_field(self),
         ]);
-
-        // TODO:
-        // Should these be inserted before IF some?
-        //  P5.serialize_field(self),
-        //  P6.serialize_field(self),
-        //  P7.serialize_field(self),
-        //  P8.serialize_field(self),
+        // P5 to P8 are only written when present
+        out.extend(
+            [P5, P6, P7, P8]
+                .iter()
+                .map(|field| field.serialize_field(self))
+                .filter(|field| !field.is_empty()),
+        );
+        out.extend([GC.serialize_field(self), RM.serialize_field(self)]);
         format_field_names(out)
     }
 
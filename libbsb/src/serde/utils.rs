@@ -5,7 +5,7 @@ use nom::{
     character::complete::{digit1, multispace0},
     combinator::map_res,
     number::streaming::double,
-    sequence::separated_pair,
+    sequence::{preceded, separated_pair},
     IResult, InputLength,
 };
 use regex::Regex;
@@ -159,23 +159,28 @@ pub fn parse_coords(input: &str) -> IResult<&str, (f64, f64)> {
     Ok((input, coords))
 }
 
+// Polynomial coefficients are written right-aligned, so they may be preceded by spaces
+fn padded_double(input: &str) -> IResult<&str, f64> {
+    preceded(multispace0, double)(input)
+}
+
 pub fn parse_polynomial(input: &str) -> IResult<&str, [f64; 6]> {
-    let (input, v1) = double(input)?;
+    let (input, v1) = padded_double(input)?;
     let (input, _) = comma_or_multispace(input)?;
 
-    let (input, v2) = double(input)?;
+    let (input, v2) = padded_double(input)?;
     let (input, _) = comma_or_multispace(input)?;
 
-    let (input, v3) = double(input)?;
+    let (input, v3) = padded_double(input)?;
     let (input, _) = comma_or_multispace(input)?;
 
-    let (input, v4) = double(input)?;
+    let (input, v4) = padded_double(input)?;
     let (input, _) = comma_or_multispace(input)?;
 
-    let (input, v5) = double(input)?;
+    let (input, v5) = padded_double(input)?;
     let (input, _) = comma_or_multispace(input)?;
 
-    let (input, v6) = double(input)?;
+    let (input, v6) = padded_double(input)?;
     Ok((input, [v1, v2, v3, v4, v5, v6]))
 }
 
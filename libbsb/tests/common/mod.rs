@@ -1,9 +1,24 @@
 #![allow(unused)]
 
-const ORIGINAL_TEST_KAP_CHESAPEAKE_BAY_HEADER: &str =
-    "../test_assets/12221_1_MapTech_testing_file_header.KAP";
+use libbsb::image::raw::header::ImageHeader;
+
+pub const ORIGINAL_TEST_KAP_CHESAPEAKE_BAY_HEADER: &str =
+    "../test_assets/12221_1_MapTech_testing_file_header.kap";
+
+pub const SAINT_MALO_HEADER: &str =
+    "../test_assets/L16-22600-32392-16-16_16_Saint_Malo_Brittany_France_header.kap";
 
 pub const TEST_KAP: &str = "../test_assets/12221_1_MapTech_testing_origin.kap";
 pub const TEST_KAP_TO_PNG: &str = "../test_assets/12221_1_MapTech_testing_origin.kap";
 
 pub const CONVERTED_PNG_MAPTECH_TEST_KAP_4_DEPTH: &str = "../test_assets/converted_png_4_depth.png";
+
+/// Reads the BSB/KAP header at `path`
+pub fn read_header(path: &str) -> anyhow::Result<ImageHeader> {
+    Ok(std::fs::read_to_string(path)?.parse()?)
+}
+
+/// Reads the header of the Chesapeake Bay test chart
+pub fn chesapeake_header() -> anyhow::Result<ImageHeader> {
+    read_header(ORIGINAL_TEST_KAP_CHESAPEAKE_BAY_HEADER)
+}
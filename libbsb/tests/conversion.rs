@@ -142,3 +142,90 @@ fn recreate_png_from_converted_png() -> anyhow::Result<()> {
     assert_eq!(hash_1, hash_2);
     Ok(())
 }
+
+/// Returns a 100x8 pixels 1-bit chart whose pixels have the indices of `raster_data`
+fn one_bit_chart(raster_data: Vec<u8>) -> Result<KapImageFile, libbsb::Error> {
+    let header = ImageHeader::builder()
+        .ifm(Depth::One)
+        .general_parameters(
+            GeneralParameters::builder()
+                .chart_name("1-bit chart".to_owned())
+                .image_width_height((100, 8))
+                .build(),
+        )
+        .rgb(vec![(0, 0, 128)])
+        .build();
+    KapImageFile::new(header, raster_data)
+}
+
+#[test]
+fn one_bit_chart_round_trips() -> anyhow::Result<()> {
+    let tmp_kap = Temp::new_file()?;
+    one_bit_chart(vec![1; 100 * 8])?.into_file(&tmp_kap)?;
+
+    let bsb = KapImageFile::from_path(&tmp_kap)?;
+    assert_eq!(bsb.header().ifm, Depth::One);
+    assert_eq!((bsb.width(), bsb.height()), (100, 8));
+    // every row is a single run, longer than a 1-bit run length byte can hold
+    assert_eq!(bsb.pixel_indices(), vec![1; 100 * 8]);
+    assert!(bsb
+        .as_palette_iter(ColorPalette::Rgb)?
+        .all(|color| color == [0, 0, 128]));
+
+    // writing the chart again gives the same file
+    let data = std::fs::read(&tmp_kap)?;
+    let tmp_kap = Temp::new_file()?;
+    bsb.into_file(&tmp_kap)?;
+    assert_eq!(std::fs::read(&tmp_kap)?, data);
+    Ok(())
+}
+
+#[test]
+fn one_bit_chart_rejects_index_zero() -> anyhow::Result<()> {
+    let mut raster_data = vec![1; 100 * 8];
+    raster_data[150] = 0;
+    assert!(matches!(
+        one_bit_chart(raster_data),
+        Err(libbsb::Error::ZeroIndexInOneBitImage)
+    ));
+
+    let tmp_kap = Temp::new_file()?;
+    one_bit_chart(vec![1; 100 * 8])?.into_file(&tmp_kap)?;
+    let mut data = std::fs::read(&tmp_kap)?;
+    // clears the pixel bit of the first run of the second row
+    let run = data
+        .windows(3)
+        .position(|bytes| bytes == [0x01, 0xc0, 0x63])
+        .unwrap();
+    data[run + 1] = 0x80;
+    std::fs::write(&tmp_kap, &data)?;
+    assert!(matches!(
+        KapImageFile::from_path(&tmp_kap),
+        Err(libbsb::Error::ZeroIndexInOneBitImage)
+    ));
+
+    // a 4-bit raster with index 0, in a file whose header claims a depth of 1, is read but
+    // cannot be written back
+    let header = ImageHeader::builder()
+        .ifm(Depth::Four)
+        .general_parameters(
+            GeneralParameters::builder()
+                .chart_name("4-bit chart".to_owned())
+                .image_width_height((100, 8))
+                .build(),
+        )
+        .rgb(vec![(0, 0, 128)])
+        .build();
+    KapImageFile::new(header, vec![0; 100 * 8])?.into_file(&tmp_kap)?;
+    let mut data = std::fs::read(&tmp_kap)?;
+    let ifm = data.windows(5).position(|bytes| bytes == b"IFM/4").unwrap();
+    data[ifm + 4] = b'1';
+    std::fs::write(&tmp_kap, &data)?;
+    let bsb = KapImageFile::from_path(&tmp_kap)?;
+    assert_eq!(bsb.header().ifm, Depth::One);
+    assert!(matches!(
+        bsb.into_file(Temp::new_file()?),
+        Err(libbsb::Error::ZeroIndexInOneBitImage)
+    ));
+    Ok(())
+}
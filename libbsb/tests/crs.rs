@@ -0,0 +1,106 @@
+use common::chesapeake_header;
+use libbsb::{
+    geodesy::{Datum, Ellipsoid},
+    projection::{Projection, ProjectionMethod},
+    Crs,
+};
+
+mod common;
+
+#[test]
+fn proj_string_from_header() -> anyhow::Result<()> {
+    let header = chesapeake_header()?;
+    assert_eq!(
+        header.to_proj_string()?,
+        "+proj=merc +lat_ts=37.083 +lon_0=0 +x_0=0 +y_0=0 +datum=NAD83 +units=m +no_defs"
+    );
+    Ok(())
+}
+
+#[test]
+fn wkt2_from_header() -> anyhow::Result<()> {
+    let wkt = chesapeake_header()?.to_wkt2()?;
+    assert!(wkt.starts_with(r#"PROJCRS["NAD83 / Mercator",BASEGEOGCRS["NAD83",DATUM["North American Datum 1983",ELLIPSOID["GRS 1980",6378137,298.257222101,"#));
+    assert!(wkt.contains(r#"METHOD["Mercator (variant B)",ID["EPSG",9805]]"#));
+    assert!(wkt.contains(r#"PARAMETER["Latitude of 1st standard parallel",37.083,"#));
+    assert_eq!(wkt.matches('[').count(), wkt.matches(']').count());
+    Ok(())
+}
+
+#[test]
+fn crs_definitions_for_all_projections() {
+    let transverse_mercator = Crs::projected(
+        Datum::Nad27,
+        Projection {
+            false_easting: 500_000.0,
+            ..Projection::new(
+                ProjectionMethod::TransverseMercator {
+                    central_meridian: -75.0,
+                    latitude_of_origin: 0.0,
+                    scale_factor: 0.9996,
+                },
+                Ellipsoid::WGS84,
+            )
+        },
+    );
+    assert_eq!(
+        transverse_mercator.projection.unwrap().ellipsoid,
+        Ellipsoid::CLARKE_1866
+    );
+    assert_eq!(
+        transverse_mercator.to_proj_string(),
+        "+proj=tmerc +lat_0=0 +lon_0=-75 +k=0.9996 +x_0=500000 +y_0=0 +datum=NAD27 +units=m +no_defs"
+    );
+    assert!(transverse_mercator
+        .to_wkt2()
+        .contains(r#"PARAMETER["Scale factor at natural origin",0.9996,SCALEUNIT["unity",1]"#));
+
+    let lambert = Crs::projected(
+        Datum::Ed50,
+        Projection::new(
+            ProjectionMethod::LambertConformalConic {
+                central_meridian: 3.0,
+                latitude_of_origin: 46.5,
+                standard_parallels: (44.0, 49.0),
+            },
+            Ellipsoid::WGS84,
+        ),
+    );
+    assert_eq!(
+        lambert.to_proj_string(),
+        "+proj=lcc +lat_1=44 +lat_2=49 +lat_0=46.5 +lon_0=3 +x_0=0 +y_0=0 +ellps=intl +towgs84=-87,-98,-121,0,0,0,0 +units=m +no_defs"
+    );
+    assert!(lambert
+        .to_wkt2()
+        .contains(r#"Lambert Conic Conformal (2SP)"#));
+
+    let geographic = Crs::geographic(Datum::Wgs84);
+    assert_eq!(
+        geographic.to_proj_string(),
+        "+proj=longlat +datum=WGS84 +no_defs"
+    );
+    assert!(geographic.to_wkt2().starts_with(r#"GEOGCRS["WGS 84""#));
+    assert!(geographic.to_wkt2().ends_with(r#"ID["EPSG",4326]]"#));
+}
+
+#[test]
+fn unsupported_crs_errors() -> anyhow::Result<()> {
+    let mut header = chesapeake_header()?;
+    header
+        .detailed_parameters
+        .as_mut()
+        .unwrap()
+        .geodetic_datum_name = Some("TOKYO".to_owned());
+    assert!(matches!(
+        header.to_wkt2(),
+        Err(libbsb::Error::UnsupportedDatum(datum)) if datum == "TOKYO"
+    ));
+
+    let mut header = chesapeake_header()?;
+    header.detailed_parameters.as_mut().unwrap().projection_name = Some("GNOMONIC".to_owned());
+    assert!(matches!(
+        header.to_proj_string(),
+        Err(libbsb::Error::UnsupportedProjection(_))
+    ));
+    Ok(())
+}
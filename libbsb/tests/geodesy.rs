@@ -0,0 +1,119 @@
+use common::chesapeake_header;
+use libbsb::geodesy::{Datum, DatumShift, Ellipsoid, Helmert};
+
+mod common;
+
+/// Approximate distance in metres between two nearby points
+fn distance((lat1, lon1): (f64, f64), (lat2, lon2): (f64, f64)) -> f64 {
+    let metres_per_degree = 111_320.0;
+    ((lat2 - lat1) * metres_per_degree)
+        .hypot((lon2 - lon1) * metres_per_degree * lat1.to_radians().cos())
+}
+
+#[test]
+fn geocentric_coordinates() {
+    let wgs84 = Ellipsoid::WGS84;
+    let (x, y, z) = wgs84.to_geocentric((0.0, 0.0, 0.0));
+    assert!((x - 6_378_137.0).abs() < 1e-6 && y.abs() < 1e-6 && z.abs() < 1e-6);
+    let (x, _, z) = wgs84.to_geocentric((90.0, 0.0, 0.0));
+    assert!(x.abs() < 1e-6 && (z - wgs84.semi_minor_axis()).abs() < 1e-6);
+
+    for point in [
+        (37.0, -76.0, 0.0),
+        (-45.5, 170.25, 1200.0),
+        (89.9, 10.0, -30.0),
+    ] {
+        let (lat, lon, height) = wgs84.from_geocentric(wgs84.to_geocentric(point));
+        assert!((lat - point.0).abs() < 1e-10 && (lon - point.1).abs() < 1e-10);
+        assert!((height - point.2).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn helmert_transformations_round_trip() {
+    let helmert = Helmert {
+        translation: (-87.0, -98.0, -121.0),
+        rotation: (0.2, -0.3, 0.5),
+        scale: 1.2,
+    };
+    let point = (4_000_000.0, 300_000.0, 4_900_000.0);
+    let (x, y, z) = helmert.inverse().apply(helmert.apply(point));
+    assert!((x - point.0).abs() < 1e-3 && (y - point.1).abs() < 1e-3 && (z - point.2).abs() < 1e-3);
+}
+
+#[test]
+fn datum_transformations() {
+    let coords = (37.0, -76.0);
+    assert_eq!(Datum::Wgs84.transform_to_wgs84(coords), coords);
+    let nad83 = Datum::Nad83.transform_to_wgs84(coords);
+    assert!(distance(coords, nad83) < 1e-3);
+
+    // NAD27 and ED50 coordinates are tens of metres away from WGS84
+    for (datum, coords) in [(Datum::Nad27, coords), (Datum::Ed50, (48.6, -2.0))] {
+        let wgs84 = datum.transform_to_wgs84(coords);
+        let shift = distance(coords, wgs84);
+        assert!((10.0..250.0).contains(&shift), "{datum}: {shift}");
+        let back = datum.transform_from_wgs84(wgs84);
+        assert!(distance(coords, back) < 1e-3, "{datum}: {back:?}");
+    }
+}
+
+#[test]
+fn datum_shift_from_header() -> anyhow::Result<()> {
+    let mut header = chesapeake_header()?;
+    assert_eq!(header.datum_shift(), DatumShift::Offset(0.0, 0.0));
+
+    // without a DTM record, the built-in parameters of the datum are used
+    header.dtm = None;
+    header
+        .detailed_parameters
+        .as_mut()
+        .unwrap()
+        .geodetic_datum_name = Some("NAD27".to_owned());
+    assert_eq!(header.datum_shift(), DatumShift::Datum(Datum::Nad27));
+    assert_eq!(header.datum_or_wgs84(), Datum::Nad27);
+    let transform = header.geo_transform()?;
+    let pixel = (5000.0, 5000.0);
+    let (coords, wgs84) = (
+        transform.pixel_to_coords(pixel),
+        transform.pixel_to_wgs84(pixel),
+    );
+    assert_eq!(wgs84, Datum::Nad27.transform_to_wgs84(coords));
+    let ((x, y), expected) = (
+        transform.wgs84_to_pixel(wgs84),
+        transform.coords_to_pixel(coords),
+    );
+    assert!((x - expected.0).abs() < 1e-3 && (y - expected.1).abs() < 1e-3);
+
+    header
+        .detailed_parameters
+        .as_mut()
+        .unwrap()
+        .geodetic_datum_name = None;
+    assert_eq!(header.datum_shift(), DatumShift::default());
+    assert_eq!(header.datum_or_wgs84(), Datum::Wgs84);
+    Ok(())
+}
+
+#[test]
+fn geodesic_matches_vincenty_example() {
+    // Flinders Peak to Buninyong, from Vincenty's original paper
+    let dms = |d: f64, m: f64, s: f64| d.signum() * (d.abs() + m / 60.0 + s / 3600.0);
+    let flinders_peak = (dms(-37.0, 57.0, 3.72030), dms(144.0, 25.0, 29.52440));
+    let buninyong = (dms(-37.0, 39.0, 10.15610), dms(143.0, 55.0, 35.38390));
+    let geodesic = Ellipsoid::GRS80.geodesic(flinders_peak, buninyong);
+    assert!((geodesic.distance - 54_972.271).abs() < 1e-3);
+    assert!((geodesic.initial_bearing - dms(306.0, 52.0, 5.37)).abs() < 1e-5);
+    assert!((geodesic.final_bearing - dms(307.0, 10.0, 25.07)).abs() < 1e-5);
+
+    let back = Ellipsoid::GRS80.geodesic(buninyong, flinders_peak);
+    assert!((back.distance - geodesic.distance).abs() < 1e-6);
+    assert_eq!(
+        Ellipsoid::WGS84.geodesic(buninyong, buninyong).distance,
+        0.0
+    );
+    // a quarter of the equator
+    let quarter = Ellipsoid::WGS84.geodesic((0.0, 0.0), (0.0, 90.0));
+    assert!((quarter.distance - 10_018_754.171).abs() < 1e-3);
+    assert!((quarter.initial_bearing - 90.0).abs() < 1e-9);
+}
@@ -0,0 +1,955 @@
+use common::{chesapeake_header, read_header, SAINT_MALO_HEADER};
+use libbsb::{
+    geodesy::{Datum, Ellipsoid},
+    georef::{Border, PolynomialOrder, Reorientation, ResizeFilter, SplitLayout},
+    image::raw::header::{DetailedParameters, GeneralParameters, ImageHeader, Polynomial, Ref},
+    projection::{Projection, ProjectionMethod},
+    ColorPalette, Crs, Depth, GeoTransform, KapImageFile,
+};
+
+mod common;
+
+#[test]
+fn polynomials_map_ref_points() -> anyhow::Result<()> {
+    let header = chesapeake_header()?;
+    let transform = header.geo_transform()?;
+    for r in header.reference_point_record.as_ref().unwrap() {
+        let (x, y) = transform.coords_to_pixel(r.coords);
+        assert!((x - r.pixels.0 as f64).abs() < 1.0, "{r:?} -> {x}");
+        assert!((y - r.pixels.1 as f64).abs() < 1.0, "{r:?} -> {y}");
+
+        let (lat, lon) = transform.pixel_to_coords((r.pixels.0 as f64, r.pixels.1 as f64));
+        assert!((lat - r.coords.0).abs() < 1e-4, "{r:?} -> {lat}");
+        assert!((lon - r.coords.1).abs() < 1e-4, "{r:?} -> {lon}");
+    }
+    Ok(())
+}
+
+#[test]
+fn missing_polynomials_error() {
+    let header = ImageHeader::builder()
+        .ifm(Depth::Seven)
+        .general_parameters(
+            GeneralParameters::builder()
+                .image_width_height((10, 10))
+                .build(),
+        )
+        .build();
+    assert!(matches!(
+        header.geo_transform(),
+        Err(libbsb::Error::MissingGeoreference)
+    ));
+}
+
+#[test]
+fn phase_and_datum_shift() -> anyhow::Result<()> {
+    let mut header = chesapeake_header()?;
+    // a chart straddling the antimeridian uses a phase shift of 180 degrees
+    for poly in [&mut header.wpx, &mut header.pwx] {
+        let Polynomial { poly, .. } = poly.as_mut().unwrap();
+        *poly = [0.0, 1.0, 0.0, 0.0, 0.0, 0.0];
+    }
+    header.phase_shift = Some(180.0);
+    header.dtm = Some((3.6, -7.2));
+    let transform = header.geo_transform()?;
+
+    let (x, _) = transform.coords_to_pixel((37.0, -170.0));
+    assert!((x - 10.0).abs() < 1e-9);
+    let (x, _) = transform.coords_to_pixel((37.0, 170.0));
+    assert!((x + 10.0).abs() < 1e-9);
+    let (_, lon) = transform.pixel_to_coords((10.0, 0.0));
+    assert!((lon + 170.0).abs() < 1e-9);
+
+    let coords = transform.pixel_to_coords((10.0, 5000.0));
+    let wgs84 = transform.pixel_to_wgs84((10.0, 5000.0));
+    assert!((wgs84.0 - coords.0 - 0.001).abs() < 1e-9);
+    assert!((wgs84.1 - coords.1 + 0.002).abs() < 1e-9);
+    let (x, _) = transform.wgs84_to_pixel(wgs84);
+    assert!((x - 10.0).abs() < 1e-6);
+    Ok(())
+}
+
+#[test]
+fn phase_shift_round_trips() -> anyhow::Result<()> {
+    let mut header = chesapeake_header()?;
+    for phase_shift in [30.0, -45.0, 90.0, 180.0] {
+        header.phase_shift = Some(phase_shift);
+        let transform = header.geo_transform()?;
+        for pixel in [(0.0, 0.0), (5000.0, 5000.0), (11547.0, 9767.0)] {
+            let (x, y) = transform.coords_to_pixel(transform.pixel_to_coords(pixel));
+            assert!(
+                (x - pixel.0).abs() < 0.5 && (y - pixel.1).abs() < 0.5,
+                "{phase_shift}: {pixel:?} -> {:?}",
+                (x, y)
+            );
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn fitted_polynomials_match_header_polynomials() -> anyhow::Result<()> {
+    let original = chesapeake_header()?;
+    let mut header = chesapeake_header()?;
+    header.fit_polynomials(PolynomialOrder::Quadratic)?;
+    let (original, fitted) = (original.geo_transform()?, header.geo_transform()?);
+    for pixel in [(0.0, 0.0), (5000.0, 5000.0), (11547.0, 9767.0)] {
+        let (a, b) = (
+            original.pixel_to_coords(pixel),
+            fitted.pixel_to_coords(pixel),
+        );
+        assert!((a.0 - b.0).abs() < 1e-5 && (a.1 - b.1).abs() < 1e-5);
+        let (a, b) = (original.coords_to_pixel(a), fitted.coords_to_pixel(a));
+        assert!((a.0 - b.0).abs() < 0.5 && (a.1 - b.1).abs() < 0.5);
+    }
+    Ok(())
+}
+
+#[test]
+fn transform_is_fitted_when_polynomials_are_missing() -> anyhow::Result<()> {
+    let mut header = chesapeake_header()?;
+    header.wpx = None;
+    // without a projection, the polynomials are fitted to the reference points
+    header.detailed_parameters.as_mut().unwrap().projection_name = None;
+    let transform = header.geo_transform()?;
+    assert!(transform.projection().is_none());
+    for r in header.reference_point_record.as_ref().unwrap() {
+        let (x, y) = transform.coords_to_pixel(r.coords);
+        assert!((x - r.pixels.0 as f64).abs() < 1.0);
+        assert!((y - r.pixels.1 as f64).abs() < 1.0);
+    }
+    Ok(())
+}
+
+#[test]
+fn linear_fit_across_antimeridian() -> anyhow::Result<()> {
+    let refs = [
+        ((0, 0), (10.0, 179.0)),
+        ((200, 0), (10.0, -179.0)),
+        ((0, 100), (9.0, 179.0)),
+    ]
+    .map(|(pixels, coords)| Ref::builder().pixels(pixels).coords(coords).build());
+    assert!(matches!(
+        GeoTransform::fit(&refs, PolynomialOrder::Quadratic),
+        Err(libbsb::Error::InsufficientReferencePoints {
+            required: 6,
+            found: 3
+        })
+    ));
+
+    let transform = GeoTransform::fit(&refs, PolynomialOrder::Linear)?;
+    assert_eq!(transform.phase_shift(), 180.0);
+    let (x, y) = transform.coords_to_pixel((9.5, 180.0));
+    assert!((x - 100.0).abs() < 1e-6 && (y - 50.0).abs() < 1e-6);
+    let (lat, lon) = transform.pixel_to_coords((150.0, 0.0));
+    assert!((lat - 10.0).abs() < 1e-9 && (lon + 179.5).abs() < 1e-9);
+    Ok(())
+}
+
+#[test]
+fn fitted_polynomials_survive_serialization() -> anyhow::Result<()> {
+    let refs = [
+        ((0, 0), (10.0, 20.0)),
+        ((40, 0), (10.0, 21.0)),
+        ((0, 30), (9.0, 20.0)),
+    ]
+    .map(|(pixels, coords)| Ref::builder().pixels(pixels).coords(coords).build());
+    let mut header = ImageHeader::builder()
+        .ifm(Depth::Seven)
+        .general_parameters(
+            GeneralParameters::builder()
+                .image_width_height((40, 30))
+                .build(),
+        )
+        .reference_point_record(refs.to_vec())
+        .build();
+    header.fit_polynomials(PolynomialOrder::Linear)?;
+
+    let parsed: ImageHeader = header.into_header_format().parse()?;
+    for (a, b) in [
+        (&header.wpx, &parsed.wpx),
+        (&header.wpy, &parsed.wpy),
+        (&header.pwx, &parsed.pwx),
+        (&header.pwy, &parsed.pwy),
+    ] {
+        let (a, b) = (a.as_ref().unwrap(), b.as_ref().unwrap());
+        assert_eq!(a.corner, b.corner);
+        for (a, b) in a.poly.iter().zip(&b.poly) {
+            assert!((a - b).abs() <= 1e-6 * a.abs().max(1.0), "{a} != {b}");
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn computed_residuals_match_err_records() -> anyhow::Result<()> {
+    let header = chesapeake_header()?;
+    let errs = header.err.clone().unwrap();
+    let residuals = header.residuals()?;
+    assert_eq!(errs.len(), residuals.len());
+    for (err, residual) in errs.iter().zip(&residuals) {
+        let computed = residual.as_err_record();
+        assert!((err[0] - computed[0]).abs() < 1e-3, "{err:?} {computed:?}");
+        assert!((err[1] - computed[1]).abs() < 1e-3, "{err:?} {computed:?}");
+        assert!((err[2] - computed[2]).abs() < 1e-7, "{err:?} {computed:?}");
+        assert!((err[3] - computed[3]).abs() < 1e-7, "{err:?} {computed:?}");
+    }
+
+    let mut regenerated = chesapeake_header()?;
+    regenerated.err = None;
+    regenerated.update_err()?;
+    assert_eq!(regenerated.err.map(|e| e.len()), Some(errs.len()));
+    Ok(())
+}
+
+#[test]
+fn residual_report_flags_bad_reference_points() -> anyhow::Result<()> {
+    let mut header = chesapeake_header()?;
+    let report = header.residual_report(1.0)?;
+    assert!(report.is_ok());
+    assert!(report.rms_pixels() < 0.5);
+
+    // move a reference point by 20 pixels without refitting the polynomials
+    header.reference_point_record.as_mut().unwrap()[4].pixels.0 += 20;
+    let report = header.residual_report(1.0)?;
+    assert_eq!(report.flagged, vec![4]);
+    assert!(report.max_pixels() > 19.0);
+    Ok(())
+}
+
+#[test]
+fn border_contains_and_masks_pixels() {
+    // a diamond inscribed in a 10x10 image
+    let border = Border::new(vec![(5.0, 0.0), (10.0, 5.0), (5.0, 10.0), (0.0, 5.0)]);
+    assert!(border.contains((5.0, 5.0)));
+    assert!(!border.contains((1.0, 1.0)));
+    assert!(border.contains_pixel((4, 4)));
+    assert!(!border.contains_pixel((9, 9)));
+    assert_eq!(border.bounds(), ((0.0, 0.0), (10.0, 10.0)));
+
+    let mask = border.mask(10, 10);
+    for y in 0..10u16 {
+        for x in 0..10u16 {
+            assert_eq!(
+                mask[usize::from(y) * 10 + usize::from(x)],
+                border.contains_pixel((x, y)),
+                "({x}, {y})"
+            );
+        }
+    }
+    assert_eq!(mask.iter().filter(|&&inside| inside).count(), 50);
+}
+
+#[test]
+fn ply_border_of_chart() -> anyhow::Result<()> {
+    let header = chesapeake_header()?;
+    let border = header.border()?.unwrap();
+    assert_eq!(border.vertices().len(), header.ply.as_ref().unwrap().len());
+    let ((min_x, min_y), (max_x, max_y)) = border.bounds();
+    assert!((min_x - 374.0).abs() < 1.0 && (max_x - 11118.0).abs() < 1.0);
+    assert!((min_y - 490.0).abs() < 1.0 && (max_y - 8836.0).abs() < 1.0);
+
+    assert!(header.contains_pixel((5000, 5000))?);
+    assert!(!header.contains_pixel((100, 100))?);
+    assert!(!header.contains_pixel((20000, 100))?);
+    assert!(header.contains_coords((37.0, -76.0))?);
+    assert!(!header.contains_coords((37.5, -76.0))?);
+    assert!(!header.contains_coords((37.0, -70.0))?);
+    Ok(())
+}
+
+#[test]
+fn rgba_palette_is_transparent_outside_ply() -> anyhow::Result<()> {
+    let refs = [
+        ((0, 0), (10.0, 20.0)),
+        ((10, 0), (10.0, 21.0)),
+        ((0, 10), (9.0, 20.0)),
+    ]
+    .map(|(pixels, coords)| Ref::builder().pixels(pixels).coords(coords).build());
+    let header = ImageHeader::builder()
+        .ifm(Depth::Four)
+        .general_parameters(
+            GeneralParameters::builder()
+                .image_width_height((10, 10))
+                .build(),
+        )
+        .rgb(vec![(10, 20, 30)])
+        .reference_point_record(refs.to_vec())
+        // the left half of the image
+        .ply(vec![(10.0, 20.0), (10.0, 20.5), (9.0, 20.5), (9.0, 20.0)])
+        .build();
+    let bsb = KapImageFile::new(header, vec![1; 100])?;
+
+    let mask = bsb.border_mask()?;
+    let rgba: Vec<_> = bsb.as_rgba_palette_iter(ColorPalette::Rgb)?.collect();
+    assert_eq!(rgba.len(), 100);
+    for (i, (color, inside)) in rgba.iter().zip(&mask).enumerate() {
+        assert_eq!(*inside, i % 10 < 5, "{i}");
+        let expected = if *inside { [10, 20, 30, 255] } else { [0; 4] };
+        assert_eq!(*color, expected);
+    }
+    assert!(matches!(
+        bsb.as_rgba_palette_iter(ColorPalette::Ngt),
+        Err(libbsb::Error::NonExistentPalette)
+    ));
+    Ok(())
+}
+
+#[test]
+fn affine_transform_of_projected_chart() -> anyhow::Result<()> {
+    let header = read_header(SAINT_MALO_HEADER)?;
+    let transform = header.geo_transform()?;
+    let affine = transform.affine().expect("projected transforms are affine");
+    for pixel in [(0.0, 0.0), (4096.0, 0.0), (1234.5, 3210.0)] {
+        let (easting, northing) = transform.pixel_to_model(pixel);
+        let (u, v) = affine.apply(pixel);
+        assert!((u - easting).abs() < 1e-6 && (v - northing).abs() < 1e-6);
+        let (x, y) = transform.model_to_pixel((u, v));
+        assert!((x - pixel.0).abs() < 1e-6 && (y - pixel.1).abs() < 1e-6);
+    }
+    let approximation = transform.affine_approximation(4096, 4096)?;
+    assert_eq!(approximation.affine, affine);
+    assert!(approximation.max_error < 1e-6);
+    Ok(())
+}
+
+#[test]
+fn affine_approximation_of_quadratic_polynomials() -> anyhow::Result<()> {
+    let header = chesapeake_header()?;
+    let transform = header.geo_transform()?;
+    assert!(transform.affine().is_none());
+    let approximation = transform.affine_approximation(11547, 9767)?;
+    // the quadratic terms are small, but not negligible over the whole chart
+    assert!(
+        (0.1..20.0).contains(&approximation.max_error),
+        "{}",
+        approximation.max_error
+    );
+    let (lon, lat) = approximation.affine.apply((5000.0, 5000.0));
+    let (expected_lat, expected_lon) = transform.pixel_to_coords((5000.0, 5000.0));
+    assert!((lon - expected_lon).abs() < 1e-3 && (lat - expected_lat).abs() < 1e-3);
+    Ok(())
+}
+
+/// A 400x300 Transverse Mercator chart with 10 m pixels, holding a checkerboard of 20 pixel
+/// squares
+fn transverse_mercator_chart() -> anyhow::Result<KapImageFile> {
+    let projection = Projection::new(
+        ProjectionMethod::TransverseMercator {
+            central_meridian: -3.0,
+            latitude_of_origin: 0.0,
+            scale_factor: 0.9996,
+        },
+        Ellipsoid::WGS84,
+    );
+    let (easting, northing) = projection.forward((48.7, -2.2));
+    let refs = [(0, 0), (400, 0), (0, 300), (400, 300)].map(|(x, y)| {
+        let coords = projection.inverse((easting + x as f64 * 10.0, northing - y as f64 * 10.0));
+        Ref::builder().pixels((x, y)).coords(coords).build()
+    });
+    let mut header = ImageHeader::builder()
+        .ifm(Depth::Four)
+        .general_parameters(
+            GeneralParameters::builder()
+                .image_width_height((400, 300))
+                .build(),
+        )
+        .detailed_parameters(
+            DetailedParameters::builder()
+                .geodetic_datum_name("WGS84".to_owned())
+                .build(),
+        )
+        .rgb(vec![(0, 0, 0), (255, 255, 255)])
+        .reference_point_record(refs.to_vec())
+        .build();
+    header.set_projection(&projection);
+    let raster = (0..300)
+        .flat_map(|y| (0..400).map(move |x| 1 + u8::from((x / 20 + y / 20) % 2 == 1)))
+        .collect();
+    Ok(KapImageFile::new(header, raster)?)
+}
+
+#[test]
+fn reprojected_chart_samples_original_pixels() -> anyhow::Result<()> {
+    let chart = transverse_mercator_chart()?;
+    let mercator = Projection::new(
+        ProjectionMethod::Mercator {
+            central_meridian: 0.0,
+            latitude_of_true_scale: 48.0,
+        },
+        Ellipsoid::WGS84,
+    );
+    let reprojected = chart.reproject(&Crs::projected(Datum::Wgs84, mercator))?;
+    let header = reprojected.header();
+    assert_eq!(header.projection()?, mercator);
+    assert_eq!(header.datum()?, Datum::Wgs84);
+    assert_eq!(header.reference_point_record.as_ref().unwrap().len(), 9);
+    assert!(header.wpx.is_none());
+    // the chart is slightly rotated in Mercator, so the reprojected image is larger
+    assert!(reprojected.width() > 400 && reprojected.height() > 300);
+
+    let (original, transform) = (chart.geo_transform()?, reprojected.geo_transform()?);
+    let mut compared = 0;
+    for y in (0..reprojected.height()).step_by(7) {
+        for x in (0..reprojected.width()).step_by(7) {
+            let coords = transform.pixel_to_coords((f64::from(x) + 0.5, f64::from(y) + 0.5));
+            let (sx, sy) = original.coords_to_pixel(coords);
+            // skip pixels outside of the chart or close to the edges of the squares
+            if !(0.0..400.0).contains(&sx)
+                || !(0.0..300.0).contains(&sy)
+                || !(2.0..18.0).contains(&(sx % 20.0))
+                || !(2.0..18.0).contains(&(sy % 20.0))
+            {
+                continue;
+            }
+            let index = |bsb: &KapImageFile, x: usize, y: usize| {
+                bsb.pixel_indices()[y * usize::from(bsb.width()) + x]
+            };
+            assert_eq!(
+                index(&reprojected, usize::from(x), usize::from(y)),
+                index(&chart, sx as usize, sy as usize),
+                "({x}, {y}) -> ({sx}, {sy})"
+            );
+            compared += 1;
+        }
+    }
+    assert!(compared > 1000, "{compared}");
+
+    // the border is the outline of the original image
+    let border = header.border()?.unwrap();
+    let corner = transform.coords_to_pixel(original.pixel_to_coords((0.0, 0.0)));
+    assert!(border.contains((corner.0 + 2.0, corner.1 + 2.0)));
+    assert!(!border.contains((1.0, 1.0)));
+    Ok(())
+}
+
+#[test]
+fn reprojection_requires_a_projection() -> anyhow::Result<()> {
+    let chart = transverse_mercator_chart()?;
+    assert!(matches!(
+        chart.reproject(&Crs::geographic(Datum::Wgs84)),
+        Err(libbsb::Error::MissingProjectionParameter("PR"))
+    ));
+    Ok(())
+}
+
+fn assert_same_position(
+    cropped: &GeoTransform,
+    original: &GeoTransform,
+    pixel: (f64, f64),
+    offset: (f64, f64),
+) {
+    let (lat, lon) = cropped.pixel_to_coords(pixel);
+    let expected = original.pixel_to_coords((pixel.0 + offset.0, pixel.1 + offset.1));
+    assert!(
+        (lat - expected.0).abs() < 1e-8 && (lon - expected.1).abs() < 1e-8,
+        "{pixel:?}: {:?} != {expected:?}",
+        (lat, lon)
+    );
+}
+
+#[test]
+fn cropped_chart_keeps_georeferencing() -> anyhow::Result<()> {
+    let chart = transverse_mercator_chart()?;
+    let cropped = chart.crop((100, 50), (200, 150))?;
+    assert_eq!((cropped.width(), cropped.height()), (200, 150));
+    for (y, row) in cropped.pixel_indices().chunks(200).enumerate() {
+        let start = (y + 50) * 400 + 100;
+        assert_eq!(row, &chart.pixel_indices()[start..start + 200]);
+    }
+    // all the original reference points are outside of the window
+    let refs = cropped.header().reference_point_record.as_ref().unwrap();
+    assert_eq!(refs.len(), 4);
+    assert_eq!(refs[2].pixels, (200, 150));
+
+    let (original, transform) = (chart.geo_transform()?, cropped.geo_transform()?);
+    for pixel in [(0.0, 0.0), (200.0, 150.0), (12.5, 140.0)] {
+        assert_same_position(&transform, &original, pixel, (100.0, 50.0));
+    }
+
+    assert!(matches!(
+        chart.crop((300, 0), (101, 10)),
+        Err(libbsb::Error::InvalidWindow { .. })
+    ));
+    assert!(matches!(
+        chart.crop((0, 0), (0, 10)),
+        Err(libbsb::Error::InvalidWindow { .. })
+    ));
+    Ok(())
+}
+
+#[test]
+fn cropped_polynomials_and_border() -> anyhow::Result<()> {
+    let chart = transverse_mercator_chart()?;
+    let original = chart.geo_transform()?;
+    let mut header = chart.header().clone();
+    let pixels = [0, 200, 400]
+        .into_iter()
+        .flat_map(|x| [0, 150, 300].map(|y| (x, y)));
+    header.reference_point_record = Some(
+        pixels
+            .map(|(x, y)| {
+                let coords = original.pixel_to_coords((x as f64, y as f64));
+                Ref::builder().pixels((x, y)).coords(coords).build()
+            })
+            .collect(),
+    );
+    header.fit_polynomials(PolynomialOrder::Quadratic)?;
+    header.update_err()?;
+    // a border around the centre of the chart
+    header.ply = Some(
+        [
+            (150.0, 100.0),
+            (250.0, 100.0),
+            (250.0, 200.0),
+            (150.0, 200.0),
+        ]
+        .map(|pixel| original.pixel_to_coords(pixel))
+        .to_vec(),
+    );
+    let chart = KapImageFile::new(header, chart.pixel_indices().to_vec())?;
+    let original = chart.geo_transform()?;
+
+    let cropped = chart.crop((180, 120), (100, 60))?;
+    let header = cropped.header();
+    let transform = cropped.geo_transform()?;
+    // the reference point at the centre of the chart is kept
+    let refs = header.reference_point_record.as_ref().unwrap();
+    assert_eq!(refs.len(), 1 + 4);
+    assert_eq!(refs[0].pixels, (20, 30));
+    assert_eq!(header.err.as_ref().unwrap().len(), refs.len());
+    for pixel in [(0.0, 0.0), (100.0, 60.0), (33.0, 7.5)] {
+        assert_same_position(&transform, &original, pixel, (180.0, 120.0));
+        let (x, y) = transform.coords_to_pixel(transform.pixel_to_coords(pixel));
+        assert!((x - pixel.0).abs() < 1e-3 && (y - pixel.1).abs() < 1e-3);
+    }
+
+    // the border is clipped to the window, except on the right where it ends inside of it
+    let border = header.border()?.unwrap();
+    let ((min_x, min_y), (max_x, max_y)) = border.bounds();
+    assert!(min_x.abs() < 1e-3 && min_y.abs() < 1e-3);
+    assert!((max_x - 70.0).abs() < 1e-3 && (max_y - 60.0).abs() < 1e-3);
+    assert!(border.contains((69.0, 59.0)));
+    assert!(!border.contains((71.0, 30.0)));
+    Ok(())
+}
+
+#[test]
+fn crop_to_coords_covers_bounding_box() -> anyhow::Result<()> {
+    let chart = transverse_mercator_chart()?;
+    let original = chart.geo_transform()?;
+    let (north_west, south_east) = (
+        original.pixel_to_coords((120.0, 60.0)),
+        original.pixel_to_coords((250.0, 180.0)),
+    );
+    let cropped =
+        chart.crop_to_coords((south_east.0, north_west.1), (north_west.0, south_east.1))?;
+    // the chart is slightly rotated, so the window is a little larger than the box
+    assert!((130..140).contains(&cropped.width()), "{}", cropped.width());
+    assert!(
+        (120..130).contains(&cropped.height()),
+        "{}",
+        cropped.height()
+    );
+    let transform = cropped.geo_transform()?;
+    for coords in [north_west, south_east] {
+        let (x, y) = transform.coords_to_pixel(coords);
+        assert!((0.0..=f64::from(cropped.width())).contains(&x));
+        assert!((0.0..=f64::from(cropped.height())).contains(&y));
+    }
+    assert!(matches!(
+        chart.crop_to_coords((10.0, 10.0), (11.0, 11.0)),
+        Err(libbsb::Error::InvalidWindow { .. })
+    ));
+    Ok(())
+}
+
+#[test]
+fn resized_chart_keeps_georeferencing() -> anyhow::Result<()> {
+    let chart = transverse_mercator_chart()?;
+    let mut header = chart.header().clone();
+    header.general_parameters.drawing_units = Some(100);
+    let knp = header.detailed_parameters.as_mut().unwrap();
+    (knp.x_resolution, knp.y_resolution) = (Some(10.0), Some(10.0));
+    let chart = KapImageFile::new(header, chart.pixel_indices().to_vec())?;
+
+    let resized = chart.resize((200, 150), ResizeFilter::Nearest)?;
+    assert_eq!((resized.width(), resized.height()), (200, 150));
+    for (y, row) in resized.pixel_indices().chunks(200).enumerate() {
+        for (x, &index) in row.iter().enumerate() {
+            assert_eq!(index, chart.pixel_indices()[(2 * y + 1) * 400 + 2 * x + 1]);
+        }
+    }
+    let header = resized.header();
+    assert_eq!(header.general_parameters.drawing_units, Some(50));
+    let knp = header.detailed_parameters.as_ref().unwrap();
+    assert_eq!(
+        (knp.x_resolution, knp.y_resolution),
+        (Some(20.0), Some(20.0))
+    );
+    let refs = header.reference_point_record.as_ref().unwrap();
+    assert_eq!(refs[3].pixels, (200, 150));
+
+    let (original, transform) = (chart.geo_transform()?, resized.geo_transform()?);
+    for pixel in [(0.0, 0.0), (200.0, 150.0), (12.5, 140.0)] {
+        let (lat, lon) = transform.pixel_to_coords(pixel);
+        let expected = original.pixel_to_coords((pixel.0 * 2.0, pixel.1 * 2.0));
+        assert!((lat - expected.0).abs() < 1e-8 && (lon - expected.1).abs() < 1e-8);
+    }
+    assert!(matches!(
+        chart.resize((0, 10), ResizeFilter::Mode),
+        Err(libbsb::Error::InvalidWindow { .. })
+    ));
+    Ok(())
+}
+
+#[test]
+fn mode_filter_keeps_thin_lines() -> anyhow::Result<()> {
+    let chart = transverse_mercator_chart()?;
+    let mut header = chart.header().clone();
+    header.rgb = Some(vec![(0, 0, 0), (255, 255, 255), (255, 0, 255)]);
+    // a 1 pixel wide vertical line on the checkerboard
+    let raster = chart
+        .pixel_indices()
+        .iter()
+        .enumerate()
+        .map(|(i, &index)| if i % 400 == 201 { 3 } else { index })
+        .collect();
+    let chart = KapImageFile::new(header, raster)?;
+
+    let column = |chart: &KapImageFile, x: usize| -> Vec<u8> {
+        (0..usize::from(chart.height()))
+            .map(|y| chart.pixel_indices()[y * usize::from(chart.width()) + x])
+            .collect()
+    };
+    let mode = chart.resize((100, 75), ResizeFilter::Mode)?;
+    assert!(column(&mode, 50).iter().all(|&index| index == 3));
+    // the other blocks are entirely within a square of the checkerboard
+    let expected: Vec<_> = column(&chart, 40).into_iter().step_by(4).collect();
+    assert_eq!(column(&mode, 10), expected);
+    let nearest = chart.resize((100, 75), ResizeFilter::Nearest)?;
+    assert!(!nearest.pixel_indices().contains(&3));
+    Ok(())
+}
+
+#[test]
+fn mode_filter_drops_isolated_pixels() -> anyhow::Result<()> {
+    let chart = transverse_mercator_chart()?;
+    let mut header = chart.header().clone();
+    header.rgb = Some(vec![(0, 0, 0), (255, 255, 255), (255, 0, 255)]);
+    let clean = KapImageFile::new(header.clone(), chart.pixel_indices().to_vec())?;
+    // stray pixels of a rare colour, one in every 10th 4x4 block along each axis
+    let raster = chart
+        .pixel_indices()
+        .iter()
+        .enumerate()
+        .map(|(i, &index)| {
+            if i % 400 % 40 == 13 && i / 400 % 40 == 14 {
+                3
+            } else {
+                index
+            }
+        })
+        .collect();
+    let noisy = KapImageFile::new(header, raster)?;
+
+    let mode = noisy.resize((100, 75), ResizeFilter::Mode)?;
+    assert!(!mode.pixel_indices().contains(&3));
+    assert_eq!(
+        mode.pixel_indices(),
+        clean.resize((100, 75), ResizeFilter::Mode)?.pixel_indices()
+    );
+    Ok(())
+}
+
+#[test]
+fn reoriented_chart_keeps_georeferencing() -> anyhow::Result<()> {
+    let chart = transverse_mercator_chart()?;
+    let original = chart.geo_transform()?;
+    let (w, h) = (400.0, 300.0);
+    type PixelMap = fn((f64, f64)) -> (f64, f64);
+    let cases: [(Reorientation, PixelMap, f32); 5] = [
+        (Reorientation::Rotate90, |(x, y)| (300.0 - y, x), 270.0),
+        (
+            Reorientation::Rotate180,
+            |(x, y)| (400.0 - x, 300.0 - y),
+            180.0,
+        ),
+        (Reorientation::Rotate270, |(x, y)| (y, 400.0 - x), 90.0),
+        (Reorientation::FlipHorizontal, |(x, y)| (400.0 - x, y), 0.0),
+        (Reorientation::FlipVertical, |(x, y)| (x, 300.0 - y), 180.0),
+    ];
+    for (reorientation, to_new, skew) in cases {
+        let reoriented = chart.reorient(reorientation)?;
+        let transform = reoriented.geo_transform()?;
+        let width = usize::from(reoriented.width());
+        for pixel in [(0.0, 0.0), (w, h), (12.5, 140.0), (215.5, 30.5)] {
+            let (lat, lon) = transform.pixel_to_coords(to_new(pixel));
+            let expected = original.pixel_to_coords(pixel);
+            assert!((lat - expected.0).abs() < 1e-8 && (lon - expected.1).abs() < 1e-8);
+        }
+        let (x, y) = to_new((215.5, 30.5));
+        assert_eq!(
+            reoriented.pixel_indices()[y as usize * width + x as usize],
+            chart.pixel_indices()[30 * 400 + 215],
+            "{reorientation:?}"
+        );
+        let knp = reoriented.header().detailed_parameters.as_ref().unwrap();
+        assert_eq!(knp.skew_angle, Some(skew));
+    }
+
+    let mut rotated = chart.reorient(Reorientation::Rotate90)?;
+    for _ in 0..3 {
+        rotated = rotated.reorient(Reorientation::Rotate90)?;
+    }
+    assert_eq!(rotated.pixel_indices(), chart.pixel_indices());
+    assert_eq!(
+        rotated.header().reference_point_record,
+        chart.header().reference_point_record
+    );
+    Ok(())
+}
+
+#[test]
+fn north_up_rotates_by_skew_angle() -> anyhow::Result<()> {
+    let chart = transverse_mercator_chart()?;
+    let original = chart.geo_transform()?;
+    let mut header = chart.header().clone();
+    header.detailed_parameters.as_mut().unwrap().skew_angle = Some(30.0);
+    let chart = KapImageFile::new(header, chart.pixel_indices().to_vec())?;
+
+    let rotated = chart.north_up()?;
+    // 400 * cos(30°) + 300 * sin(30°) by 400 * sin(30°) + 300 * cos(30°)
+    assert_eq!((rotated.width(), rotated.height()), (497, 460));
+    let header = rotated.header();
+    assert_eq!(
+        header.detailed_parameters.as_ref().unwrap().skew_angle,
+        Some(0.0)
+    );
+    assert_eq!(header.ply.as_ref().map(Vec::len), Some(4));
+
+    // the top left corner is rotated onto the top edge
+    let transform = rotated.geo_transform()?;
+    let (lat, lon) = transform.pixel_to_coords((150.0, 0.0));
+    let expected = original.pixel_to_coords((0.0, 0.0));
+    assert!((lat - expected.0).abs() < 1e-8 && (lon - expected.1).abs() < 1e-8);
+    let border = header.border()?.unwrap();
+    assert!(border.contains((248.5, 230.0)));
+    assert!(!border.contains((2.0, 2.0)));
+
+    let mut header = chart.header().clone();
+    header.detailed_parameters.as_mut().unwrap().skew_angle = Some(90.0);
+    let chart = KapImageFile::new(header, chart.pixel_indices().to_vec())?;
+    // the north points right, so the chart is rotated clockwise
+    let rotated = chart.north_up()?;
+    assert!(rotated.pixel_indices() == chart.reorient(Reorientation::Rotate90)?.pixel_indices());
+    Ok(())
+}
+
+#[test]
+fn mosaic_merges_adjacent_charts() -> anyhow::Result<()> {
+    let chart = transverse_mercator_chart()?;
+    let left = chart.crop((0, 0), (220, 300))?;
+    let right = chart.crop((180, 0), (220, 300))?;
+    // the same colours, in another order
+    let mut header = right.header().clone();
+    header.rgb = Some(vec![(255, 255, 255), (0, 0, 0)]);
+    let indices = right.pixel_indices().iter().map(|&i| 3 - i).collect();
+    let right = KapImageFile::new(header, indices)?;
+
+    let mosaic = KapImageFile::mosaic(&[left, right], &chart.header().crs()?)?;
+    assert_eq!((mosaic.width(), mosaic.height()), (400, 300));
+    let header = mosaic.header();
+    assert_eq!(header.rgb.as_ref().unwrap().len(), 2);
+    assert_eq!(header.reference_point_record.as_ref().unwrap().len(), 9);
+    assert_eq!(header.ply.as_ref().unwrap().len(), 4);
+    assert!(header.wpx.is_some() && header.err.is_some());
+
+    let (original, transform) = (chart.geo_transform()?, mosaic.geo_transform()?);
+    let colors: Vec<_> = mosaic.as_palette_iter(ColorPalette::Rgb)?.collect();
+    let expected: Vec<_> = chart.as_palette_iter(ColorPalette::Rgb)?.collect();
+    let mut mismatches = 0;
+    for y in (0..300u16).step_by(3) {
+        for x in (0..400u16).step_by(3) {
+            let coords = transform.pixel_to_coords((f64::from(x) + 0.5, f64::from(y) + 0.5));
+            let (sx, sy) = original.coords_to_pixel(coords);
+            let pixel = sy as usize * 400 + sx as usize;
+            if colors[usize::from(y) * 400 + usize::from(x)] != expected[pixel] {
+                mismatches += 1;
+            }
+        }
+    }
+    // only pixels on the edges of the squares may differ
+    assert!(mismatches < 100, "{mismatches}");
+    Ok(())
+}
+
+#[test]
+fn mosaic_reduces_merged_palette() -> anyhow::Result<()> {
+    let chart = transverse_mercator_chart()?;
+    let gradient = |chart: KapImageFile, color: fn(u8) -> (u8, u8, u8)| {
+        let mut header = chart.header().clone();
+        header.rgb = Some((0..100).map(color).collect());
+        header.ifm = Depth::Seven;
+        let width = usize::from(chart.width());
+        let indices = (0..chart.pixel_indices().len())
+            .map(|i| 1 + (i % width % 100) as u8)
+            .collect();
+        KapImageFile::new(header, indices)
+    };
+    let left = gradient(chart.crop((0, 0), (200, 300))?, |i| (2 * i, 2 * i, 2 * i))?;
+    let right = gradient(chart.crop((200, 0), (200, 300))?, |i| (2 * i, 0, 0))?;
+
+    let mosaic = KapImageFile::mosaic(&[left, right], &chart.header().crs()?)?;
+    let header = mosaic.header();
+    assert_eq!(header.rgb.as_ref().unwrap().len(), 127);
+    assert_eq!(header.ifm, Depth::Seven);
+    assert!(mosaic
+        .pixel_indices()
+        .iter()
+        .all(|&i| (1..=127).contains(&i)));
+    // the merged colours stay close to the original ones
+    let colors: Vec<_> = mosaic.as_palette_iter(ColorPalette::Rgb)?.collect();
+    for (x, expected) in [(10, [20, 20, 20]), (250, [100, 0, 0]), (399, [198, 0, 0])] {
+        let color = colors[150 * 400 + x];
+        assert!(
+            (0..3).all(|i| color[i].abs_diff(expected[i]) <= 4),
+            "{color:?} != {expected:?}"
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn mosaic_border_follows_covered_pixels() -> anyhow::Result<()> {
+    let chart = transverse_mercator_chart()?;
+    let charts = [
+        chart.crop((0, 0), (200, 150))?,
+        chart.crop((200, 0), (200, 150))?,
+        chart.crop((0, 150), (200, 150))?,
+    ];
+
+    let mosaic = KapImageFile::mosaic(&charts, &chart.header().crs()?)?;
+    assert_eq!((mosaic.width(), mosaic.height()), (400, 300));
+    // an L shape, without the bottom right quarter
+    assert_eq!(mosaic.header().ply.as_ref().unwrap().len(), 6);
+    let mask = mosaic.border_mask()?;
+    assert!(mask[75 * 400 + 100] && mask[75 * 400 + 300] && mask[225 * 400 + 100]);
+    assert!(!mask[225 * 400 + 300]);
+    Ok(())
+}
+
+#[test]
+fn mosaic_rejects_disjoint_charts() -> anyhow::Result<()> {
+    let chart = transverse_mercator_chart()?;
+    let charts = [
+        chart.crop((0, 0), (150, 300))?,
+        chart.crop((250, 0), (150, 300))?,
+    ];
+
+    let result = KapImageFile::mosaic(&charts, &chart.header().crs()?);
+    assert!(matches!(result, Err(libbsb::Error::DisjointCoverage)));
+    Ok(())
+}
+
+#[test]
+fn split_chart_into_sub_charts() -> anyhow::Result<()> {
+    let chart = transverse_mercator_chart()?;
+    let mut header = chart.header().clone();
+    header.general_parameters.chart_name = Some("Approach".to_owned());
+    header.general_parameters.chart_number = Some("1234".to_owned());
+    // an unused color
+    header.rgb = Some(vec![(0, 0, 0), (255, 0, 0), (255, 255, 255)]);
+    let indices = chart.pixel_indices().iter().map(|&i| 2 * i - 1).collect();
+    let chart = KapImageFile::new(header, indices)?;
+
+    let charts = chart.split(SplitLayout::Grid {
+        columns: 2,
+        rows: 2,
+    })?;
+    assert_eq!(charts.len(), 4);
+    let original = chart.geo_transform()?;
+    for (i, (sub_chart, offset)) in charts
+        .iter()
+        .zip([(0.0, 0.0), (200.0, 0.0), (0.0, 150.0), (200.0, 150.0)])
+        .enumerate()
+    {
+        assert_eq!((sub_chart.width(), sub_chart.height()), (200, 150));
+        let parameters = &sub_chart.header().general_parameters;
+        assert_eq!(
+            parameters.chart_name.as_deref(),
+            Some(format!("Approach {}/4", i + 1).as_str())
+        );
+        assert_eq!(
+            parameters.chart_number.as_deref(),
+            Some(format!("1234-{}", i + 1).as_str())
+        );
+        assert_eq!(
+            sub_chart.header().rgb,
+            Some(vec![(0, 0, 0), (255, 255, 255)])
+        );
+        let transform = sub_chart.geo_transform()?;
+        for pixel in [(0.0, 0.0), (200.0, 150.0), (12.5, 140.0)] {
+            assert_same_position(&transform, &original, pixel, offset);
+        }
+    }
+    let colors: Vec<_> = charts[3].as_palette_iter(ColorPalette::Rgb)?.collect();
+    let expected: Vec<_> = chart.as_palette_iter(ColorPalette::Rgb)?.collect();
+    assert_eq!(colors[10 * 200 + 30], expected[160 * 400 + 230]);
+
+    let charts = chart.split(SplitLayout::MaxSize(150))?;
+    let sizes: Vec<_> = charts.iter().map(|c| (c.width(), c.height())).collect();
+    assert_eq!(sizes[..3], [(133, 150), (133, 150), (134, 150)]);
+    assert_eq!(sizes.len(), 6);
+
+    for layout in [
+        SplitLayout::MaxSize(0),
+        SplitLayout::Grid {
+            columns: 0,
+            rows: 2,
+        },
+        SplitLayout::Grid {
+            columns: 401,
+            rows: 1,
+        },
+    ] {
+        assert!(
+            matches!(
+                chart.split(layout),
+                Err(libbsb::Error::InvalidWindow { .. })
+            ),
+            "{layout:?}"
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn distances_and_bearings_on_chart() -> anyhow::Result<()> {
+    let chart = transverse_mercator_chart()?;
+    // the pixels are 10 m wide in Transverse Mercator, slightly enlarged off the central meridian
+    let (width, height) = chart.pixel_size_at((200.0, 150.0))?;
+    assert!((9.99..10.01).contains(&width), "{width}");
+    assert!((9.99..10.01).contains(&height), "{height}");
+
+    let leg = chart.geodesic_between_pixels((0.0, 150.0), (400.0, 150.0))?;
+    assert!((leg.distance - 4000.0).abs() < 2.0, "{}", leg.distance);
+    // east of the central meridian, the grid east is rotated clockwise by the convergence,
+    // about 0.6° here
+    assert!((90.5..90.7).contains(&leg.initial_bearing), "{leg:?}");
+
+    let coords = chart.geo_transform()?.pixel_to_coords((400.0, 150.0));
+    let to_coords = chart.geodesic_to_coords((0.0, 150.0), coords)?;
+    assert!((to_coords.distance - leg.distance).abs() < 1e-9);
+
+    // charts with an unknown datum are measured on the WGS84 ellipsoid
+    let mut header = chart.header().clone();
+    if let Some(parameters) = header.detailed_parameters.as_mut() {
+        parameters.geodetic_datum_name = Some("Unknown".to_owned());
+    }
+    let unknown = KapImageFile::new(header, chart.pixel_indices().to_vec())?;
+    let leg = unknown.geodesic_between_pixels((0.0, 150.0), (400.0, 150.0))?;
+    assert!((leg.distance - 4000.0).abs() < 2.0, "{}", leg.distance);
+    assert!(unknown.pixel_size_at((200.0, 150.0)).is_ok());
+    Ok(())
+}
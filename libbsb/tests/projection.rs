@@ -0,0 +1,229 @@
+use common::{chesapeake_header, read_header, SAINT_MALO_HEADER};
+use libbsb::{
+    geodesy::{Datum, Ellipsoid},
+    image::raw::header::{AdditionalParameters, ImageHeader},
+    projection::{Projection, ProjectionMethod},
+};
+
+mod common;
+
+fn assert_round_trip(projection: &Projection, points: &[(f64, f64)]) {
+    for &point in points {
+        let projected = projection.forward(point);
+        let (lat, lon) = projection.inverse(projected);
+        assert!(
+            (lat - point.0).abs() < 1e-9 && (lon - point.1).abs() < 1e-9,
+            "{:?}: {point:?} -> {projected:?} -> {:?}",
+            projection.method,
+            (lat, lon)
+        );
+    }
+}
+
+#[test]
+fn mercator_matches_known_values() {
+    let projection = Projection::new(
+        ProjectionMethod::Mercator {
+            central_meridian: 0.0,
+            latitude_of_true_scale: 0.0,
+        },
+        Ellipsoid::WGS84,
+    );
+    // EPSG:3395 (World Mercator)
+    let (x, y) = projection.forward((45.0, 10.0));
+    assert!((x - 1_113_194.907_9).abs() < 1e-3, "{x}");
+    assert!((y - 5_591_295.918_5).abs() < 1e-3, "{y}");
+}
+
+#[test]
+fn transverse_mercator_follows_the_meridian_arc() {
+    let projection = Projection {
+        false_easting: 500_000.0,
+        ..Projection::new(
+            ProjectionMethod::TransverseMercator {
+                central_meridian: -75.0,
+                latitude_of_origin: 0.0,
+                scale_factor: 0.9996,
+            },
+            Ellipsoid::WGS84,
+        )
+    };
+    let (x, y) = projection.forward((45.0, -75.0));
+    assert!((x - 500_000.0).abs() < 1e-6, "{x}");
+    // the meridian arc from the equator to 45°N is 4 984 944.378 m on WGS84
+    assert!((y - 0.9996 * 4_984_944.378).abs() < 1e-2, "{y}");
+}
+
+#[test]
+fn projections_round_trip() {
+    let points = [
+        (36.8, -76.4),
+        (37.4, -75.5),
+        (40.0, -79.0),
+        (32.0, -72.0),
+        (-10.0, -75.0),
+    ];
+    let methods = [
+        ProjectionMethod::Mercator {
+            central_meridian: 0.0,
+            latitude_of_true_scale: 37.083,
+        },
+        ProjectionMethod::TransverseMercator {
+            central_meridian: -75.0,
+            latitude_of_origin: 30.0,
+            scale_factor: 0.9996,
+        },
+        ProjectionMethod::LambertConformalConic {
+            central_meridian: -76.0,
+            latitude_of_origin: 35.0,
+            standard_parallels: (33.0, 45.0),
+        },
+        ProjectionMethod::LambertConformalConic {
+            central_meridian: -76.0,
+            latitude_of_origin: 37.0,
+            standard_parallels: (37.0, 37.0),
+        },
+        ProjectionMethod::Polyconic {
+            central_meridian: -76.0,
+            latitude_of_origin: 36.0,
+        },
+    ];
+    for method in methods {
+        for ellipsoid in [
+            Ellipsoid::GRS80,
+            Ellipsoid::CLARKE_1866,
+            Ellipsoid::sphere(6.371e6),
+        ] {
+            let projection = Projection {
+                false_easting: 1000.0,
+                false_northing: -2000.0,
+                ..Projection::new(method, ellipsoid)
+            };
+            assert_round_trip(&projection, &points);
+        }
+    }
+}
+
+#[test]
+fn projection_from_header() -> anyhow::Result<()> {
+    let header = chesapeake_header()?;
+    assert_eq!(header.datum()?, Datum::Nad83);
+    let projection = header.projection()?;
+    assert_eq!(projection.ellipsoid, Ellipsoid::GRS80);
+    let ProjectionMethod::Mercator {
+        latitude_of_true_scale,
+        ..
+    } = projection.method
+    else {
+        panic!("expected Mercator, got {:?}", projection.method);
+    };
+    assert!((latitude_of_true_scale - 37.083).abs() < 1e-5);
+
+    let mut header = header;
+    header.detailed_parameters.as_mut().unwrap().projection_name = Some("GNOMONIC".to_owned());
+    assert!(matches!(
+        header.projection(),
+        Err(libbsb::Error::UnsupportedProjection(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn charts_without_polynomials_use_their_projection() -> anyhow::Result<()> {
+    let header = read_header(SAINT_MALO_HEADER)?;
+    assert!(header.wpx.is_none());
+    let transform = header.geo_transform()?;
+    assert!(transform.projection().is_some());
+    for r in header.reference_point_record.as_ref().unwrap() {
+        let (x, y) = transform.coords_to_pixel(r.coords);
+        assert!((x - r.pixels.0 as f64).abs() < 1e-3, "{r:?} -> {x}");
+        assert!((y - r.pixels.1 as f64).abs() < 1e-3, "{r:?} -> {y}");
+    }
+    // the latitude of the middle row differs from the mean latitude on a Mercator chart
+    let (lat, lon) = transform.pixel_to_coords((2048.0, 2048.0));
+    assert!((lon - f64::midpoint(-2.065430, -1.977539)).abs() < 1e-9);
+    assert!((lat - f64::midpoint(48.661943, 48.603858)).abs() > 1e-6);
+    Ok(())
+}
+
+#[test]
+fn projected_transform_matches_header_polynomials() -> anyhow::Result<()> {
+    let original = chesapeake_header()?;
+    let mut header = chesapeake_header()?;
+    (header.wpx, header.wpy, header.pwx, header.pwy) = (None, None, None, None);
+    let (original, projected) = (original.geo_transform()?, header.geo_transform()?);
+    assert!(projected.projection().is_some());
+    for pixel in [(0.0, 0.0), (5000.0, 5000.0), (11547.0, 9767.0)] {
+        let coords = original.pixel_to_coords(pixel);
+        let (x, y) = projected.coords_to_pixel(coords);
+        assert!(
+            (x - pixel.0).abs() < 2.0 && (y - pixel.1).abs() < 2.0,
+            "{pixel:?} -> {:?}",
+            (x, y)
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn additional_parameters_survive_serialization() -> anyhow::Result<()> {
+    let mut header = read_header(SAINT_MALO_HEADER)?;
+    header.additional_parameters = Some(
+        AdditionalParameters::builder()
+            .p1("UNKNOWN".to_owned())
+            .p2(0.0)
+            .p3("0.9996".to_owned())
+            .p5("-3.0".to_owned())
+            .p6("0.0".to_owned())
+            .p7("500000.0".to_owned())
+            .p8("0.0".to_owned())
+            .pc("TC".to_owned())
+            .build(),
+    );
+    let parsed: ImageHeader = header.into_header_format().parse()?;
+    let (knq, parsed) = (
+        header.additional_parameters.unwrap(),
+        parsed.additional_parameters.unwrap(),
+    );
+    // empty fields are read back as empty strings, so only compare those that were set
+    assert_eq!(
+        (parsed.p3, parsed.p5, parsed.p6, parsed.p7, parsed.p8, parsed.pc),
+        (knq.p3, knq.p5, knq.p6, knq.p7, knq.p8, knq.pc)
+    );
+    Ok(())
+}
+
+#[test]
+fn projection_written_into_header_is_read_back() -> anyhow::Result<()> {
+    let mut header = chesapeake_header()?;
+    for method in [
+        ProjectionMethod::Mercator {
+            central_meridian: 0.0,
+            latitude_of_true_scale: 45.5,
+        },
+        ProjectionMethod::TransverseMercator {
+            central_meridian: -75.0,
+            latitude_of_origin: 0.0,
+            scale_factor: 0.9996,
+        },
+        ProjectionMethod::LambertConformalConic {
+            central_meridian: -96.0,
+            latitude_of_origin: 23.0,
+            standard_parallels: (29.5, 45.5),
+        },
+        ProjectionMethod::Polyconic {
+            central_meridian: -76.5,
+            latitude_of_origin: 37.0,
+        },
+    ] {
+        let projection = Projection {
+            method,
+            ellipsoid: Ellipsoid::GRS80,
+            false_easting: 500_000.0,
+            false_northing: 0.0,
+        };
+        header.set_projection(&projection);
+        assert_eq!(header.projection()?, projection);
+    }
+    Ok(())
+}